@@ -0,0 +1,9 @@
+#![feature(vec_deque_truncate_front)]
+#![feature(asm_experimental_arch)]
+#![feature(array_ptr_get)]
+#![allow(unsafe_op_in_unsafe_fn)]
+
+pub mod library;
+
+pub use library::handler::{Handler, Request, Response};
+pub use library::server::{run, Server};
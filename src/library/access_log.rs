@@ -0,0 +1,399 @@
+use std::{
+    cell::UnsafeCell,
+    fs::OpenOptions,
+    io::{self, BufWriter, Write},
+    net::IpAddr,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    },
+    thread,
+    time::Duration,
+};
+use tracing::error;
+
+// Who asked for what, written down by someone who isn't in a hurry.
+//
+// Every worker gets a ring of fixed-size entries. The worker is the only one writing to it,
+// the log thread the only one reading — so two indices and no locks. A full ring drops the
+// entry and counts it: the io_uring loop never waits for a disk. Strings are cut to fit
+// their slot, nothing allocates on the worker side.
+
+// Entries per worker ring. A power of two, ~0.6 KB each.
+const RING_SLOTS: usize = 4096;
+// How often the log thread looks for news when there was none last time.
+const IDLE_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Line format of the access log.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AccessLogFormat {
+    /// NCSA common log format, plus the latency in seconds at the end.
+    #[default]
+    Common,
+    /// Common, plus referer and user agent. Latency still at the end.
+    Combined,
+    /// One JSON object per line.
+    Json,
+}
+
+impl AccessLogFormat {
+    /// `common`, `combined` or `json`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "common" => Some(Self::Common),
+            "combined" => Some(Self::Combined),
+            "json" => Some(Self::Json),
+            _ => None,
+        }
+    }
+}
+
+/// Bytes cut to at most `N`. Lives in the ring, so no heap.
+#[derive(Clone, Copy)]
+pub(crate) struct Field<const N: usize> {
+    len: u16,
+    buf: [u8; N],
+}
+
+impl<const N: usize> Field<N> {
+    const EMPTY: Self = Self {
+        len: 0,
+        buf: [0u8; N],
+    };
+
+    #[inline(always)]
+    pub(crate) fn set(&mut self, value: &[u8]) {
+        let len: usize = value.len().min(N);
+        self.buf[..len].copy_from_slice(&value[..len]);
+        self.len = len as u16;
+    }
+
+    #[inline(always)]
+    fn get(&self) -> &[u8] {
+        &self.buf[..self.len as usize]
+    }
+}
+
+/// One served request.
+#[derive(Clone, Copy)]
+pub(crate) struct Entry {
+    /// Unix seconds.
+    pub(crate) time: i64,
+    /// `None` for Unix socket clients.
+    pub(crate) peer: Option<IpAddr>,
+    pub(crate) method: Field<16>,
+    pub(crate) path: Field<256>,
+    pub(crate) version: Field<16>,
+    pub(crate) status: u16,
+    /// Response bytes, head included.
+    pub(crate) bytes: u64,
+    /// From the request's first bytes to its response being ready, ns.
+    pub(crate) latency: u64,
+    pub(crate) referer: Field<128>,
+    pub(crate) user_agent: Field<128>,
+}
+
+impl Entry {
+    const EMPTY: Self = Self {
+        time: 0,
+        peer: None,
+        method: Field::EMPTY,
+        path: Field::EMPTY,
+        version: Field::EMPTY,
+        status: 0,
+        bytes: 0,
+        latency: 0,
+        referer: Field::EMPTY,
+        user_agent: Field::EMPTY,
+    };
+}
+
+// Head and tail on cache lines of their own. The two threads have enough to argue about.
+#[repr(align(64))]
+struct Index(AtomicUsize);
+
+struct Ring {
+    slots: Box<[UnsafeCell<Entry>]>,
+    // Next entry to read. Moved by the log thread only.
+    head: Index,
+    // Next entry to write. Moved by the worker only.
+    tail: Index,
+    dropped: AtomicU64,
+}
+
+// One writer, one reader, and a slot is only ever touched by the side that owns it: the log
+// thread reads from `head` up to `tail`, the worker writes from `tail` to a lap ahead of `head`.
+unsafe impl Sync for Ring {}
+
+impl Ring {
+    fn new() -> Self {
+        Self {
+            slots: (0..RING_SLOTS)
+                .map(|_| UnsafeCell::new(Entry::EMPTY))
+                .collect(),
+            head: Index(AtomicUsize::new(0)),
+            tail: Index(AtomicUsize::new(0)),
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    #[inline(always)]
+    fn push(&self, fill: impl FnOnce(&mut Entry)) {
+        let tail: usize = self.tail.0.load(Ordering::Relaxed);
+        let head: usize = self.head.0.load(Ordering::Acquire);
+        if tail.wrapping_sub(head) == RING_SLOTS {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        // Between head and tail + 1 nobody reads. This slot is ours until the tail moves.
+        fill(unsafe { &mut *self.slots[tail % RING_SLOTS].get() });
+        self.tail.0.store(tail.wrapping_add(1), Ordering::Release);
+    }
+
+    /// Hand every entry waiting to `write`. Returns how many there were.
+    fn drain(&self, mut write: impl FnMut(&Entry)) -> usize {
+        let head: usize = self.head.0.load(Ordering::Relaxed);
+        let tail: usize = self.tail.0.load(Ordering::Acquire);
+        let count: usize = tail.wrapping_sub(head);
+        for i in 0..count {
+            write(unsafe { &*self.slots[head.wrapping_add(i) % RING_SLOTS].get() });
+        }
+        self.head.0.store(tail, Ordering::Release);
+        count
+    }
+}
+
+struct Shared {
+    rings: Box<[Ring]>,
+    stop: AtomicBool,
+}
+
+/// Handle on the rings. Cloned into every worker; empty unless `run` sets it up.
+#[derive(Clone, Default)]
+pub struct AccessLog {
+    shared: Option<Arc<Shared>>,
+}
+
+impl AccessLog {
+    /// One ring per worker.
+    pub fn new(workers: usize) -> Self {
+        Self {
+            shared: Some(Arc::new(Shared {
+                rings: (0..workers).map(|_| Ring::new()).collect(),
+                stop: AtomicBool::new(false),
+            })),
+        }
+    }
+
+    #[inline(always)]
+    pub fn is_active(&self) -> bool {
+        self.shared.is_some()
+    }
+
+    /// Write an entry into `worker`'s ring. Ring full? Then it's gone — and counted.
+    #[inline(always)]
+    pub(crate) fn record(&self, worker: usize, fill: impl FnOnce(&mut Entry)) {
+        if let Some(ring) = self
+            .shared
+            .as_deref()
+            .and_then(|shared| shared.rings.get(worker))
+        {
+            ring.push(fill);
+        }
+    }
+
+    /// Start the log thread, writing to `target` — a file to append to, or `-` for stdout.
+    /// Runs until `stop`, and empties the rings one last time on the way out.
+    pub fn start(
+        &self,
+        target: &str,
+        format: AccessLogFormat,
+    ) -> io::Result<thread::JoinHandle<()>> {
+        let shared: Arc<Shared> = match self.shared.as_ref() {
+            Some(shared) => shared.clone(),
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "access log not set up",
+                ));
+            }
+        };
+        let out: Box<dyn Write + Send> = if target == "-" {
+            Box::new(io::stdout())
+        } else {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(target)
+                .map_err(|err| {
+                    io::Error::new(err.kind(), format!("can't open access log {target}: {err}"))
+                })?;
+            Box::new(file)
+        };
+        thread::Builder::new()
+            .name("Tachyon-log".into())
+            .spawn(move || write_loop(&shared, BufWriter::new(out), format))
+    }
+
+    /// Tell the log thread to finish up. Call once the workers are gone, or their last
+    /// words get lost.
+    pub fn stop(&self) {
+        if let Some(shared) = self.shared.as_deref() {
+            shared.stop.store(true, Ordering::Release);
+        }
+    }
+}
+
+fn write_loop(shared: &Shared, mut out: BufWriter<Box<dyn Write + Send>>, format: AccessLogFormat) {
+    let mut line: Vec<u8> = Vec::with_capacity(1024);
+    loop {
+        // Read the flag first: whatever was pushed before it went up gets drained below.
+        let stopping: bool = shared.stop.load(Ordering::Acquire);
+        let mut written: usize = 0;
+        let mut failed: Option<io::Error> = None;
+        for ring in shared.rings.iter() {
+            written += ring.drain(|entry| {
+                line.clear();
+                format_entry(&mut line, entry, format);
+                if let Err(err) = out.write_all(&line) {
+                    failed = Some(err);
+                }
+            });
+            let dropped: u64 = ring.dropped.swap(0, Ordering::Relaxed);
+            if dropped > 0 {
+                error!("Access log can't keep up. Dropped {} entries", dropped);
+            }
+        }
+        if written == 0 {
+            if let Err(err) = out.flush() {
+                failed = Some(err);
+            }
+            if stopping {
+                return;
+            }
+            thread::sleep(IDLE_INTERVAL);
+        }
+        if let Some(err) = failed {
+            error!("Access log write failed: {}", err);
+        }
+    }
+}
+
+fn format_entry(line: &mut Vec<u8>, entry: &Entry, format: AccessLogFormat) {
+    let (date, clock): ((i64, u32, u32), u32) = civil(entry.time);
+    let (year, month, day) = date;
+    let peer: String = entry
+        .peer
+        .map_or_else(|| "-".to_owned(), |peer| peer.to_string());
+    let latency_us: u64 = entry.latency / 1_000;
+    match format {
+        AccessLogFormat::Common | AccessLogFormat::Combined => {
+            let _ = write!(
+                line,
+                "{} - - [{:02}/{}/{}:{:02}:{:02}:{:02} +0000] \"",
+                peer,
+                day,
+                MONTHS[month as usize - 1],
+                year,
+                clock / 3600,
+                clock / 60 % 60,
+                clock % 60
+            );
+            quoted(line, entry.method.get());
+            line.push(b' ');
+            quoted(line, entry.path.get());
+            line.push(b' ');
+            quoted(line, entry.version.get());
+            let _ = write!(line, "\" {} {}", entry.status, entry.bytes);
+            if format == AccessLogFormat::Combined {
+                for field in [entry.referer.get(), entry.user_agent.get()] {
+                    line.extend_from_slice(b" \"");
+                    quoted(line, if field.is_empty() { b"-" } else { field });
+                    line.push(b'"');
+                }
+            }
+            let _ = writeln!(
+                line,
+                " {}.{:06}",
+                latency_us / 1_000_000,
+                latency_us % 1_000_000
+            );
+        }
+        AccessLogFormat::Json => {
+            let _ = write!(
+                line,
+                "{{\"time\":\"{}-{:02}-{:02}T{:02}:{:02}:{:02}Z\",\"remote_addr\":\"{}\",\"method\":\"",
+                year,
+                month,
+                day,
+                clock / 3600,
+                clock / 60 % 60,
+                clock % 60,
+                peer
+            );
+            json(line, entry.method.get());
+            line.extend_from_slice(b"\",\"path\":\"");
+            json(line, entry.path.get());
+            line.extend_from_slice(b"\",\"protocol\":\"");
+            json(line, entry.version.get());
+            let _ = write!(
+                line,
+                "\",\"status\":{},\"bytes\":{},\"latency_us\":{},\"referer\":\"",
+                entry.status, entry.bytes, latency_us
+            );
+            json(line, entry.referer.get());
+            line.extend_from_slice(b"\",\"user_agent\":\"");
+            json(line, entry.user_agent.get());
+            line.extend_from_slice(b"\"}\n");
+        }
+    }
+}
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+// Unix seconds to ((year, month, day), seconds into the day). Howard Hinnant's civil_from_days.
+fn civil(time: i64) -> ((i64, u32, u32), u32) {
+    let days: i64 = time.div_euclid(86_400);
+    let clock: u32 = time.rem_euclid(86_400) as u32;
+    let z: i64 = days + 719_468;
+    let era: i64 = z.div_euclid(146_097);
+    let doe: i64 = z - era * 146_097;
+    let yoe: i64 = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy: i64 = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp: i64 = (5 * doy + 2) / 153;
+    let day: u32 = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month: u32 = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year: i64 = yoe + era * 400 + (month <= 2) as i64;
+    ((year, month, day), clock)
+}
+
+// Inside a quoted log field: quotes, backslashes and anything unprintable become \xHH.
+// Nobody gets to forge a log line by putting one in their path.
+fn quoted(line: &mut Vec<u8>, value: &[u8]) {
+    for &byte in value {
+        if byte == b'"' || byte == b'\\' || !(0x20..0x7f).contains(&byte) {
+            let _ = write!(line, "\\x{:02X}", byte);
+        } else {
+            line.push(byte);
+        }
+    }
+}
+
+// Inside a JSON string. Bytes that aren't UTF-8 come out as U+FFFD.
+fn json(line: &mut Vec<u8>, value: &[u8]) {
+    for c in String::from_utf8_lossy(value).chars() {
+        match c {
+            '"' => line.extend_from_slice(b"\\\""),
+            '\\' => line.extend_from_slice(b"\\\\"),
+            c if (c as u32) < 0x20 || c == '\u{7f}' => {
+                let _ = write!(line, "\\u{:04x}", c as u32);
+            }
+            c => {
+                let mut utf8: [u8; 4] = [0u8; 4];
+                line.extend_from_slice(c.encode_utf8(&mut utf8).as_bytes());
+            }
+        }
+    }
+}
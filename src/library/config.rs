@@ -0,0 +1,398 @@
+use crate::library::{access_log::AccessLogFormat, rate_limit::RateLimit, server::Server};
+use std::{env, fs, io, time::Duration};
+use toml::{Table, Value};
+
+// Every knob `Server` has, from three places, in rising order of importance:
+//
+//   tachyon.toml          workers = 4
+//   environment           TACHYON_WORKERS=4
+//   command line          --workers 4
+//
+// Same names everywhere, give or take the case and the dashes. Later wins. The file is
+// picked with `--config` or `TACHYON_CONFIG`; without one we just skip it.
+
+const ENV_PREFIX: &str = "TACHYON_";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Str,
+    // Repeat the flag, use a TOML array, or comma-separate the env var.
+    List,
+    Int,
+    Bool,
+}
+
+// Name, kind, what it does. Also the `--help` text, so keep it short.
+const SETTINGS: &[(&str, Kind, &str)] = &[
+    ("listen", Kind::List, "TCP address to listen on, repeatable (default 0.0.0.0:8080)"),
+    ("listen_unix", Kind::List, "Unix socket path to listen on, repeatable"),
+    ("unix_mode", Kind::Int, "permission bits for the Unix sockets, e.g. 0o660"),
+    ("unix_owner", Kind::Str, "owner of the Unix sockets, numeric uid:gid"),
+    ("ipv6_only", Kind::Bool, "IPV6_V6ONLY for IPv6 listeners (default: system setting)"),
+    ("workers", Kind::Int, "worker threads, each pinned to a core (default: all cores)"),
+    ("uring_size", Kind::Int, "io_uring entries per worker, a power of two"),
+    ("sqpoll", Kind::Bool, "let a kernel thread poll the submission queue"),
+    ("sqpoll_idle", Kind::Int, "ms the SQPOLL thread spins before it naps"),
+    ("realtime", Kind::Bool, "run workers with realtime priority"),
+    ("ubdma", Kind::Bool, "read straight out of kernel buffers. UB. You've been warned"),
+    ("max_body_size", Kind::Int, "request body limit in bytes, 413 beyond it"),
+    ("max_header_size", Kind::Int, "request head limit in bytes, 431 beyond it"),
+    ("drain_timeout", Kind::Int, "seconds to finish in-flight requests on shutdown"),
+    ("header_timeout", Kind::Int, "seconds to send a request head, 408 after (0 = off)"),
+    ("body_timeout", Kind::Int, "seconds a request body may stall, 408 after (0 = off)"),
+    ("send_timeout", Kind::Int, "seconds a response may stall unread, closed after (0 = off)"),
+    ("handoff_path", Kind::Str, "Unix socket for zero-downtime restarts"),
+    ("accept_multiplicator", Kind::Int, "multishot accepts per listener per worker"),
+    ("listen_backlog", Kind::Int, "listen() backlog"),
+    ("sndbuf_size", Kind::Int, "SO_SNDBUF for client sockets, bytes"),
+    ("busy_poll", Kind::Int, "SO_BUSY_POLL for TCP clients, µs (0 = off)"),
+    ("buffer_size", Kind::Int, "recv buffer size in bytes, a multiple of 64"),
+    ("buffers_count", Kind::Int, "recv buffers per worker"),
+    ("huge_pages", Kind::Bool, "put the recv buffers on huge pages"),
+    ("max_connections", Kind::Int, "open connections, all workers together (0 = no limit)"),
+    ("max_worker_connections", Kind::Int, "open connections per worker (0 = no limit)"),
+    ("max_connections_per_ip", Kind::Int, "open connections per client address (0 = no limit)"),
+    ("reject_with_503", Kind::Bool, "answer 503 before closing connections over a limit"),
+    ("rate_limit", Kind::Int, "requests per second per connection, 429 beyond (0 = off)"),
+    ("rate_burst", Kind::Int, "requests a connection may fire at once (0 = rate_limit)"),
+    ("ip_rate_limit", Kind::Int, "requests per second per client address and worker (0 = off)"),
+    ("ip_rate_burst", Kind::Int, "requests an address may fire at once (0 = ip_rate_limit)"),
+    ("metrics_path", Kind::Str, "serve Prometheus metrics at this path, e.g. /metrics"),
+    ("metrics_addr", Kind::Str, "serve the metrics on this admin address instead"),
+    ("access_log", Kind::Str, "log every request to this file, - for stdout"),
+    ("access_log_format", Kind::Str, "common, combined or json (default common)"),
+];
+
+#[derive(Clone, Debug)]
+enum Setting {
+    Str(String),
+    List(Vec<String>),
+    Int(i64),
+    Bool(bool),
+}
+
+/// Settings gathered from the config file, the environment and the command line.
+/// Only what was actually given is kept — everything else stays at the `Server` defaults.
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    // Name, value, and where it came from — for error messages that point somewhere.
+    settings: Vec<(&'static str, Setting, String)>,
+}
+
+impl Config {
+    /// Everything from `args` (without the program name), the environment and the config file.
+    /// Typos and type mismatches are errors, not silently ignored.
+    pub fn load<I: IntoIterator<Item = String>>(args: I) -> io::Result<Config> {
+        let mut config_path: Option<String> = env::var(format!("{ENV_PREFIX}CONFIG")).ok();
+        let mut cli: Vec<(&'static str, Setting, String)> = Vec::new();
+        let mut args = args.into_iter().peekable();
+        while let Some(arg) = args.next() {
+            let flag: &str = arg.strip_prefix("--").ok_or_else(|| {
+                invalid(format!("unexpected argument {arg:?}. Try --help"))
+            })?;
+            let (name, inline): (&str, Option<&str>) = match flag.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (flag, None),
+            };
+            let source: String = format!("--{name}");
+            if name == "config" {
+                let path: Option<String> = inline.map(str::to_owned).or_else(|| args.next());
+                config_path = Some(path.ok_or_else(|| invalid("--config needs a path".into()))?);
+                continue;
+            }
+            let (key, kind) = lookup(&name.replace('-', "_"))
+                .ok_or_else(|| invalid(format!("unknown option {source}. Try --help")))?;
+            let raw: String = match (inline, kind) {
+                (Some(value), _) => value.to_owned(),
+                // A bare boolean flag means "yes". `--sqpoll false` works too.
+                (None, Kind::Bool) => match args.peek().map(String::as_str) {
+                    Some("true" | "false") => args.next().unwrap(),
+                    _ => "true".to_owned(),
+                },
+                (None, _) => args
+                    .next()
+                    .ok_or_else(|| invalid(format!("{source} needs a value")))?,
+            };
+            let setting: Setting = from_str(kind, &raw, &source)?;
+            // Repeated list flags add up, everything else is last one wins.
+            match (cli.iter_mut().find(|(name, ..)| *name == key), setting) {
+                (Some((_, Setting::List(list), _)), Setting::List(more)) => list.extend(more),
+                (_, setting) => cli.push((key, setting, source)),
+            }
+        }
+        let mut config: Config = Config::default();
+        if let Some(path) = config_path {
+            config.read_file(&path)?;
+        }
+        for (key, kind, _) in SETTINGS.iter() {
+            let var: String = format!("{ENV_PREFIX}{}", key.to_uppercase());
+            if let Ok(raw) = env::var(&var) {
+                let setting: Setting = from_str(*kind, &raw, &var)?;
+                config.set(key, setting, var);
+            }
+        }
+        for (key, setting, source) in cli {
+            config.set(key, setting, source);
+        }
+        Ok(config)
+    }
+
+    fn read_file(&mut self, path: &str) -> io::Result<()> {
+        let text: String = fs::read_to_string(path)
+            .map_err(|err| io::Error::new(err.kind(), format!("can't read config {path}: {err}")))?;
+        let table: Table = text
+            .parse()
+            .map_err(|err| invalid(format!("{path} is not valid TOML: {err}")))?;
+        for (name, value) in table {
+            let source: String = format!("{path}: {name}");
+            let (key, kind) = lookup(&name)
+                .ok_or_else(|| invalid(format!("{path}: unknown setting {name:?}")))?;
+            let setting: Setting = match (kind, value) {
+                (Kind::Str, Value::String(value)) => Setting::Str(value),
+                (Kind::List, Value::String(value)) => Setting::List(vec![value]),
+                (Kind::List, Value::Array(values)) => Setting::List(
+                    values
+                        .into_iter()
+                        .map(|value| match value {
+                            Value::String(value) => Ok(value),
+                            other => Err(invalid(format!("{source}: expected strings, got {}", other.type_str()))),
+                        })
+                        .collect::<io::Result<_>>()?,
+                ),
+                (Kind::Int, Value::Integer(value)) => Setting::Int(value),
+                (Kind::Bool, Value::Boolean(value)) => Setting::Bool(value),
+                (kind, other) => {
+                    return Err(invalid(format!(
+                        "{source}: expected {}, got {}",
+                        kind_name(kind),
+                        other.type_str()
+                    )));
+                }
+            };
+            self.set(key, setting, source);
+        }
+        Ok(())
+    }
+
+    fn set(&mut self, key: &'static str, setting: Setting, source: String) {
+        self.settings.retain(|(name, ..)| *name != key);
+        self.settings.push((key, setting, source));
+    }
+
+    /// Feed every setting into `server`, then let `Server::validate` have a look at the result.
+    pub fn apply(&self, server: &mut Server) -> io::Result<()> {
+        for (key, setting, source) in self.settings.iter() {
+            match (*key, setting) {
+                ("listen", Setting::List(addrs)) => {
+                    server.set_addrs(addrs.iter().map(|addr| leak(addr)).collect());
+                }
+                ("listen_unix", Setting::List(paths)) => {
+                    for path in paths {
+                        server.listen_unix(leak(path));
+                    }
+                }
+                ("unix_mode", Setting::Int(mode)) => {
+                    server.set_unix_mode(number(*mode, source)?);
+                }
+                ("unix_owner", Setting::Str(owner)) => {
+                    let (uid, gid) = owner
+                        .split_once(':')
+                        .and_then(|(uid, gid)| Some((uid.parse().ok()?, gid.parse().ok()?)))
+                        .ok_or_else(|| invalid(format!("{source} = {owner:?}: expected uid:gid")))?;
+                    server.set_unix_owner(uid, gid);
+                }
+                ("ipv6_only", Setting::Bool(only)) => {
+                    server.set_ipv6_only(*only);
+                }
+                ("workers", Setting::Int(workers)) => {
+                    server.set_workers(number(*workers, source)?);
+                }
+                ("uring_size", Setting::Int(size)) => {
+                    server.set_uring_size(number(*size, source)?);
+                }
+                ("sqpoll", Setting::Bool(enabled)) => {
+                    server.set_sqpoll_enabled(*enabled);
+                }
+                ("sqpoll_idle", Setting::Int(idle)) => {
+                    server.set_sqpoll_idle(number(*idle, source)?);
+                }
+                ("realtime", Setting::Bool(enabled)) => {
+                    server.set_realtime(*enabled);
+                }
+                ("ubdma", Setting::Bool(enabled)) => {
+                    server.set_ub_kernel_dma(*enabled);
+                }
+                ("max_body_size", Setting::Int(size)) => {
+                    server.set_max_body_size(number(*size, source)?);
+                }
+                ("max_header_size", Setting::Int(size)) => {
+                    server.set_max_header_size(number(*size, source)?);
+                }
+                ("drain_timeout", Setting::Int(secs)) => {
+                    server.set_drain_timeout(Duration::from_secs(number(*secs, source)?));
+                }
+                ("header_timeout", Setting::Int(secs)) => {
+                    server.set_header_timeout(Duration::from_secs(number(*secs, source)?));
+                }
+                ("body_timeout", Setting::Int(secs)) => {
+                    server.set_body_timeout(Duration::from_secs(number(*secs, source)?));
+                }
+                ("send_timeout", Setting::Int(secs)) => {
+                    server.set_send_timeout(Duration::from_secs(number(*secs, source)?));
+                }
+                ("handoff_path", Setting::Str(path)) => {
+                    server.set_handoff_path(leak(path));
+                }
+                ("accept_multiplicator", Setting::Int(count)) => {
+                    server.set_accept_multiplicator(number(*count, source)?);
+                }
+                ("listen_backlog", Setting::Int(backlog)) => {
+                    server.set_listen_backlog(number(*backlog, source)?);
+                }
+                ("sndbuf_size", Setting::Int(size)) => {
+                    server.set_sndbuf_size(number(*size, source)?);
+                }
+                ("busy_poll", Setting::Int(usecs)) => {
+                    server.set_busy_poll(number(*usecs, source)?);
+                }
+                ("buffer_size", Setting::Int(size)) => {
+                    server.set_buffer_size(number(*size, source)?);
+                }
+                ("buffers_count", Setting::Int(count)) => {
+                    server.set_buffers_count(number(*count, source)?);
+                }
+                ("huge_pages", Setting::Bool(enabled)) => {
+                    server.set_huge_pages(*enabled);
+                }
+                ("max_connections", Setting::Int(count)) => {
+                    server.set_max_connections(number(*count, source)?);
+                }
+                ("max_worker_connections", Setting::Int(count)) => {
+                    server.set_max_worker_connections(number(*count, source)?);
+                }
+                ("max_connections_per_ip", Setting::Int(count)) => {
+                    server.set_max_connections_per_ip(number(*count, source)?);
+                }
+                ("reject_with_503", Setting::Bool(enabled)) => {
+                    server.set_reject_with_503(*enabled);
+                }
+                ("rate_limit", Setting::Int(rate)) => {
+                    let limit: RateLimit = server.get_rate_limit();
+                    server.set_rate_limit(RateLimit::new(number(*rate, source)?, limit.burst));
+                }
+                ("rate_burst", Setting::Int(burst)) => {
+                    let limit: RateLimit = server.get_rate_limit();
+                    server.set_rate_limit(RateLimit::new(limit.rate, number(*burst, source)?));
+                }
+                ("ip_rate_limit", Setting::Int(rate)) => {
+                    let limit: RateLimit = server.get_ip_rate_limit();
+                    server.set_ip_rate_limit(RateLimit::new(number(*rate, source)?, limit.burst));
+                }
+                ("ip_rate_burst", Setting::Int(burst)) => {
+                    let limit: RateLimit = server.get_ip_rate_limit();
+                    server.set_ip_rate_limit(RateLimit::new(limit.rate, number(*burst, source)?));
+                }
+                ("metrics_path", Setting::Str(path)) => {
+                    server.set_metrics_path(leak(path));
+                }
+                ("metrics_addr", Setting::Str(addr)) => {
+                    server.set_metrics_addr(leak(addr));
+                }
+                ("access_log", Setting::Str(path)) => {
+                    server.set_access_log(leak(path));
+                }
+                ("access_log_format", Setting::Str(name)) => {
+                    let format: AccessLogFormat = AccessLogFormat::from_name(name).ok_or_else(|| {
+                        invalid(format!("{source} = {name:?}: expected common, combined or json"))
+                    })?;
+                    server.set_access_log_format(format);
+                }
+                // `load` checked every name against SETTINGS and every value against its kind.
+                _ => unreachable!("setting {key} slipped past the table"),
+            }
+        }
+        server.validate()
+    }
+
+    /// What `--help` prints.
+    pub fn usage() -> String {
+        let mut usage: String = String::from(
+            "Usage: tachyon [--config FILE] [--option VALUE]...\n\n\
+             Every option can also go into the TOML file (same name, underscores)\n\
+             or into the environment (TACHYON_ + name in upper case).\n\
+             Command line beats environment beats file.\n\n",
+        );
+        usage.push_str(&format!("  {:<26}{}\n", "--config FILE", "TOML config file (or TACHYON_CONFIG)"));
+        for (key, kind, help) in SETTINGS.iter() {
+            let flag: String = match kind {
+                Kind::Bool => format!("--{}", key.replace('_', "-")),
+                _ => format!("--{} {}", key.replace('_', "-"), kind_name(*kind).to_uppercase()),
+            };
+            usage.push_str(&format!("  {flag:<26}{help}\n"));
+        }
+        usage
+    }
+}
+
+fn lookup(name: &str) -> Option<(&'static str, Kind)> {
+    SETTINGS
+        .iter()
+        .find(|(key, ..)| *key == name)
+        .map(|(key, kind, _)| (*key, *kind))
+}
+
+fn kind_name(kind: Kind) -> &'static str {
+    match kind {
+        Kind::Str => "string",
+        Kind::List => "list",
+        Kind::Int => "integer",
+        Kind::Bool => "bool",
+    }
+}
+
+// Environment and command line only know strings.
+fn from_str(kind: Kind, raw: &str, source: &str) -> io::Result<Setting> {
+    let raw: &str = raw.trim();
+    match kind {
+        Kind::Str => Ok(Setting::Str(raw.to_owned())),
+        Kind::List => Ok(Setting::List(
+            raw.split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(str::to_owned)
+                .collect(),
+        )),
+        Kind::Int => {
+            let digits: String = raw.replace('_', "");
+            let parsed: Result<i64, _> = if let Some(octal) = digits.strip_prefix("0o") {
+                i64::from_str_radix(octal, 8)
+            } else if let Some(hex) = digits.strip_prefix("0x") {
+                i64::from_str_radix(hex, 16)
+            } else {
+                digits.parse()
+            };
+            parsed
+                .map(Setting::Int)
+                .map_err(|_| invalid(format!("{source} = {raw:?}: expected an integer")))
+        }
+        Kind::Bool => match raw {
+            "true" | "1" | "yes" | "on" => Ok(Setting::Bool(true)),
+            "false" | "0" | "no" | "off" => Ok(Setting::Bool(false)),
+            _ => Err(invalid(format!("{source} = {raw:?}: expected true or false"))),
+        },
+    }
+}
+
+fn number<T: TryFrom<i64>>(value: i64, source: &str) -> io::Result<T> {
+    T::try_from(value).map_err(|_| invalid(format!("{source} = {value} is out of range")))
+}
+
+// `Server` wants `&'static str` for addresses and paths. Config is read once per process,
+// so a few leaked bytes are the cheapest way there.
+fn leak(value: &str) -> &'static str {
+    Box::leak(value.to_owned().into_boxed_str())
+}
+
+fn invalid(reason: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, reason)
+}
@@ -0,0 +1,339 @@
+use crate::library::{handler::PendingStream, utils::chunked::ChunkedDecoder};
+use std::{io, net::IpAddr};
+
+// Payload bytes pulled from a `BodyStream` per send.
+pub(crate) const STREAM_CHUNK_SIZE: usize = 16 * 1024;
+// Room in front of the payload for the chunk size line ("ffffffff\r\n" fits easily).
+const CHUNK_PREFIX: usize = 16;
+
+/// Chunked request whose body is still trickling in.
+#[derive(Clone)]
+pub(crate) struct PendingChunked {
+    /// Copy of the request head — the kernel buffer it came from is long gone.
+    pub(crate) head: Vec<u8>,
+    pub(crate) decoder: ChunkedDecoder,
+}
+
+/// Outbound bytes of one connection that the kernel hasn't accepted yet.
+///
+/// Responses are staged in the client's `SmallLake` and moved in here by `wideband_send`.
+/// One send is in flight at a time and its bytes stay right where they are until the
+/// completion tells us how many the kernel actually took. Whatever shows up meanwhile
+/// waits in the backlog.
+#[derive(Clone, Default)]
+pub(crate) struct SendQueue {
+    // Bytes handed to the kernel. Hands off while `in_flight`.
+    flight: Vec<u8>,
+    // How much of `flight` the kernel already took.
+    sent: usize,
+    in_flight: bool,
+    backlog: Vec<u8>,
+}
+
+impl SendQueue {
+    #[inline(always)]
+    pub(crate) fn push(&mut self, data: &[u8]) {
+        self.backlog.extend_from_slice(data);
+    }
+
+    #[inline(always)]
+    pub(crate) fn in_flight(&self) -> bool {
+        self.in_flight
+    }
+
+    /// Next bytes to submit. Marks them in flight — the slice must stay valid until
+    /// `complete` or `retry`, and it will: nobody else touches `flight` meanwhile.
+    pub(crate) fn begin(&mut self) -> Option<&[u8]> {
+        if self.in_flight {
+            return None;
+        }
+        if self.sent == self.flight.len() {
+            // Previous batch is out. Recycle its allocation for the backlog.
+            self.flight.clear();
+            self.sent = 0;
+            std::mem::swap(&mut self.flight, &mut self.backlog);
+        }
+        if self.flight.is_empty() {
+            return None;
+        }
+        self.in_flight = true;
+        Some(&self.flight[self.sent..])
+    }
+
+    /// The kernel took `sent` bytes. A short write leaves the rest for the next `begin`.
+    #[inline(always)]
+    pub(crate) fn complete(&mut self, sent: usize) {
+        self.sent = (self.sent + sent).min(self.flight.len());
+        self.in_flight = false;
+    }
+
+    /// Nothing went out. Same bytes, next round.
+    #[inline(always)]
+    pub(crate) fn retry(&mut self) {
+        self.in_flight = false;
+    }
+
+    /// The last batch made it out in full. No short write leftovers.
+    #[inline(always)]
+    pub(crate) fn flight_done(&self) -> bool {
+        self.sent == self.flight.len()
+    }
+}
+
+/// When the responses waiting on a connection were asked for. Feeds the latency histograms.
+///
+/// Responses travel in batches, so each batch is timed once and counted once per response,
+/// at the wait of its oldest member. Pessimistic, never flattering.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct SendTiming {
+    // Staged, not handed to the kernel yet. `staged_since` is when the oldest one's request
+    // arrived.
+    staged: u32,
+    staged_since: i64,
+    // Riding the send in flight.
+    flying: u32,
+    flying_since: i64,
+}
+
+impl SendTiming {
+    /// A response got staged for a request that arrived at `arrived`.
+    #[inline(always)]
+    pub(crate) fn stage(&mut self, arrived: i64) {
+        if self.staged == 0 {
+            self.staged_since = arrived;
+        }
+        self.staged += 1;
+    }
+
+    /// A send went to the kernel at `now`. If it's a fresh batch, everything staged is on it.
+    /// Returns the batch's wait and size. `None` for a retry or the rest of a short write.
+    #[inline(always)]
+    pub(crate) fn submit(&mut self, now: i64) -> Option<(u64, u64)> {
+        if self.flying != 0 || self.staged == 0 {
+            return None;
+        }
+        self.flying = std::mem::take(&mut self.staged);
+        self.flying_since = self.staged_since;
+        Some(((now - self.flying_since).max(0) as u64, self.flying as u64))
+    }
+
+    /// The batch in flight is out, as of `now`. Returns its wait and size.
+    #[inline(always)]
+    pub(crate) fn complete(&mut self, now: i64) -> Option<(u64, u64)> {
+        if self.flying == 0 {
+            return None;
+        }
+        let count: u32 = std::mem::take(&mut self.flying);
+        Some(((now - self.flying_since).max(0) as u64, count as u64))
+    }
+}
+
+/// Streamed response body on its way out.
+///
+/// Exactly one piece is in the kernel's hands at a time; the next one is pulled
+/// from the source only after the previous send completes. That's the backpressure:
+/// a full socket buffer means no completion, which means no more pulling.
+pub(crate) struct OutboundStream {
+    source: PendingStream,
+    // Staging area. Must outlive the send SQE pointing into it.
+    buf: Vec<u8>,
+    start: usize,
+    end: usize,
+    // Bytes of buf[start..end] already accepted by the kernel.
+    pub(crate) sent: usize,
+    pub(crate) in_flight: bool,
+    // Source drained, terminator (if any) staged. Once sent — we're done.
+    pub(crate) finished: bool,
+    // Body bytes still owed for a `Content-Length` stream.
+    remaining: usize,
+}
+
+impl OutboundStream {
+    pub(crate) fn new(source: PendingStream) -> Self {
+        let remaining: usize = source.length.unwrap_or(0);
+        let finished: bool = source.length == Some(0);
+        Self {
+            source,
+            buf: vec![0u8; CHUNK_PREFIX + STREAM_CHUNK_SIZE + 2],
+            start: 0,
+            end: 0,
+            sent: 0,
+            in_flight: false,
+            finished,
+            remaining,
+        }
+    }
+
+    /// Bytes staged but not yet accepted by the kernel.
+    #[inline(always)]
+    pub(crate) fn pending(&self) -> &[u8] {
+        &self.buf[self.start + self.sent..self.end]
+    }
+
+    /// Pull the next piece from the source into the staging buffer.
+    /// Fails if the source broke, or dried up before paying the full `Content-Length` —
+    /// the response is now broken and the connection with it. No terminator for a chunked
+    /// body that failed: the client has to see it was cut short.
+    pub(crate) fn fill(&mut self) -> io::Result<()> {
+        self.sent = 0;
+        let data: usize = CHUNK_PREFIX;
+        let capacity: usize = match self.source.length {
+            Some(_) => self.remaining.min(STREAM_CHUNK_SIZE),
+            None => STREAM_CHUNK_SIZE,
+        };
+        let len: usize = self
+            .source
+            .body
+            .next_chunk(&mut self.buf[data..data + capacity])?
+            .min(capacity);
+        match self.source.length {
+            Some(_) => {
+                self.start = data;
+                self.end = data + len;
+                self.remaining -= len;
+                if self.remaining == 0 {
+                    self.finished = true;
+                } else if len == 0 {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "body shorter than its Content-Length",
+                    ));
+                }
+            }
+            None if len == 0 => {
+                // Last chunk and an empty trailer block.
+                self.buf[..5].copy_from_slice(b"0\r\n\r\n");
+                self.start = 0;
+                self.end = 5;
+                self.finished = true;
+            }
+            None => {
+                let mut hex: [u8; CHUNK_PREFIX] = [0u8; CHUNK_PREFIX];
+                let mut pos: usize = hex.len() - 2;
+                hex[pos..].copy_from_slice(b"\r\n");
+                let mut value: usize = len;
+                loop {
+                    pos -= 1;
+                    hex[pos] = b"0123456789abcdef"[value & 0xF];
+                    value >>= 4;
+                    if value == 0 {
+                        break;
+                    }
+                }
+                self.buf[pos..data].copy_from_slice(&hex[pos..]);
+                self.buf[data + len..data + len + 2].copy_from_slice(b"\r\n");
+                self.start = pos;
+                self.end = data + len + 2;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Per-connection state that has to survive between two `recv` completions.
+#[derive(Default)]
+pub(crate) struct Connection {
+    /// Unfinished request copied out of the kernel buffer — that one goes back
+    /// to the pool right after parsing, so we can't just keep pointing at it.
+    pub(crate) carry: Vec<u8>,
+    /// Flush whatever is queued, then shut the socket down. Set after we replied
+    /// to something we can't keep talking to (bad framing, oversized body).
+    pub(crate) close_after_flush: bool,
+    /// Chunked body being decoded across recv completions.
+    pub(crate) chunked: Option<Box<PendingChunked>>,
+    /// Streamed response in progress. Everything pipelined behind it waits in `carry`.
+    pub(crate) stream: Option<Box<OutboundStream>>,
+    /// Responses the kernel still owes us a completion for.
+    pub(crate) send: SendQueue,
+    /// How long those responses have been waiting.
+    pub(crate) timing: SendTiming,
+    /// Shutdown already submitted. Saying goodbye twice is just awkward.
+    pub(crate) hung_up: bool,
+    /// Socket buffer was full. No more sends until the kernel says there's room.
+    pub(crate) awaiting_writable: bool,
+    /// We're done with it, but the kernel isn't done with us: a send still points into our
+    /// bytes. Freed by the completion that lets go of them.
+    pub(crate) closing: bool,
+    /// Last time bytes came in or a send completed (`nano_clock`). Idle and body timers run from here.
+    pub(crate) last_active: i64,
+    /// When the last send went to the kernel. The send timeout runs from here.
+    pub(crate) send_since: i64,
+    /// When the first bytes of the request still being read showed up. 0 if there's none.
+    pub(crate) request_since: i64,
+    /// That request has its head already. We're waiting on the body.
+    pub(crate) reading_body: bool,
+    /// Requests served on this connection so far.
+    pub(crate) requests: u32,
+    /// Source address, if anything needs it (per-IP caps or rate limits). TCP only.
+    pub(crate) peer: Option<IpAddr>,
+    /// `peer` took a seat in the per-IP connection count. Handed back on close.
+    pub(crate) peer_counted: bool,
+    /// Unique per worker, unlike the connection ID. Names its rate limit buckets.
+    pub(crate) serial: u64,
+}
+
+// `Server` is cloned into every worker before the first client ever shows up, so there's
+// never live connection state to copy. A stream can't be cloned anyway — it's left behind.
+impl Clone for Connection {
+    fn clone(&self) -> Self {
+        Self {
+            carry: self.carry.clone(),
+            close_after_flush: self.close_after_flush,
+            chunked: self.chunked.clone(),
+            stream: None,
+            send: self.send.clone(),
+            timing: self.timing,
+            hung_up: self.hung_up,
+            awaiting_writable: self.awaiting_writable,
+            closing: self.closing,
+            last_active: self.last_active,
+            send_since: self.send_since,
+            request_since: self.request_since,
+            reading_body: self.reading_body,
+            requests: self.requests,
+            peer: self.peer,
+            peer_counted: self.peer_counted,
+            serial: self.serial,
+        }
+    }
+}
+
+impl Connection {
+    /// Fresh connection, accepted at `now`. The idle clock starts ticking right away.
+    #[inline(always)]
+    pub(crate) fn build(now: i64) -> Self {
+        Self {
+            last_active: now,
+            ..Self::default()
+        }
+    }
+
+    /// What goes into this connection's user data. Ids get reused, serials don't — 16 bits of one
+    /// is plenty to tell two holders of the same id apart.
+    #[inline(always)]
+    pub(crate) fn generation(&self) -> u16 {
+        self.serial as u16
+    }
+
+    /// The client stopped taking our bytes. Nothing more goes out, pipelined requests included,
+    /// and no shutdown either — there's nobody left to say goodbye to.
+    #[inline(always)]
+    pub(crate) fn abandon(&mut self) {
+        self.send = Default::default();
+        self.timing = Default::default();
+        self.stream = None;
+        self.carry = Vec::new();
+        self.chunked = None;
+        self.close_after_flush = true;
+        self.hung_up = true;
+    }
+
+    /// Something the kernel will complete is still out there. Until it does, this connection
+    /// stays right where it is.
+    #[inline(always)]
+    pub(crate) fn sending(&self) -> bool {
+        self.send.in_flight()
+            || self.awaiting_writable
+            || self.stream.as_deref().is_some_and(|stream| stream.in_flight)
+    }
+}
@@ -0,0 +1,422 @@
+use crate::library::{
+    router::Params,
+    utils::{
+        compat::{LakeTools, SmallLake},
+        http::{Headers, RequestHead},
+    },
+};
+use bytes::{Buf, Bytes};
+use std::{
+    io::{self, Read},
+    time::Duration,
+};
+use tachyon_json::TachyonBuffer;
+use tracing::error;
+
+pub const STATUS_SUCCESS: &[u8] = b"HTTP/1.1 200 OK\r\n";
+pub const STATUS_BAD_REQUEST: &[u8] = b"HTTP/1.1 400 Bad Request\r\n";
+pub const STATUS_NOT_FOUND: &[u8] = b"HTTP/1.1 404 Not Found\r\n";
+pub const STATUS_METHOD_NOT_ALLOWED: &[u8] = b"HTTP/1.1 405 Method Not Allowed\r\n";
+pub const STATUS_PAYLOAD_TOO_LARGE: &[u8] = b"HTTP/1.1 413 Payload Too Large\r\n";
+pub const STATUS_TOO_MANY_REQUESTS: &[u8] = b"HTTP/1.1 429 Too Many Requests\r\n";
+pub const STATUS_HEADERS_TOO_LARGE: &[u8] = b"HTTP/1.1 431 Request Header Fields Too Large\r\n";
+pub const STATUS_REQUEST_TIMEOUT: &[u8] = b"HTTP/1.1 408 Request Timeout\r\n";
+pub const STATUS_INTERNAL_SERVER_ERROR: &[u8] = b"HTTP/1.1 500 Internal Server Error\r\n";
+pub const STATUS_NOT_IMPLEMENTED: &[u8] = b"HTTP/1.1 501 Not Implemented\r\n";
+pub const STATUS_SERVICE_UNAVAILABLE: &[u8] = b"HTTP/1.1 503 Service Unavailable\r\n";
+pub const CONTENT_TYPE_TEXT: &[u8] = b"Content-Type: text/plain; charset=utf-8\r\n";
+pub const CONTENT_TYPE_JSON: &[u8] = b"Content-Type: application/json; charset=utf-8\r\n";
+// What `BASE_HEADERS` promises every client. Change them together, or we're lying.
+pub(crate) const KEEPALIVE_TIMEOUT: Duration = Duration::from_secs(5);
+pub(crate) const KEEPALIVE_MAX_REQUESTS: u32 = 1000;
+// For connections turned away right at accept. No Date, no keep-alive — they're not staying.
+pub(crate) const RESPONSE_OVERLOADED: &[u8] = b"HTTP/1.1 503 Service Unavailable\r\n\
+Server: Tachyon\r\n\
+Connection: close\r\n\
+Content-Type: text/plain; charset=utf-8\r\n\
+Content-Length: 23\r\n\
+\r\n\
+503 Service Unavailable";
+pub const BASE_HEADERS: &[u8] = b"Server: Tachyon\r\n\
+Connection: keep-alive\r\n\
+Keep-Alive: timeout=5, max=1000\r\n\
+\r\n";
+// Same, for the last response before we hang up. No promises we won't keep.
+pub const CLOSE_HEADERS: &[u8] = b"Server: Tachyon\r\n\
+Connection: close\r\n\
+\r\n";
+
+/// Status code of the response starting with `status_line` (`HTTP/1.1 200 ...`). 0 if there's none.
+#[inline(always)]
+pub(crate) fn status_code(status_line: &[u8]) -> u16 {
+    match status_line.get(9..12) {
+        Some(code) if code.iter().all(u8::is_ascii_digit) => code
+            .iter()
+            .fold(0, |code, digit| code * 10 + (digit - b'0') as u16),
+        _ => 0,
+    }
+}
+
+/// Everything we managed to squeeze out of the kernel buffer for one request.
+/// All slices point straight into kernel-provided memory — nothing is copied,
+/// so don't even think about keeping them past `Handler::handle`.
+#[derive(Debug, Clone, Copy)]
+pub struct Request<'a> {
+    pub method: &'a [u8],
+    pub path: &'a [u8],
+    /// `HTTP/1.1`, most likely. Empty in UBDMA mode — it only cares about method and path.
+    pub version: &'a [u8],
+    pub headers: Headers<'a>,
+    /// Complete body — by `Content-Length`, or reassembled from chunks. Empty when there is none.
+    pub body: &'a [u8],
+    /// Chunked trailers. Empty unless the body was chunked and the client felt chatty.
+    pub trailers: Headers<'a>,
+    /// Filled in by the `Router`. Empty for hand-rolled handlers.
+    pub params: Params<'a>,
+}
+
+impl<'a> Request<'a> {
+    #[inline(always)]
+    pub fn new(method: &'a [u8], path: &'a [u8]) -> Self {
+        Self {
+            method,
+            path,
+            version: &[],
+            headers: Headers::default(),
+            body: &[],
+            trailers: Headers::default(),
+            params: Params::default(),
+        }
+    }
+
+    #[inline(always)]
+    pub fn from_head(head: &RequestHead<'a>, body: &'a [u8]) -> Self {
+        Self {
+            method: head.method,
+            path: head.path,
+            version: head.version,
+            headers: head.headers,
+            body,
+            trailers: Headers::default(),
+            params: Params::default(),
+        }
+    }
+
+    /// Case-insensitive header lookup.
+    #[inline(always)]
+    pub fn header(&self, name: &[u8]) -> Option<&'a [u8]> {
+        self.headers.get(name)
+    }
+}
+
+/// Source of a streamed response body.
+///
+/// Pulled from the worker loop one piece at a time, and only once the previous piece
+/// has been accepted by the kernel — a slow client slows the source down, not the server.
+/// Runs on the worker thread, so don't block in here for long.
+pub trait BodyStream {
+    /// Fill `buf` with the next piece of the body and return its length.
+    /// `Ok(0)` means the body is over. An error means it's broken — the connection goes
+    /// down with it rather than pretend the body ended fine.
+    fn next_chunk(&mut self, buf: &mut [u8]) -> io::Result<usize>;
+}
+
+impl BodyStream for Bytes {
+    #[inline(always)]
+    fn next_chunk(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len: usize = self.len().min(buf.len());
+        buf[..len].copy_from_slice(&self[..len]);
+        self.advance(len);
+        Ok(len)
+    }
+}
+
+/// Streams anything `Read`. An interrupted read is simply tried again; any other error
+/// breaks the body off, `WouldBlock` included — there's no waking up for a reader later.
+pub struct ReaderStream<R: Read>(pub R);
+
+impl<R: Read> BodyStream for ReaderStream<R> {
+    #[inline(always)]
+    fn next_chunk(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            match self.0.read(buf) {
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                result => return result,
+            }
+        }
+    }
+}
+
+/// A streamed body the handler left behind, waiting to be picked up by the server.
+pub(crate) struct PendingStream {
+    pub(crate) body: Box<dyn BodyStream>,
+    /// `Some` — plain `Content-Length` body. `None` — `Transfer-Encoding: chunked`.
+    pub(crate) length: Option<usize>,
+}
+
+// The hot lake. A response has to fit in here whole to skip the heap.
+const LAKE_SIZE: usize = 512;
+// Worst case for the framing header: `Content-Length: ` and 20 digits, or the chunked one.
+const FRAMING_RESERVE: usize = 16 + 20 + 2;
+
+/// Response writer handed to the handler.
+///
+/// Bytes are staged in the worker's hot `SmallLake` and then dumped straight
+/// into the hot internal cache. No heap, no mercy — unless the response doesn't fit.
+/// Then the body is copied out and streamed after the head, like `stream` would.
+pub struct Response<'a> {
+    date: &'a [u8; 35],
+    out: &'a mut [u8],
+    json_buf: &'a mut TachyonBuffer<100>,
+    data_lake: &'a mut SmallLake<LAKE_SIZE>,
+    len: usize,
+    stream: Option<PendingStream>,
+    // Which route answered, for the metrics. 0 = not the router.
+    route: u16,
+    // The connection closes right after this one. Say so.
+    close: bool,
+}
+
+impl<'a> Response<'a> {
+    #[inline(always)]
+    pub(crate) fn new(
+        date: &'a [u8; 35],
+        out: &'a mut [u8],
+        json_buf: &'a mut TachyonBuffer<100>,
+        data_lake: &'a mut SmallLake<LAKE_SIZE>,
+    ) -> Self {
+        // Clean old json entries
+        json_buf.reset_pos();
+        // Clean old requests
+        data_lake.reset_pos();
+        Self {
+            date,
+            out,
+            json_buf,
+            data_lake,
+            len: 0,
+            stream: None,
+            route: 0,
+            close: false,
+        }
+    }
+
+    /// Scratch JSON buffer. Encode into it, then call `write_json`.
+    #[inline(always)]
+    pub fn json_buf(&mut self) -> &mut TachyonBuffer<100> {
+        self.json_buf
+    }
+
+    /// Bytes written so far.
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Whole response in one go. Up to about 350 bytes of body it never leaves the hot path;
+    /// anything bigger takes a copy and goes out as a stream.
+    #[inline(always)]
+    pub fn write(&mut self, status_line: &[u8], content_type: &[u8], body: &[u8]) {
+        self.write_with_headers(status_line, content_type, &[], body);
+    }
+
+    /// Same as `write`. `headers` must be complete `Name: value\r\n` lines.
+    #[inline(always)]
+    pub fn write_with_headers(
+        &mut self,
+        status_line: &[u8],
+        content_type: &[u8],
+        headers: &[u8],
+        body: &[u8],
+    ) {
+        if self.head_len(status_line, content_type, headers) + body.len() > self.room() {
+            let body: Bytes = Bytes::copy_from_slice(body);
+            self.stream_with_headers(status_line, content_type, headers, Some(body.len()), body);
+            return;
+        }
+        unsafe {
+            let data_lake: &mut SmallLake<LAKE_SIZE> =
+                self.write_head(status_line, content_type, headers);
+            data_lake.write(b"Content-Length: ".as_ptr(), 16);
+            data_lake.write_num_str(body.len());
+            data_lake.write(b"\r\n".as_ptr(), 2);
+            let base: &[u8] = self.base_headers();
+            let data_lake: &mut SmallLake<LAKE_SIZE> = &mut *self.data_lake;
+            data_lake.write(base.as_ptr(), base.len());
+            data_lake.write(body.as_ptr(), body.len());
+            self.commit();
+        }
+    }
+
+    /// Send only the head now and stream the body afterwards, piece by piece.
+    /// With `length` the body goes out as plain bytes under `Content-Length` (and had better
+    /// be exactly that long), without it — as `Transfer-Encoding: chunked`.
+    ///
+    /// Pipelined requests behind this one wait until the stream is over.
+    #[inline(always)]
+    pub fn stream<S: BodyStream + 'static>(
+        &mut self,
+        status_line: &[u8],
+        content_type: &[u8],
+        length: Option<usize>,
+        body: S,
+    ) {
+        self.stream_with_headers(status_line, content_type, &[], length, body);
+    }
+
+    #[inline(always)]
+    fn stream_with_headers<S: BodyStream + 'static>(
+        &mut self,
+        status_line: &[u8],
+        content_type: &[u8],
+        headers: &[u8],
+        length: Option<usize>,
+        body: S,
+    ) {
+        // Even the head alone is too much. Nothing sane sends that many headers.
+        if self.head_len(status_line, content_type, headers) > self.room() {
+            self.overflow();
+            return;
+        }
+        unsafe {
+            let data_lake: &mut SmallLake<LAKE_SIZE> =
+                self.write_head(status_line, content_type, headers);
+            match length {
+                Some(length) => {
+                    data_lake.write(b"Content-Length: ".as_ptr(), 16);
+                    data_lake.write_num_str(length);
+                    data_lake.write(b"\r\n".as_ptr(), 2);
+                }
+                None => {
+                    const CHUNKED: &[u8] = b"Transfer-Encoding: chunked\r\n";
+                    data_lake.write(CHUNKED.as_ptr(), CHUNKED.len());
+                }
+            }
+            let base: &[u8] = self.base_headers();
+            let data_lake: &mut SmallLake<LAKE_SIZE> = &mut *self.data_lake;
+            data_lake.write(base.as_ptr(), base.len());
+            self.commit();
+        }
+        self.stream = Some(PendingStream {
+            body: Box::new(body),
+            length,
+        });
+    }
+
+    #[inline(always)]
+    pub(crate) fn set_route(&mut self, route: u16) {
+        self.route = route;
+    }
+
+    #[inline(always)]
+    pub(crate) fn route(&self) -> u16 {
+        self.route
+    }
+
+    /// This is the connection's last response. Goes out with `Connection: close`.
+    #[inline(always)]
+    pub(crate) fn set_close(&mut self) {
+        self.close = true;
+    }
+
+    #[inline(always)]
+    fn base_headers(&self) -> &'static [u8] {
+        match self.close {
+            true => CLOSE_HEADERS,
+            false => BASE_HEADERS,
+        }
+    }
+
+    #[inline(always)]
+    pub(crate) fn take_stream(&mut self) -> Option<PendingStream> {
+        self.stream.take()
+    }
+
+    // What fits in one go: the lake, or what's left of the hot cache if that's less.
+    #[inline(always)]
+    fn room(&self) -> usize {
+        self.out.len().min(LAKE_SIZE)
+    }
+
+    // Everything but the body, framing at its longest.
+    #[inline(always)]
+    fn head_len(&self, status_line: &[u8], content_type: &[u8], headers: &[u8]) -> usize {
+        status_line.len()
+            + content_type.len()
+            + self.date.len()
+            + 2
+            + headers.len()
+            + FRAMING_RESERVE
+            + self.base_headers().len()
+    }
+
+    // The handler asked for a head we can't build. It gets a 500 instead — ours always fits.
+    #[cold]
+    fn overflow(&mut self) {
+        error!("Response head doesn't fit in {} bytes. Sending 500 instead", self.room());
+        const BODY: &[u8] = b"Internal Server Error";
+        self.len = 0;
+        if self.head_len(STATUS_INTERNAL_SERVER_ERROR, CONTENT_TYPE_TEXT, &[]) + BODY.len()
+            <= self.room()
+        {
+            self.write(STATUS_INTERNAL_SERVER_ERROR, CONTENT_TYPE_TEXT, BODY);
+        }
+    }
+
+    // Status line, content type, date and extra headers. Framing headers are up to the caller.
+    #[inline(always)]
+    unsafe fn write_head(
+        &mut self,
+        status_line: &[u8],
+        content_type: &[u8],
+        headers: &[u8],
+    ) -> &mut SmallLake<LAKE_SIZE> {
+        let data_lake: &mut SmallLake<LAKE_SIZE> = &mut *self.data_lake;
+        data_lake.reset_pos();
+        // Build tachyon data lake
+        data_lake.write(status_line.as_ptr(), status_line.len());
+        data_lake.write(content_type.as_ptr(), content_type.len());
+        data_lake.write(self.date.as_ptr(), self.date.len());
+        data_lake.write(b"\r\n".as_ptr(), 2);
+        data_lake.write(headers.as_ptr(), headers.len());
+        data_lake
+    }
+
+    #[inline(always)]
+    unsafe fn commit(&mut self) {
+        let data_lake: &SmallLake<LAKE_SIZE> = &*self.data_lake;
+        LakeTools::write_to(self.out.as_mut_ptr(), data_lake.as_ptr(), data_lake.len());
+        self.len = data_lake.len();
+    }
+
+    /// Same as `write`, body is whatever sits in `json_buf`.
+    #[inline(always)]
+    pub fn write_json(&mut self, status_line: &[u8]) {
+        // json_buf and data_lake never alias, so detach the body from the borrow of self.
+        let json: &[u8] = unsafe { self.json_buf.as_slice() };
+        let body: &[u8] = unsafe { std::slice::from_raw_parts(json.as_ptr(), json.len()) };
+        self.write(status_line, CONTENT_TYPE_JSON, body);
+    }
+}
+
+/// The thing you plug into `Server` to actually serve something.
+///
+/// Called on the worker thread, right in the middle of the io_uring loop.
+/// Block here and every connection on this core blocks with you.
+pub trait Handler: Send + Sync {
+    fn handle(&self, request: &Request, response: &mut Response);
+}
+
+/// Default handler: everything is 404. Honest, at least.
+pub struct NotFound;
+
+impl Handler for NotFound {
+    fn handle(&self, _request: &Request, response: &mut Response) {
+        response.write(STATUS_NOT_FOUND, CONTENT_TYPE_TEXT, b"Not, found!");
+    }
+}
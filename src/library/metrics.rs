@@ -0,0 +1,364 @@
+use crate::library::{
+    router::Route,
+    utils::histogram::{AtomicHistogram, Histogram},
+};
+use std::{
+    fmt::Write,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+// Numbers for Prometheus, shared by every worker.
+//
+// Workers count in plain local fields — no atomics on the hot path — and tip their tallies in
+// here once a second, right where the stats line gets printed. Counters are totals since
+// start, gauges are each worker's last full second. A scrape is a second or two behind.
+// Latency quantiles cover everything since start, every worker merged at scrape time.
+
+/// `Content-Type` of the Prometheus text format.
+pub const CONTENT_TYPE_METRICS: &[u8] =
+    b"Content-Type: text/plain; version=0.0.4; charset=utf-8\r\n";
+
+// Status codes worth a slot: 100 to 599. Anything else isn't HTTP.
+const STATUS_MIN: usize = 100;
+const STATUS_SLOTS: usize = 500;
+
+// Latency quantiles worth a line.
+pub(crate) const QUANTILES: [f64; 3] = [0.5, 0.99, 0.999];
+
+/// Handle on the shared numbers. Cloned into every worker; empty until `run` sets it up.
+#[derive(Clone, Default)]
+pub struct Metrics {
+    shared: Option<Arc<Shared>>,
+}
+
+struct Shared {
+    requests: AtomicU64,
+    bytes_in: AtomicU64,
+    bytes_out: AtomicU64,
+    enobufs: AtomicU64,
+    accept_errors: AtomicU64,
+    rejected: AtomicU64,
+    throttled: AtomicU64,
+    statuses: Box<[AtomicU64]>,
+    // Method, path and count, in `Route` order. Route id `n` lives at `n - 1`.
+    routes: Box<[(&'static str, &'static str, AtomicU64)]>,
+    workers: Box<[Gauges]>,
+}
+
+#[derive(Default)]
+struct Gauges {
+    rps: AtomicU64,
+    hz: AtomicU64,
+    cq: AtomicU64,
+    sq: AtomicU64,
+    connections: AtomicU64,
+    buffers: AtomicU64,
+    buffers_in_use: AtomicU64,
+    // Latencies since start. Kept per worker, merged when somebody asks.
+    submitted: AtomicHistogram,
+    completed: AtomicHistogram,
+}
+
+/// One worker's last second, as `sq_poll` saw it.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct Second {
+    pub(crate) rps: u64,
+    pub(crate) hz: u64,
+    pub(crate) cq: u64,
+    pub(crate) sq: u64,
+    pub(crate) connections: u64,
+    pub(crate) buffers: u64,
+    pub(crate) buffers_in_use: u64,
+    pub(crate) rejected: u64,
+    pub(crate) throttled: u64,
+}
+
+/// What a worker counted since it last published. Plain numbers, one owner.
+#[derive(Clone, Default)]
+pub(crate) struct Tally {
+    pub(crate) bytes_in: u64,
+    pub(crate) bytes_out: u64,
+    pub(crate) enobufs: u64,
+    pub(crate) accept_errors: u64,
+    statuses: Vec<u64>,
+    routes: Vec<u64>,
+    /// Nanoseconds from a request's recv completion to its response's send being submitted.
+    pub(crate) submitted: Histogram,
+    /// Same start, up to the kernel confirming that send.
+    pub(crate) completed: Histogram,
+}
+
+impl Tally {
+    /// Count a response with status `code`.
+    #[inline(always)]
+    pub(crate) fn status(&mut self, code: u16) {
+        if let Some(count) = (code as usize)
+            .checked_sub(STATUS_MIN)
+            .and_then(|slot| self.statuses.get_mut(slot))
+        {
+            *count += 1;
+        }
+    }
+
+    /// Count a request the router sent to route `route`. 0 is nobody's.
+    #[inline(always)]
+    pub(crate) fn route(&mut self, route: u16) {
+        if let Some(count) = (route as usize)
+            .checked_sub(1)
+            .and_then(|slot| self.routes.get_mut(slot))
+        {
+            *count += 1;
+        }
+    }
+}
+
+impl Metrics {
+    /// Room for `workers` workers and every route in `routes`.
+    pub fn new(workers: usize, routes: &[Route]) -> Self {
+        Self {
+            shared: Some(Arc::new(Shared {
+                requests: AtomicU64::new(0),
+                bytes_in: AtomicU64::new(0),
+                bytes_out: AtomicU64::new(0),
+                enobufs: AtomicU64::new(0),
+                accept_errors: AtomicU64::new(0),
+                rejected: AtomicU64::new(0),
+                throttled: AtomicU64::new(0),
+                statuses: (0..STATUS_SLOTS).map(|_| AtomicU64::new(0)).collect(),
+                routes: routes
+                    .iter()
+                    .map(|route| (route.method, route.path, AtomicU64::new(0)))
+                    .collect(),
+                workers: (0..workers).map(|_| Gauges::default()).collect(),
+            })),
+        }
+    }
+
+    #[inline(always)]
+    pub fn is_active(&self) -> bool {
+        self.shared.is_some()
+    }
+
+    /// A blank tally, sized for what's being counted.
+    pub(crate) fn tally(&self) -> Tally {
+        match self.shared.as_deref() {
+            Some(shared) => Tally {
+                statuses: vec![0; STATUS_SLOTS],
+                routes: vec![0; shared.routes.len()],
+                ..Tally::default()
+            },
+            None => Tally::default(),
+        }
+    }
+
+    /// Add `tally` to the totals and zero it. Gauges of `worker` become `second`.
+    pub(crate) fn publish(&self, worker: usize, tally: &mut Tally, second: &Second) {
+        let shared: &Shared = match self.shared.as_deref() {
+            Some(shared) => shared,
+            None => return,
+        };
+        let add = |counter: &AtomicU64, value: u64| {
+            if value != 0 {
+                counter.fetch_add(value, Ordering::Relaxed);
+            }
+        };
+        add(&shared.requests, second.rps);
+        add(&shared.rejected, second.rejected);
+        add(&shared.throttled, second.throttled);
+        add(&shared.bytes_in, std::mem::take(&mut tally.bytes_in));
+        add(&shared.bytes_out, std::mem::take(&mut tally.bytes_out));
+        add(&shared.enobufs, std::mem::take(&mut tally.enobufs));
+        add(
+            &shared.accept_errors,
+            std::mem::take(&mut tally.accept_errors),
+        );
+        for (counter, count) in shared.statuses.iter().zip(tally.statuses.iter_mut()) {
+            add(counter, std::mem::take(count));
+        }
+        for ((_, _, counter), count) in shared.routes.iter().zip(tally.routes.iter_mut()) {
+            add(counter, std::mem::take(count));
+        }
+        if let Some(gauges) = shared.workers.get(worker) {
+            gauges.rps.store(second.rps, Ordering::Relaxed);
+            gauges.hz.store(second.hz, Ordering::Relaxed);
+            gauges.cq.store(second.cq, Ordering::Relaxed);
+            gauges.sq.store(second.sq, Ordering::Relaxed);
+            gauges
+                .connections
+                .store(second.connections, Ordering::Relaxed);
+            gauges.buffers.store(second.buffers, Ordering::Relaxed);
+            gauges
+                .buffers_in_use
+                .store(second.buffers_in_use, Ordering::Relaxed);
+            gauges.submitted.add(&tally.submitted);
+            gauges.completed.add(&tally.completed);
+        }
+        tally.submitted.clear();
+        tally.completed.clear();
+    }
+
+    /// Everything, in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let shared: &Shared = match self.shared.as_deref() {
+            Some(shared) => shared,
+            None => return String::new(),
+        };
+        let mut out: String = String::with_capacity(4096);
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        let counters: [(&str, &str, &AtomicU64); 7] = [
+            ("requests_total", "Requests answered.", &shared.requests),
+            (
+                "received_bytes_total",
+                "Bytes received from clients.",
+                &shared.bytes_in,
+            ),
+            (
+                "sent_bytes_total",
+                "Bytes the kernel took off our hands.",
+                &shared.bytes_out,
+            ),
+            (
+                "enobufs_total",
+                "Times the kernel ran out of recv buffers.",
+                &shared.enobufs,
+            ),
+            (
+                "accept_errors_total",
+                "Failed accepts.",
+                &shared.accept_errors,
+            ),
+            (
+                "rejected_connections_total",
+                "Connections turned away at a limit.",
+                &shared.rejected,
+            ),
+            (
+                "throttled_requests_total",
+                "Requests answered with 429.",
+                &shared.throttled,
+            ),
+        ];
+        for (name, help, counter) in counters {
+            header(&mut out, name, help, "counter");
+            let _ = writeln!(out, "tachyon_{} {}", name, load(counter));
+        }
+        header(
+            &mut out,
+            "responses_total",
+            "Responses by status code.",
+            "counter",
+        );
+        for (slot, counter) in shared.statuses.iter().enumerate() {
+            let count: u64 = load(counter);
+            if count != 0 {
+                let _ = writeln!(
+                    out,
+                    "tachyon_responses_total{{code=\"{}\"}} {}",
+                    slot + STATUS_MIN,
+                    count
+                );
+            }
+        }
+        if !shared.routes.is_empty() {
+            header(
+                &mut out,
+                "route_requests_total",
+                "Requests by route.",
+                "counter",
+            );
+            for (method, path, counter) in shared.routes.iter() {
+                let _ = writeln!(
+                    out,
+                    "tachyon_route_requests_total{{method=\"{}\",route=\"{}\"}} {}",
+                    escape(method),
+                    escape(path),
+                    load(counter)
+                );
+            }
+        }
+        let gauges: [(&str, &str, fn(&Gauges) -> &AtomicU64); 7] = [
+            ("rps", "Requests in the last second.", |gauges| &gauges.rps),
+            (
+                "loop_hz",
+                "Event loop rounds in the last second.",
+                |gauges| &gauges.hz,
+            ),
+            ("cq_depth", "Completion queue entries waiting.", |gauges| {
+                &gauges.cq
+            }),
+            ("sq_depth", "Submission queue entries waiting.", |gauges| {
+                &gauges.sq
+            }),
+            ("connections", "Open connections.", |gauges| {
+                &gauges.connections
+            }),
+            ("buffers", "Recv buffers in the pool.", |gauges| {
+                &gauges.buffers
+            }),
+            (
+                "buffers_in_use",
+                "Recv buffers not back with the kernel yet.",
+                |gauges| &gauges.buffers_in_use,
+            ),
+        ];
+        for (name, help, gauge) in gauges {
+            header(&mut out, name, help, "gauge");
+            for (worker, gauges) in shared.workers.iter().enumerate() {
+                let _ = writeln!(
+                    out,
+                    "tachyon_{}{{worker=\"{}\"}} {}",
+                    name,
+                    worker,
+                    load(gauge(gauges))
+                );
+            }
+        }
+        let latencies: [(&str, &str, fn(&Gauges) -> &AtomicHistogram); 2] = [
+            (
+                "send_submitted_seconds",
+                "From a request's recv completion to its response's send being submitted.",
+                |gauges| &gauges.submitted,
+            ),
+            (
+                "send_completed_seconds",
+                "From a request's recv completion to the kernel confirming its response's send.",
+                |gauges| &gauges.completed,
+            ),
+        ];
+        for (name, help, latency) in latencies {
+            let mut merged: Histogram = Histogram::default();
+            for gauges in shared.workers.iter() {
+                latency(gauges).merge_into(&mut merged);
+            }
+            header(&mut out, name, help, "summary");
+            for quantile in QUANTILES {
+                let _ = writeln!(
+                    out,
+                    "tachyon_{}{{quantile=\"{}\"}} {:.9}",
+                    name,
+                    quantile,
+                    merged.quantile(quantile) as f64 / 1e9
+                );
+            }
+            let _ = writeln!(out, "tachyon_{}_sum {:.9}", name, merged.sum() as f64 / 1e9);
+            let _ = writeln!(out, "tachyon_{}_count {}", name, merged.count());
+        }
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, help: &str, kind: &str) {
+    let _ = writeln!(out, "# HELP tachyon_{name} {help}");
+    let _ = writeln!(out, "# TYPE tachyon_{name} {kind}");
+}
+
+// Label values: backslash, quote and newline need escaping. Everything else goes as is.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
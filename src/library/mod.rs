@@ -1,4 +1,11 @@
+pub mod access_log;
+pub mod config;
+pub mod connection;
+pub mod handler;
+pub mod metrics;
 pub mod network;
+pub mod rate_limit;
+pub mod router;
 pub mod server;
 pub mod server_internals;
 pub mod uring;
@@ -0,0 +1,78 @@
+use crate::library::{
+    handler::{CONTENT_TYPE_TEXT, STATUS_BAD_REQUEST, STATUS_NOT_FOUND, STATUS_SUCCESS},
+    metrics::{CONTENT_TYPE_METRICS, Metrics},
+    utils::http::{HeadParse, parse_request_head},
+};
+use std::{
+    io::{self, Read, Write},
+    net::{TcpListener, TcpStream},
+    time::Duration,
+};
+use tracing::{error, trace};
+
+// The admin port. Plain std sockets on a thread of its own — nowhere near a ring.
+// One request per connection, then we hang up. Prometheus doesn't mind.
+
+// How long a scraper gets to say what it wants, and to take the answer.
+const ADMIN_TIMEOUT: Duration = Duration::from_secs(5);
+// Biggest request head we bother reading. Scrapers send a few hundred bytes.
+const ADMIN_MAX_HEAD: usize = 8 * 1024;
+
+/// Answer `GET path` on `listener` with `metrics`, forever. Everything else is a 404.
+pub fn serve_metrics(listener: TcpListener, metrics: Metrics, path: &'static str) {
+    for stream in listener.incoming() {
+        let result: io::Result<()> = stream.and_then(|stream| answer(stream, &metrics, path));
+        if let Err(err) = result {
+            trace!("Admin request failed: {}", err);
+        }
+    }
+    error!("Admin listener gave up");
+}
+
+fn answer(mut stream: TcpStream, metrics: &Metrics, path: &str) -> io::Result<()> {
+    stream.set_read_timeout(Some(ADMIN_TIMEOUT))?;
+    stream.set_write_timeout(Some(ADMIN_TIMEOUT))?;
+    let mut buf: Vec<u8> = vec![0u8; ADMIN_MAX_HEAD];
+    let mut len: usize = 0;
+    let (status, content_type, body): (&[u8], &[u8], Vec<u8>) = loop {
+        let read: usize = stream.read(&mut buf[len..])?;
+        if read == 0 {
+            return Ok(());
+        }
+        len += read;
+        match parse_request_head(&buf[..len]) {
+            HeadParse::Complete(head) => {
+                let requested: &[u8] = match memchr::memchr(b'?', head.path) {
+                    Some(q) => &head.path[..q],
+                    None => head.path,
+                };
+                if head.method == b"GET" && requested == path.as_bytes() {
+                    break (
+                        STATUS_SUCCESS,
+                        CONTENT_TYPE_METRICS,
+                        metrics.render().into_bytes(),
+                    );
+                }
+                break (STATUS_NOT_FOUND, CONTENT_TYPE_TEXT, b"Not, found!".to_vec());
+            }
+            HeadParse::Partial if len < buf.len() => continue,
+            HeadParse::Partial | HeadParse::Invalid | HeadParse::TooMany => {
+                break (
+                    STATUS_BAD_REQUEST,
+                    CONTENT_TYPE_TEXT,
+                    b"Bad Request".to_vec(),
+                );
+            }
+        }
+    };
+    let mut response: Vec<u8> = Vec::with_capacity(body.len() + 256);
+    response.extend_from_slice(status);
+    response.extend_from_slice(content_type);
+    write!(
+        response,
+        "Content-Length: {}\r\nServer: Tachyon\r\nConnection: close\r\n\r\n",
+        body.len()
+    )?;
+    response.extend_from_slice(&body);
+    stream.write_all(&response)
+}
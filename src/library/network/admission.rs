@@ -0,0 +1,67 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+// Bouncer at the door, shared by every worker.
+//
+// The global count is one atomic — every accept and every close touches it, so that's all it
+// gets to be. The per-IP table sits behind a lock, but only servers with a per-IP cap ever
+// take it. Per-worker limits need none of this: a worker just counts its own connections.
+
+#[derive(Clone, Default)]
+pub struct Admission {
+    open: Arc<AtomicUsize>,
+    per_ip: Arc<Mutex<HashMap<IpAddr, u32>>>,
+}
+
+impl Admission {
+    /// Connections open right now, all workers together.
+    #[inline(always)]
+    pub fn open(&self) -> usize {
+        self.open.load(Ordering::Relaxed)
+    }
+
+    /// Count one more connection, unless that's one past `max`. 0 means no limit.
+    #[inline(always)]
+    pub fn enter(&self, max: usize) -> bool {
+        let before: usize = self.open.fetch_add(1, Ordering::Relaxed);
+        if max != 0 && before >= max {
+            self.open.fetch_sub(1, Ordering::Relaxed);
+            return false;
+        }
+        true
+    }
+
+    /// One connection less. Pairs with a successful `enter`.
+    #[inline(always)]
+    pub fn leave(&self) {
+        self.open.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// Same as `enter`, for connections from `ip`. `max` must not be 0.
+    pub fn enter_ip(&self, ip: IpAddr, max: u32) -> bool {
+        let mut per_ip = self.per_ip.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let count: &mut u32 = per_ip.entry(ip).or_insert(0);
+        if *count >= max {
+            return false;
+        }
+        *count += 1;
+        true
+    }
+
+    /// Pairs with a successful `enter_ip`. Last one out takes the entry with it.
+    pub fn leave_ip(&self, ip: IpAddr) {
+        let mut per_ip = self.per_ip.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(count) = per_ip.get_mut(&ip) {
+            *count -= 1;
+            if *count == 0 {
+                per_ip.remove(&ip);
+            }
+        }
+    }
+}
@@ -0,0 +1,242 @@
+use libc::{
+    c_void, cmsghdr, iovec, msghdr, recvmsg, sendmsg, CMSG_DATA, CMSG_FIRSTHDR, CMSG_LEN,
+    CMSG_SPACE, MSG_CMSG_CLOEXEC, SCM_RIGHTS, SOL_SOCKET,
+};
+use std::{
+    io::{self, Read, Write},
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+        unix::net::{UnixListener, UnixStream},
+    },
+    time::{Duration, Instant},
+};
+use tracing::{error, info};
+
+// Zero-downtime upgrades, the old-school way.
+//
+// The running process listens on a Unix socket. A new process starting with the same
+// handoff path connects to it and gets every listening socket passed over with SCM_RIGHTS —
+// same sockets, same accept backlog, nobody gets a RST. The new one binds its own handoff
+// socket under a temporary name, spins up its workers and says "ready". Only then does the old
+// one stop accepting and drain, and only then does the new socket take over the path. If the new
+// process dies before saying ready, the old one shrugs and keeps serving — still reachable,
+// so the next upgrade finds it.
+//
+// The old one waits for "ready" in its main loop, next to signals and the watchdog. An upgrade
+// taking its time is no reason to stop answering systemd.
+//
+//   old                         new
+//    | <------- connect -------- |
+//    | ---- 'L' + listener fds ->|
+//    |                           | bind handoff socket aside, start workers
+//    | <-------- 'R' ----------- |
+//    | drain, exit               | move the socket onto the path, business as usual
+
+// The kernel won't pass more than this in one message anyway (SCM_MAX_FD).
+const MAX_HANDOFF_FDS: usize = 253;
+const LISTENERS: u8 = b'L';
+const READY: u8 = b'R';
+// How long the old process waits for its successor to get its act together.
+const READY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Listening sockets inherited from the previous process, plus the line back to it.
+pub struct Inherited {
+    pub listeners: Vec<OwnedFd>,
+    predecessor: UnixStream,
+}
+
+impl Inherited {
+    /// Tell the old process we're up. It starts draining as soon as it reads this.
+    pub fn ready(mut self) -> io::Result<()> {
+        self.predecessor.write_all(&[READY])
+    }
+}
+
+/// Ask whoever sits on `path` for its listeners. `None` if nobody's home — first start.
+pub fn inherit_listeners(path: &str) -> io::Result<Option<Inherited>> {
+    let predecessor: UnixStream = match UnixStream::connect(path) {
+        Ok(stream) => stream,
+        Err(err)
+            if matches!(
+                err.kind(),
+                io::ErrorKind::NotFound | io::ErrorKind::ConnectionRefused
+            ) =>
+        {
+            return Ok(None);
+        }
+        Err(err) => return Err(err),
+    };
+    let listeners: Vec<OwnedFd> = receive_fds(&predecessor)?
+        .into_iter()
+        .map(|fd| unsafe { OwnedFd::from_raw_fd(fd) })
+        .collect();
+    info!("Inherited {} listeners from {}", listeners.len(), path);
+    Ok(Some(Inherited {
+        listeners,
+        predecessor,
+    }))
+}
+
+// Where the handoff socket waits until it may take over `path`. Ours alone, by pid.
+fn staging_path(path: &str) -> String {
+    format!("{}.{}", path, std::process::id())
+}
+
+/// Bind the handoff socket — next to `path`, not on it. Whoever sits on `path` may be the
+/// predecessor, and it has to stay reachable until we're up. `publish_handoff` moves it over.
+pub fn bind_handoff(path: &str) -> io::Result<UnixListener> {
+    let staging: String = staging_path(path);
+    match std::fs::remove_file(&staging) {
+        Ok(()) => {}
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => return Err(err),
+    }
+    UnixListener::bind(&staging)
+}
+
+/// We're up: the handoff socket takes over `path`. Whatever was there is either stale or the
+/// predecessor we just took over from — either way it's ours now. The rename is atomic, so
+/// there's never a moment with nobody home.
+pub fn publish_handoff(path: &str) -> io::Result<()> {
+    std::fs::rename(staging_path(path), path)?;
+    info!("Handoff socket: {}", path);
+    Ok(())
+}
+
+/// A successor that has our listeners and owes us a "ready".
+pub struct Successor {
+    stream: UnixStream,
+    deadline: Instant,
+}
+
+impl Successor {
+    /// Readable once it has answered — or hung up.
+    #[inline(always)]
+    pub fn as_raw_fd(&self) -> RawFd {
+        self.stream.as_raw_fd()
+    }
+
+    /// How much longer we wait for it. Zero means it blew it.
+    #[inline(always)]
+    pub fn time_left(&self) -> Duration {
+        self.deadline.saturating_duration_since(Instant::now())
+    }
+
+    /// Its answer, once the fd is readable. True means it's ready and we should drain.
+    /// False — it didn't make it, carry on.
+    pub fn answer(mut self) -> bool {
+        let mut answer: [u8; 1] = [0u8; 1];
+        match self.stream.read_exact(&mut answer) {
+            Ok(()) if answer[0] == READY => {
+                info!("Successor is ready. Draining");
+                true
+            }
+            Ok(()) => {
+                error!("Successor said {:?} instead of ready. Staying up", answer[0]);
+                false
+            }
+            Err(err) => {
+                error!("Successor never got ready ({}). Staying up", err);
+                false
+            }
+        }
+    }
+}
+
+/// Serve one successor waiting on `handoff`: pass it `fds`. Its answer comes later — wait for
+/// `Successor::as_raw_fd`, at most `Successor::time_left`. `None` if it didn't get that far.
+pub fn hand_over(handoff: &UnixListener, fds: &[RawFd]) -> Option<Successor> {
+    let (stream, _) = match handoff.accept() {
+        Ok(conn) => conn,
+        Err(err) => {
+            error!("Handoff accept failed: {}", err);
+            return None;
+        }
+    };
+    info!("Successor connected. Handing over {} listeners", fds.len());
+    if let Err(err) = send_fds(&stream, fds) {
+        error!("Handoff failed: {}", err);
+        return None;
+    }
+    Some(Successor {
+        stream,
+        deadline: Instant::now() + READY_TIMEOUT,
+    })
+}
+
+fn send_fds(stream: &UnixStream, fds: &[RawFd]) -> io::Result<()> {
+    if fds.is_empty() || fds.len() > MAX_HANDOFF_FDS {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("can't hand over {} fds", fds.len()),
+        ));
+    }
+    let payload_len: u32 = (fds.len() * size_of::<RawFd>()) as u32;
+    let mut control: Vec<u8> = vec![0u8; unsafe { CMSG_SPACE(payload_len) } as usize];
+    let mut tag: [u8; 1] = [LISTENERS];
+    let mut iov: iovec = iovec {
+        iov_base: tag.as_mut_ptr() as *mut c_void,
+        iov_len: tag.len(),
+    };
+    unsafe {
+        let mut msg: msghdr = std::mem::zeroed();
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr() as *mut c_void;
+        msg.msg_controllen = control.len() as _;
+        let cmsg: *mut cmsghdr = CMSG_FIRSTHDR(&msg);
+        (*cmsg).cmsg_level = SOL_SOCKET;
+        (*cmsg).cmsg_type = SCM_RIGHTS;
+        (*cmsg).cmsg_len = CMSG_LEN(payload_len) as _;
+        std::ptr::copy_nonoverlapping(
+            fds.as_ptr() as *const u8,
+            CMSG_DATA(cmsg),
+            payload_len as usize,
+        );
+        if sendmsg(stream.as_raw_fd(), &msg, 0) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+fn receive_fds(stream: &UnixStream) -> io::Result<Vec<RawFd>> {
+    let capacity: u32 = (MAX_HANDOFF_FDS * size_of::<RawFd>()) as u32;
+    let mut control: Vec<u8> = vec![0u8; unsafe { CMSG_SPACE(capacity) } as usize];
+    let mut tag: [u8; 1] = [0u8; 1];
+    let mut iov: iovec = iovec {
+        iov_base: tag.as_mut_ptr() as *mut c_void,
+        iov_len: tag.len(),
+    };
+    let mut fds: Vec<RawFd> = Vec::new();
+    unsafe {
+        let mut msg: msghdr = std::mem::zeroed();
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr() as *mut c_void;
+        msg.msg_controllen = control.len() as _;
+        let n: isize = recvmsg(stream.as_raw_fd(), &mut msg, MSG_CMSG_CLOEXEC);
+        if n < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let cmsg: *mut cmsghdr = CMSG_FIRSTHDR(&msg);
+        if !cmsg.is_null() && (*cmsg).cmsg_level == SOL_SOCKET && (*cmsg).cmsg_type == SCM_RIGHTS
+        {
+            let payload: usize = (*cmsg).cmsg_len as usize - CMSG_LEN(0) as usize;
+            let data: *const RawFd = CMSG_DATA(cmsg) as *const RawFd;
+            for i in 0..payload / size_of::<RawFd>() {
+                fds.push(std::ptr::read_unaligned(data.add(i)));
+            }
+        }
+        if n != 1 || tag[0] != LISTENERS || fds.is_empty() {
+            for fd in fds {
+                libc::close(fd);
+            }
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "predecessor sent no listeners",
+            ));
+        }
+    }
+    Ok(fds)
+}
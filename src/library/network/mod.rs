@@ -1,2 +1,6 @@
+pub mod admin;
+pub mod admission;
+pub mod handoff;
 pub mod socket_helpers;
+pub mod systemd;
 pub mod tsocket;
@@ -1,10 +1,21 @@
 use libc::{O_NONBLOCK, fcntl, socklen_t};
-use std::os::fd::RawFd;
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    os::fd::RawFd,
+};
 use tracing::trace;
 
-pub unsafe fn prepare_incoming_socket(client_fd: RawFd) {
-    // Give the socket a 1MB send buffer — because bigger is always better (probably).
-    let sndbuf_size: i32 = 1 * 1024 * 1024;
+/// Tune a freshly accepted client socket. `tcp` is false for Unix domain sockets,
+/// which only get the send buffer and `O_NONBLOCK` — the rest means nothing to them.
+/// `busy_poll` is in microseconds, 0 turns it off. `zerocopy` is off on kernels without `SO_ZEROCOPY`.
+pub unsafe fn prepare_incoming_socket(
+    client_fd: RawFd,
+    tcp: bool,
+    sndbuf_size: i32,
+    busy_poll: i32,
+    zerocopy: bool,
+) {
+    // Give the socket a big send buffer — because bigger is always better (probably).
     libc::setsockopt(
         client_fd,
         libc::SOL_SOCKET,
@@ -23,15 +34,25 @@ pub unsafe fn prepare_incoming_socket(client_fd: RawFd) {
         &mut len,
     );
     trace!("Real sndbuf client size: {} bytes", size);
+    if tcp {
+        tune_tcp_socket(client_fd, busy_poll, zerocopy);
+    }
+    // Make the socket non-blocking — like a true async rebel.
+    let flag = fcntl(client_fd, libc::F_GETFL);
+    fcntl(client_fd, libc::F_SETFL, flag | O_NONBLOCK);
+}
+
+unsafe fn tune_tcp_socket(client_fd: RawFd, busy_poll: i32, zerocopy: bool) {
     // Enable busy polling — let the kernel aggressively wait like it's on caffeine.
-    let timeout: i32 = 50;
-    libc::setsockopt(
-        client_fd,
-        libc::SOL_SOCKET,
-        libc::SO_BUSY_POLL,
-        &timeout as *const _ as *const libc::c_void,
-        size_of::<i32>() as socklen_t,
-    );
+    if busy_poll > 0 {
+        libc::setsockopt(
+            client_fd,
+            libc::SOL_SOCKET,
+            libc::SO_BUSY_POLL,
+            &busy_poll as *const _ as *const libc::c_void,
+            size_of::<i32>() as socklen_t,
+        );
+    }
     // Disable Nagle's algorithm — small packets need love too.
     let flag: i32 = 1;
     libc::setsockopt(
@@ -42,15 +63,64 @@ pub unsafe fn prepare_incoming_socket(client_fd: RawFd) {
         size_of::<i32>() as socklen_t,
     );
     // Turn on zero-copy because copying memory is so 20th century.
-    let enable: libc::c_int = 1;
-    libc::setsockopt(
-        client_fd,
+    if zerocopy {
+        let enable: libc::c_int = 1;
+        libc::setsockopt(
+            client_fd,
+            libc::SOL_SOCKET,
+            libc::SO_ZEROCOPY,
+            &enable as *const _ as *const libc::c_void,
+            size_of_val(&enable) as _,
+        );
+    }
+}
+
+/// Whether `fd` is an `AF_UNIX` socket. Listeners can come from systemd or a predecessor,
+/// so we ask the socket instead of trusting the config.
+pub unsafe fn is_unix_socket(fd: RawFd) -> bool {
+    let mut domain: libc::c_int = 0;
+    let mut len: socklen_t = size_of::<libc::c_int>() as socklen_t;
+    let ret: i32 = libc::getsockopt(
+        fd,
         libc::SOL_SOCKET,
-        libc::SO_ZEROCOPY,
-        &enable as *const _ as *const libc::c_void,
-        size_of_val(&enable) as _,
+        libc::SO_DOMAIN,
+        &mut domain as *mut _ as *mut libc::c_void,
+        &mut len,
     );
-    // Make the socket non-blocking — like a true async rebel.
-    let flag = fcntl(client_fd, libc::F_GETFL);
-    fcntl(client_fd, libc::F_SETFL, flag | O_NONBLOCK);
+    ret == 0 && domain == libc::AF_UNIX
+}
+
+/// Source address of a TCP client, IPv4-mapped IPv6 folded back into IPv4 — same client,
+/// whichever listener it came through. `None` if the kernel won't say.
+pub unsafe fn peer_ip(fd: RawFd) -> Option<IpAddr> {
+    let mut addr: libc::sockaddr_storage = std::mem::zeroed();
+    let mut len: socklen_t = size_of::<libc::sockaddr_storage>() as socklen_t;
+    if libc::getpeername(fd, &mut addr as *mut _ as *mut libc::sockaddr, &mut len) != 0 {
+        return None;
+    }
+    match addr.ss_family as i32 {
+        libc::AF_INET => {
+            let v4: &libc::sockaddr_in = &*(&addr as *const _ as *const libc::sockaddr_in);
+            Some(IpAddr::V4(Ipv4Addr::from(u32::from_be(v4.sin_addr.s_addr))))
+        }
+        libc::AF_INET6 => {
+            let v6: &libc::sockaddr_in6 = &*(&addr as *const _ as *const libc::sockaddr_in6);
+            Some(IpAddr::V6(Ipv6Addr::from(v6.sin6_addr.s6_addr)).to_canonical())
+        }
+        _ => None,
+    }
+}
+
+/// Show a client we won't serve the door. With `response`, it gets those bytes first —
+/// best effort, one non-blocking send, no retries. Then the socket is closed.
+pub unsafe fn turn_away(fd: RawFd, response: Option<&[u8]>) {
+    if let Some(response) = response {
+        libc::send(
+            fd,
+            response.as_ptr() as *const libc::c_void,
+            response.len(),
+            libc::MSG_DONTWAIT | libc::MSG_NOSIGNAL,
+        );
+    }
+    libc::close(fd);
 }
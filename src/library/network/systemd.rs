@@ -0,0 +1,124 @@
+use std::{
+    env, io,
+    os::{
+        fd::{FromRawFd, OwnedFd, RawFd},
+        linux::net::SocketAddrExt,
+        unix::net::{SocketAddr, UnixDatagram},
+    },
+    time::Duration,
+};
+use tracing::{error, info};
+
+// Playing nice with systemd, without linking libsystemd.
+//
+// Socket activation: systemd opens the listening sockets and passes them starting at fd 3,
+// with LISTEN_FDS saying how many and LISTEN_PID saying who they're for.
+// Notifications: one datagram per state change to whatever NOTIFY_SOCKET points at.
+// That's the whole protocol. Fits on a napkin.
+
+// First fd systemd passes. Everything below is stdio.
+const SD_LISTEN_FDS_START: RawFd = 3;
+
+/// Listening sockets (TCP or Unix) passed in by systemd socket activation. Empty if there are none for us.
+///
+/// The `LISTEN_*` variables are cleared afterwards, so nothing we spawn gets confused.
+pub fn listen_fds() -> io::Result<Vec<OwnedFd>> {
+    let pid: Option<u32> = env::var("LISTEN_PID").ok().and_then(|pid| pid.parse().ok());
+    let count: Option<i32> = env::var("LISTEN_FDS").ok().and_then(|fds| fds.parse().ok());
+    unsafe {
+        // Called from `run` before any worker exists. Nobody else is reading the environment.
+        env::remove_var("LISTEN_PID");
+        env::remove_var("LISTEN_FDS");
+        env::remove_var("LISTEN_FDNAMES");
+    }
+    let count: i32 = match (pid, count) {
+        (Some(pid), Some(count)) if pid == std::process::id() && count > 0 => count,
+        _ => return Ok(Vec::new()),
+    };
+    let mut listeners: Vec<OwnedFd> = Vec::with_capacity(count as usize);
+    for fd in SD_LISTEN_FDS_START..SD_LISTEN_FDS_START + count {
+        if !is_listening(fd) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("fd {fd} from LISTEN_FDS is not a listening socket"),
+            ));
+        }
+        unsafe {
+            // systemd hands them over blocking and inheritable. We want neither.
+            libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+            let flags: i32 = libc::fcntl(fd, libc::F_GETFL);
+            libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK);
+            listeners.push(OwnedFd::from_raw_fd(fd));
+        }
+    }
+    info!("Socket activation: {} listeners from systemd", listeners.len());
+    Ok(listeners)
+}
+
+fn is_listening(fd: RawFd) -> bool {
+    let mut listening: i32 = 0;
+    let mut len: libc::socklen_t = size_of::<i32>() as libc::socklen_t;
+    let ret: i32 = unsafe {
+        libc::getsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            libc::SO_ACCEPTCONN,
+            &mut listening as *mut _ as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    ret == 0 && listening != 0
+}
+
+/// `sd_notify` without the library. Silent no-op outside of a `Type=notify` unit.
+pub struct Notifier {
+    socket: UnixDatagram,
+    addr: SocketAddr,
+}
+
+impl Notifier {
+    /// Whatever `NOTIFY_SOCKET` points at: a path, or an abstract socket if it starts with `@`.
+    pub fn from_env() -> Option<Self> {
+        let path: String = env::var("NOTIFY_SOCKET").ok()?;
+        let addr: io::Result<SocketAddr> = match path.strip_prefix('@') {
+            Some(name) => SocketAddr::from_abstract_name(name.as_bytes()),
+            None => SocketAddr::from_pathname(&path),
+        };
+        let notifier: io::Result<Self> = addr.and_then(|addr| {
+            Ok(Self {
+                socket: UnixDatagram::unbound()?,
+                addr,
+            })
+        });
+        match notifier {
+            Ok(notifier) => Some(notifier),
+            Err(err) => {
+                error!("NOTIFY_SOCKET {} is unusable: {}", path, err);
+                None
+            }
+        }
+    }
+
+    /// Send one state update, e.g. `READY=1`. Failures are logged and otherwise ignored —
+    /// systemd not listening is not a reason to stop serving.
+    pub fn notify(&self, state: &str) {
+        if let Err(err) = self.socket.send_to_addr(state.as_bytes(), &self.addr) {
+            error!("sd_notify {:?} failed: {}", state, err);
+        }
+    }
+}
+
+/// How often to pet the watchdog, if systemd wants us to: half of `WATCHDOG_USEC`,
+/// the same margin `sd_watchdog_enabled` suggests.
+pub fn watchdog_interval() -> Option<Duration> {
+    if let Ok(pid) = env::var("WATCHDOG_PID") {
+        if pid.parse::<u32>().ok() != Some(std::process::id()) {
+            return None;
+        }
+    }
+    let usec: u64 = env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+    if usec == 0 {
+        return None;
+    }
+    Some(Duration::from_micros(usec / 2))
+}
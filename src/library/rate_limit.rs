@@ -0,0 +1,167 @@
+use std::net::IpAddr;
+
+// Token buckets, one per client per limit class, in a fixed-size table per worker.
+//
+// Workers share nothing here — no locks, no atomics. The flip side: a client spread over
+// several workers gets a bucket in each, so per-IP limits are per worker.
+//
+// The table is 4-way set associative. A new client takes an empty slot in its set, or
+// evicts whoever was seen least recently. Evicted means forgotten: it comes back to a full
+// bucket. That's the price of never allocating. Keep the table big enough for the crowd.
+
+// Slots per worker. 24 bytes each.
+const TABLE_SLOTS: usize = 16 * 1024;
+const WAYS: usize = 4;
+
+/// Token bucket: `rate` requests per second sustained, `burst` at once.
+/// `rate` 0 means no limit. `burst` 0 means one second's worth.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RateLimit {
+    pub rate: u32,
+    pub burst: u32,
+}
+
+impl RateLimit {
+    #[inline(always)]
+    pub const fn new(rate: u32, burst: u32) -> Self {
+        Self { rate, burst }
+    }
+
+    #[inline(always)]
+    pub fn is_active(&self) -> bool {
+        self.rate != 0
+    }
+
+    #[inline(always)]
+    fn capacity(&self) -> f64 {
+        if self.burst == 0 {
+            self.rate as f64
+        } else {
+            self.burst as f64
+        }
+    }
+}
+
+/// What a request is checked against: a bucket for its connection and one for its
+/// source address. Either can be off.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Limits {
+    pub connection: RateLimit,
+    pub ip: RateLimit,
+}
+
+impl Limits {
+    #[inline(always)]
+    pub fn is_active(&self) -> bool {
+        self.connection.is_active() || self.ip.is_active()
+    }
+}
+
+/// Whose bucket. Connections are told apart by a per-worker serial — ids get reused,
+/// serials don't, so a new client never inherits the last one's empty bucket.
+#[derive(Clone, Copy, Debug)]
+pub enum Subject {
+    Connection(u64),
+    Ip(IpAddr),
+}
+
+#[derive(Clone, Copy, Default)]
+struct Slot {
+    // Hash of subject and class. 0 = free.
+    key: u64,
+    tokens: f64,
+    // `nano_clock` of the last refill.
+    last: i64,
+}
+
+#[derive(Clone, Default)]
+pub struct RateTable {
+    slots: Vec<Slot>,
+    seed: u64,
+}
+
+impl RateTable {
+    /// `seed` salts the hash, so nobody can pick addresses that all land in one set.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            slots: vec![Slot::default(); TABLE_SLOTS],
+            seed,
+        }
+    }
+
+    /// Take a token from the bucket of `subject` in limit class `class` at `now`.
+    /// Empty bucket? Then the answer is how many seconds until there's one again.
+    pub fn take(
+        &mut self,
+        subject: Subject,
+        class: u16,
+        limit: RateLimit,
+        now: i64,
+    ) -> Result<(), u32> {
+        if self.slots.is_empty() {
+            return Ok(());
+        }
+        let key: u64 = self.key(subject, class);
+        let set: usize = Self::set(key);
+        let ways: &mut [Slot] = &mut self.slots[set..set + WAYS];
+        let capacity: f64 = limit.capacity();
+        let slot: &mut Slot = match ways.iter().position(|slot| slot.key == key) {
+            Some(way) => &mut ways[way],
+            None => {
+                // Stranger. Free slot or the stalest one, and a full bucket to start with.
+                let way: usize = (0..WAYS)
+                    .min_by_key(|way| (ways[*way].key != 0, ways[*way].last))
+                    .unwrap();
+                ways[way] = Slot {
+                    key,
+                    tokens: capacity,
+                    last: now,
+                };
+                &mut ways[way]
+            }
+        };
+        let elapsed: f64 = (now - slot.last).max(0) as f64 / 1_000_000_000.0;
+        slot.tokens = (slot.tokens + elapsed * limit.rate as f64).min(capacity);
+        slot.last = now;
+        if slot.tokens >= 1.0 {
+            slot.tokens -= 1.0;
+            return Ok(());
+        }
+        Err(((1.0 - slot.tokens) / limit.rate as f64).ceil().max(1.0) as u32)
+    }
+
+    /// First slot of the set `subject` lands in for `class`. Same set, same four ways to share.
+    pub fn set_of(&self, subject: Subject, class: u16) -> usize {
+        Self::set(self.key(subject, class))
+    }
+
+    #[inline(always)]
+    fn set(key: u64) -> usize {
+        (key as usize % (TABLE_SLOTS / WAYS)) * WAYS
+    }
+
+    #[inline(always)]
+    fn key(&self, subject: Subject, class: u16) -> u64 {
+        let (tag, value): (u64, u128) = match subject {
+            Subject::Connection(serial) => (1, serial as u128),
+            Subject::Ip(IpAddr::V4(ip)) => (2, u32::from(ip) as u128),
+            Subject::Ip(IpAddr::V6(ip)) => (3, u128::from(ip)),
+        };
+        // Class gets mixed in on its own. XOR it straight into the value and serial 1 in
+        // class 0 turns out to be serial 0 in class 1.
+        let mut hash: u64 = mix(self.seed ^ (tag << 16 | class as u64));
+        hash = mix(hash ^ value as u64);
+        hash = mix(hash ^ (value >> 64) as u64);
+        // 0 marks a free slot. Nobody gets to be 0.
+        hash | 1
+    }
+}
+
+// splitmix64 finalizer. Cheap, and every input bit moves every output bit.
+#[inline(always)]
+fn mix(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    x ^ (x >> 31)
+}
@@ -0,0 +1,334 @@
+use crate::library::{
+    handler::{
+        CONTENT_TYPE_TEXT, Handler, Request, Response, STATUS_METHOD_NOT_ALLOWED, STATUS_NOT_FOUND,
+    },
+    rate_limit::Limits,
+};
+use std::io;
+
+pub const MAX_PARAMS: usize = 8;
+
+/// Route endpoint. Plain fn pointer — no boxes, no captures, no vtables.
+pub type RouteFn = fn(&Request, &mut Response);
+
+/// Route declaration as handed to `Server::route`. Compiled into a `Router` at `Server::build()`.
+#[derive(Clone, Copy)]
+pub struct Route {
+    pub method: &'static str,
+    pub path: &'static str,
+    pub handler: RouteFn,
+    /// Rate limits of its own. `None` goes by the server-wide ones.
+    pub limits: Option<Limits>,
+}
+
+/// Extracted path params. Names come from the route table, values point straight into
+/// the kernel buffer. Fixed capacity, lives on the stack, dies with the request.
+#[derive(Debug, Clone, Copy)]
+pub struct Params<'a> {
+    entries: [(&'static [u8], &'a [u8]); MAX_PARAMS],
+    len: usize,
+}
+
+impl<'a> Default for Params<'a> {
+    #[inline(always)]
+    fn default() -> Self {
+        Self {
+            entries: [(&[][..], &[][..]); MAX_PARAMS],
+            len: 0,
+        }
+    }
+}
+
+impl<'a> Params<'a> {
+    #[inline(always)]
+    pub fn get(&self, name: &str) -> Option<&'a [u8]> {
+        self.entries[..self.len]
+            .iter()
+            .find(|(n, _)| *n == name.as_bytes())
+            .map(|(_, v)| *v)
+    }
+    #[inline(always)]
+    pub fn iter(&self) -> impl Iterator<Item = (&'static [u8], &'a [u8])> + '_ {
+        self.entries[..self.len].iter().copied()
+    }
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    #[inline(always)]
+    fn push(&mut self, name: &'static [u8], value: &'a [u8]) {
+        // Route compilation guarantees we never go past MAX_PARAMS.
+        self.entries[self.len] = (name, value);
+        self.len += 1;
+    }
+}
+
+#[derive(Default)]
+struct Node {
+    statics: Vec<(&'static [u8], usize)>,
+    param: Option<(&'static [u8], usize)>,
+    wildcard: Option<(&'static [u8], usize)>,
+    // Method, endpoint, limit class (0 = server-wide limits), route id.
+    methods: Vec<(&'static [u8], RouteFn, u16, u16)>,
+    // Pre-baked `Allow: ...\r\n` line for 405 replies.
+    allow: Vec<u8>,
+}
+
+enum Segment {
+    Static(&'static [u8]),
+    Param(&'static [u8]),
+    Wildcard(&'static [u8]),
+}
+
+pub enum RouteMatch<'a> {
+    /// Endpoint, params, route id (`Route` table position + 1) and limit class (0 if the route
+    /// goes by the server-wide limits — see `Router::class_limits`).
+    Found(RouteFn, Params<'a>, u16, u16),
+    MethodNotAllowed(&'a [u8]),
+    NotFound,
+}
+
+/// Compiled route table: a segment trie flattened into a Vec.
+///
+/// Lookup order per segment is static > `:param` > `*wildcard`, with backtracking,
+/// so `/users/new` wins over `/users/:id` and both win over `/users/*`.
+pub struct Router {
+    nodes: Vec<Node>,
+    // Limits of routes that have their own. Class `n` lives at `n - 1`.
+    limits: Vec<Limits>,
+}
+
+impl Router {
+    pub fn compile(routes: &[Route]) -> io::Result<Router> {
+        let mut router: Router = Router {
+            nodes: vec![Node::default()],
+            limits: Vec::new(),
+        };
+        for (index, route) in routes.iter().enumerate() {
+            router.insert(route, index)?;
+        }
+        for node in router.nodes.iter_mut() {
+            if node.methods.is_empty() {
+                continue;
+            }
+            node.allow.extend_from_slice(b"Allow: ");
+            for (i, (method, ..)) in node.methods.iter().enumerate() {
+                if i != 0 {
+                    node.allow.extend_from_slice(b", ");
+                }
+                node.allow.extend_from_slice(method);
+            }
+            node.allow.extend_from_slice(b"\r\n");
+        }
+        Ok(router)
+    }
+
+    // `index` is where `route` sits in the table. Its id is one more — 0 means no route.
+    fn insert(&mut self, route: &Route, index: usize) -> io::Result<()> {
+        let invalid = |reason: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("route {} {}: {}", route.method, route.path, reason),
+            )
+        };
+        if !route.path.starts_with('/') {
+            return Err(invalid("path must start with '/'"));
+        }
+        let segments: Vec<Segment> = Self::split_route(route.path);
+        let params: usize = segments
+            .iter()
+            .filter(|s| !matches!(s, Segment::Static(_)))
+            .count();
+        if params > MAX_PARAMS {
+            return Err(invalid("too many params"));
+        }
+        if index >= u16::MAX as usize {
+            return Err(invalid("too many routes"));
+        }
+        let mut node: usize = 0;
+        for (i, segment) in segments.iter().enumerate() {
+            node = match *segment {
+                Segment::Static(name) => {
+                    match self.nodes[node].statics.iter().find(|(s, _)| *s == name) {
+                        Some((_, child)) => *child,
+                        None => {
+                            let child: usize = self.new_node();
+                            self.nodes[node].statics.push((name, child));
+                            child
+                        }
+                    }
+                }
+                Segment::Param(name) => match self.nodes[node].param {
+                    Some((existing, child)) if existing == name => child,
+                    Some(_) => return Err(invalid("conflicting param name at the same position")),
+                    None => {
+                        let child: usize = self.new_node();
+                        self.nodes[node].param = Some((name, child));
+                        child
+                    }
+                },
+                Segment::Wildcard(name) => {
+                    if i != segments.len() - 1 {
+                        return Err(invalid("wildcard must be the last segment"));
+                    }
+                    match self.nodes[node].wildcard {
+                        Some((existing, child)) if existing == name => child,
+                        Some(_) => return Err(invalid("conflicting wildcard name")),
+                        None => {
+                            let child: usize = self.new_node();
+                            self.nodes[node].wildcard = Some((name, child));
+                            child
+                        }
+                    }
+                }
+            };
+        }
+        let class: u16 = match route.limits {
+            Some(_) if self.limits.len() >= u16::MAX as usize => {
+                return Err(invalid("too many routes with limits of their own"));
+            }
+            Some(limits) => {
+                self.limits.push(limits);
+                self.limits.len() as u16
+            }
+            None => 0,
+        };
+        let method: &'static [u8] = route.method.as_bytes();
+        let methods: &mut Vec<(&'static [u8], RouteFn, u16, u16)> = &mut self.nodes[node].methods;
+        if methods.iter().any(|(m, ..)| *m == method) {
+            return Err(invalid("duplicate route"));
+        }
+        methods.push((method, route.handler, class, index as u16 + 1));
+        Ok(())
+    }
+
+    fn new_node(&mut self) -> usize {
+        self.nodes.push(Node::default());
+        self.nodes.len() - 1
+    }
+
+    fn split_route(path: &'static str) -> Vec<Segment> {
+        path.as_bytes()
+            .split(|b| *b == b'/')
+            .filter(|s| !s.is_empty())
+            .map(|s| match s[0] {
+                b':' => Segment::Param(&s[1..]),
+                b'*' if s.len() == 1 => Segment::Wildcard(b"*"),
+                b'*' => Segment::Wildcard(&s[1..]),
+                _ => Segment::Static(s),
+            })
+            .collect()
+    }
+
+    /// Resolve method + path. Query string is ignored for matching.
+    #[inline(always)]
+    pub fn lookup<'a>(&'a self, method: &[u8], path: &'a [u8]) -> RouteMatch<'a> {
+        let path: &[u8] = match memchr::memchr(b'?', path) {
+            Some(q) => &path[..q],
+            None => path,
+        };
+        let path: &[u8] = path.strip_prefix(b"/").unwrap_or(path);
+        let mut params: Params = Params::default();
+        let node: usize = match self.find(0, path, &mut params) {
+            Some(node) => node,
+            None => return RouteMatch::NotFound,
+        };
+        let node: &Node = &self.nodes[node];
+        match node.methods.iter().find(|(m, ..)| *m == method) {
+            Some((_, handler, class, route)) => {
+                RouteMatch::Found(*handler, params, *route, *class)
+            }
+            None => RouteMatch::MethodNotAllowed(&node.allow),
+        }
+    }
+
+    /// Answer `request` with what `lookup` made of it. For callers that needed the match first.
+    #[inline(always)]
+    pub fn respond(&self, matched: RouteMatch, request: &Request, response: &mut Response) {
+        match matched {
+            RouteMatch::Found(handler, params, route, _) => {
+                let request: Request = Request { params, ..*request };
+                response.set_route(route);
+                handler(&request, response);
+            }
+            RouteMatch::MethodNotAllowed(allow) => response.write_with_headers(
+                STATUS_METHOD_NOT_ALLOWED,
+                CONTENT_TYPE_TEXT,
+                allow,
+                b"Method Not Allowed",
+            ),
+            RouteMatch::NotFound => {
+                response.write(STATUS_NOT_FOUND, CONTENT_TYPE_TEXT, b"Not, found!")
+            }
+        }
+    }
+
+    /// Whether any route brought limits of its own. If not, every class is 0.
+    #[inline(always)]
+    pub fn has_limits(&self) -> bool {
+        !self.limits.is_empty()
+    }
+
+    /// Limits of a class from `RouteMatch::Found`. `None` for 0 — no limits of its own.
+    #[inline(always)]
+    pub fn class_limits(&self, class: u16) -> Option<Limits> {
+        match class {
+            0 => None,
+            class => self.limits.get(class as usize - 1).copied(),
+        }
+    }
+
+    fn find<'a>(&self, node: usize, rest: &'a [u8], params: &mut Params<'a>) -> Option<usize> {
+        let current: &Node = &self.nodes[node];
+        if rest.is_empty() {
+            if !current.methods.is_empty() {
+                return Some(node);
+            }
+            // `/static/*` also matches `/static` — with an empty tail.
+            if let Some((name, child)) = current.wildcard {
+                params.push(name, rest);
+                return Some(child);
+            }
+            return None;
+        }
+        let (segment, tail): (&[u8], &[u8]) = match memchr::memchr(b'/', rest) {
+            Some(slash) => (&rest[..slash], &rest[slash + 1..]),
+            None => (rest, &[][..]),
+        };
+        for (name, child) in current.statics.iter() {
+            if *name == segment {
+                if let Some(found) = self.find(*child, tail, params) {
+                    return Some(found);
+                }
+                break;
+            }
+        }
+        if let Some((name, child)) = current.param {
+            if !segment.is_empty() {
+                let checkpoint: usize = params.len;
+                params.push(name, segment);
+                if let Some(found) = self.find(child, tail, params) {
+                    return Some(found);
+                }
+                // Dead end. Roll back and pretend we never went there.
+                params.len = checkpoint;
+            }
+        }
+        if let Some((name, child)) = current.wildcard {
+            params.push(name, rest);
+            return Some(child);
+        }
+        None
+    }
+}
+
+impl Handler for Router {
+    #[inline(always)]
+    fn handle(&self, request: &Request, response: &mut Response) {
+        self.respond(self.lookup(request.method, request.path), request, response);
+    }
+}
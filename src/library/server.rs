@@ -1,32 +1,75 @@
 use crate::library::{
-    network::socket_helpers::prepare_incoming_socket,
+    access_log::{AccessLog, AccessLogFormat},
+    connection::{Connection, OutboundStream, PendingChunked},
+    handler::{
+        status_code, Handler, NotFound, PendingStream, Request, Response, CONTENT_TYPE_TEXT, KEEPALIVE_MAX_REQUESTS,
+        KEEPALIVE_TIMEOUT, RESPONSE_OVERLOADED, STATUS_BAD_REQUEST, STATUS_HEADERS_TOO_LARGE, STATUS_NOT_IMPLEMENTED,
+        STATUS_PAYLOAD_TOO_LARGE, STATUS_REQUEST_TIMEOUT, STATUS_SUCCESS, STATUS_TOO_MANY_REQUESTS,
+    },
+    metrics::{Metrics, Second, Tally, CONTENT_TYPE_METRICS, QUANTILES},
+    network::{
+        admin::serve_metrics,
+        admission::Admission,
+        handoff::{
+            bind_handoff, hand_over, inherit_listeners, publish_handoff, Inherited, Successor,
+        },
+        socket_helpers::{is_unix_socket, peer_ip, prepare_incoming_socket, turn_away},
+        systemd::{listen_fds, watchdog_interval, Notifier},
+    },
+    rate_limit::{Limits, RateLimit, RateTable, Subject},
+    router::{Route, RouteFn, RouteMatch, Router},
     server_internals::{
-        ServerInternal, UserData, BUFFER_REGISTER_CODE, CODE_ACCEPT, INIT_REQUEST,
-        POLL_EVENT, REQ_RESP_OFFSET,
+        ServerInternal, UserData, BUFFER_REGISTER_CODE, BUF_GROUP, CODE_ACCEPT, CODE_ACCEPT_ONCE, CODE_ACCEPT_UNIX,
+        CODE_DRAIN_TIMEOUT, CODE_SHUTDOWN, CODE_TICK, INIT_REQUEST, POLL_EVENT, REQ_RESP_OFFSET, SEND_EVENT, SHUTDOWN_EVENT,
+        STREAM_SEND, WRITABLE_EVENT,
     },
     uring::{
-        kernel_cmds::{accept_multi, poll_add, provide_buffer, recv_multi, send},
+        kernel_cmds::{
+            accept_multi, accept_once, cancel, cancel_accepts, poll_add, poll_once, poll_writable, provide_buffer,
+            provide_buffers, recv_buf_group, recv_multi, send_tracked, shutdown, timeout,
+        },
+        probe::Capabilities,
         Uring,
     },
     utils::{
+        buffer_pool::BufferPool,
         faf_helpers::attach_reuseport_cbpf,
-        http::{parse_http_methods_paths, RequestEntry},
+        histogram::Histogram,
+        chunked::{ChunkedDecoder, ChunkedStatus},
+        compat::{nano_http_date, nano_timestamp, timestamp, SmallLake},
+        cpu::init_simd_level,
+        http::{
+            parse_headers, parse_http_methods_paths, parse_request_head, BodyFraming, HeadParse,
+            Headers, HeadersParse, RequestEntry, RequestHead,
+        },
         kernel::log_kernel_error,
         shift::shift_ub_inplace,
-        trim::l_trim256,
+        signals::{ShutdownSignal, Wakeup},
+        trim::l_trim,
     },
 };
+use bytes::Bytes;
 use core_affinity::CoreId;
-use io_uring::{cqueue, squeue::Entry, CompletionQueue, SubmissionQueue, Submitter};
-use lake::small_lake::SmallLake;
+use io_uring::{
+    cqueue,
+    squeue::Entry,
+    types::Timespec,
+    CompletionQueue, SubmissionQueue, Submitter,
+};
 use libc::ENOBUFS;
-use nano_clock::{nano_http_date, nano_timestamp, timestamp};
 use stable_vec::ExternStableVec;
 use std::{
     cell::Cell,
-    io,
-    net::TcpListener,
-    os::fd::{AsRawFd, RawFd},
+    io::{self, Write},
+    net::{IpAddr, TcpListener},
+    os::{
+        fd::{AsRawFd, OwnedFd, RawFd},
+        unix::net::UnixListener,
+    },
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
     thread,
 };
 use std::time::Duration;
@@ -34,12 +77,34 @@ use tachyon_json::TachyonBuffer;
 use thread_priority::{ThreadBuilderExt, *};
 use tracing::{error, info, trace};
 
+// Default recv buffer size. Also what the per-connection staging areas are sized by.
 pub(crate) const BUFFER_SIZE: usize = 7168; // 6272 7168
-const BUFFERS_COUNT: usize = 1024;
+const DEFAULT_BUFFERS_COUNT: usize = 1024;
+// Buffer ids are 16 bits on the wire. The kernel takes at most this many per group.
+const MAX_BUFFERS_COUNT: usize = 32768;
+// What a worker needs on its stack now that the buffers live on the heap. Generous on purpose.
+const WORKER_STACK_SIZE: usize = 64 * 1024 * 1024;
 const DEFAULT_URING_SIZE: u32 = 4096;
 const DEFAULT_ACCEPT_MULTIPLICATOR: u8 = 16;
 const DEFAULT_SQPOLL_IDLE: u32 = 5000;
 const DATA_LAKE_SIZE: usize = BUFFER_SIZE * 3; // 4100
+const DEFAULT_MAX_BODY_SIZE: usize = 1024 * 1024;
+const DEFAULT_MAX_HEADER_SIZE: usize = 16 * 1024;
+const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_HEADER_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_BODY_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_SEND_TIMEOUT: Duration = Duration::from_secs(30);
+// How often overstaying connections get looked for. Timeouts are this coarse.
+const TICK_INTERVAL: Duration = Duration::from_secs(1);
+const DEFAULT_LISTEN_BACKLOG: i32 = 32768;
+const DEFAULT_SNDBUF_SIZE: i32 = 1024 * 1024;
+const DEFAULT_BUSY_POLL: i32 = 50;
+// What `client_out_buffers` is sized for. Past that a worker is better off saying no.
+const DEFAULT_MAX_WORKER_CONNECTIONS: usize = u16::MAX as usize;
+// The kernel refuses rings bigger than this (IORING_MAX_ENTRIES).
+const MAX_URING_SIZE: u32 = 32768;
+// Worst case a single response can take in the hot cache (it's staged in a 512 byte lake).
+const HOT_RESPONSE_RESERVE: usize = 512;
 
 thread_local! {
     static CURRENT_KERNEL_BUF: Cell<*const u8> = Cell::<*const u8>::new(std::ptr::null());
@@ -48,27 +113,95 @@ thread_local! {
 #[derive(Clone)]
 pub struct Server {
     // Public config
-    addr: &'static str,
+    addrs: Vec<&'static str>,
+    ipv6_only: Option<bool>,
     workers: u8,
     uring_size: u32,
     sqpoll_enabled: bool,
     sqpoll_idle: u32,
     realtime: bool,
     ub_kernel_dma: bool,
+    handler: Arc<dyn Handler>,
+    routes: Vec<Route>,
+    max_body_size: usize,
+    max_header_size: usize,
+    drain_timeout: Duration,
+    header_timeout: Duration,
+    body_timeout: Duration,
+    send_timeout: Duration,
+    handoff_path: Option<&'static str>,
+    unix_paths: Vec<&'static str>,
+    unix_mode: Option<u32>,
+    unix_owner: Option<(u32, u32)>,
+    accept_multiplicator: u8,
+    listen_backlog: i32,
+    sndbuf_size: i32,
+    busy_poll: i32,
+    buffer_size: usize,
+    buffers_count: usize,
+    huge_pages: bool,
+    max_connections: usize,
+    max_worker_connections: usize,
+    max_connections_per_ip: u32,
+    reject_with_503: bool,
+    rate_limits: Limits,
+    metrics_path: Option<&'static str>,
+    metrics_addr: Option<&'static str>,
+    access_log_path: Option<&'static str>,
+    access_log_format: AccessLogFormat,
     // Internal
     client_fds: ExternStableVec<RawFd>,
+    connections: ExternStableVec<Connection>,
+    // Ids of connections that are gone, up for grabs again. Stable vecs never reuse a slot on
+    // their own.
+    free_ids: Vec<usize>,
 
     pub(crate) date: [u8; 35],
     hot_json_buf: TachyonBuffer<100>,
     hot_data_lake: SmallLake<512>, // 230
 
     sync_now: bool,
-    buffers: [[u8; BUFFER_SIZE]; BUFFERS_COUNT],
+    buffers: BufferPool,
     released_buffers: Vec<u16>,
+    // Recv buffers we got from a completion and haven't handed back to the kernel yet.
+    buffers_in_use: u64,
     client_out_buffers: ExternStableVec<SmallLake<DATA_LAKE_SIZE>>,
     hot_internal_cache: [u8; BUFFER_SIZE * 2],
     universal_counter: usize,
     io_send_busy: bool,
+    // Graceful shutdown
+    shutdown_fd: RawFd,
+    listener_fds: Vec<RawFd>,
+    // What this ring's kernel can do. Decides between multishot and the one-at-a-time fallbacks.
+    caps: Capabilities,
+    // Who's in, across all workers. Shared by every clone.
+    admission: Admission,
+    // Routes with rate limits of their own. `None` if there are none.
+    rate_router: Option<Arc<Router>>,
+    // This worker's token buckets. Stays empty unless something is rate limited.
+    rate_table: RateTable,
+    // Next connection serial.
+    serials: u64,
+    // Numbers for /metrics. Shared by all workers, empty if nobody asked for them.
+    metrics: Metrics,
+    // What this worker counted since it last published.
+    tally: Tally,
+    // Our seat in `metrics`, and our ring in `access_log`.
+    worker: usize,
+    // Per-worker rings the log thread empties. Empty if there's no access log.
+    access_log: AccessLog,
+    // Requests answered with 429 this second, and last second.
+    _throttled: usize,
+    throttled: usize,
+    // Connections turned away this second. And the last full second's worth, for the stats.
+    _rejected: usize,
+    rejected: usize,
+    // Our listeners belong to a successor now. Hands off the sockets, just stop accepting.
+    handed_off: Arc<AtomicBool>,
+    draining: bool,
+    drain_expired: bool,
+    drain_ts: Timespec,
+    tick_ts: Timespec,
     // Internal clock
     nano_clock: i64,
     clock: i64,
@@ -89,7 +222,7 @@ unsafe impl Send for Server {}
 impl Server {
     /// # Safety:
     /// This function dances with the kernel using raw pointers and unholy rituals.
-    /// It assumes that `self.buffers` are properly allocated and aligned.
+    /// It assumes that `self.buffers` is mapped and nobody else handed it to a kernel.
     /// Call it only if you know what you're doing — or have accepted your fate.
     unsafe fn register_buffers(
         &mut self,
@@ -97,31 +230,23 @@ impl Server {
         submitter: &Submitter,
     ) -> io::Result<()> {
         trace!("Registering buffers");
-        // Prepare the kernel offering plate: N buffer registration requests
-        let rqs: [Entry; BUFFERS_COUNT] = std::array::from_fn(|i| {
-            trace!("    Registering buffer {} ({} bytes)", i, BUFFER_SIZE);
-            let ptr: *mut u8 = self.buffers[i].as_mut_ptr();
-            // Give the kernel a chunk of our soul (and memory)
-            provide_buffer(ptr, i as u16, BUFFER_SIZE as i32)
-        });
         trace!(
             "Total reserved {} bytes for kernel",
-            BUFFER_SIZE * BUFFERS_COUNT
+            self.buffers.buffer_size() * self.buffers.count()
+        );
+        // Give the kernel a chunk of our soul (and memory). The whole pool in one SQE.
+        let rq: Entry = provide_buffers(
+            self.buffers.base(),
+            self.buffers.buffer_size() as i32,
+            self.buffers.count() as u16,
         );
         trace!("Send to kernel...");
-        // Retry loop in case kernel isn't feeling cooperative today
-        loop {
-            match sq.push_multiple(rqs.as_slice()) {
-                Ok(_) => break,
-                Err(err) => {
-                    error!("Push buffers failed: {}", err);
-                }
-            }
-        }
+        sq.push(&rq)
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
         // Sync SQE state to the kernel
         sq.sync();
-        // Block until all buffers are acknowledged by the Great Ring
-        submitter.submit_and_wait(BUFFERS_COUNT)?;
+        // Block until the pool is acknowledged by the Great Ring
+        submitter.submit_and_wait(1)?;
         trace!("Buffers successfully registered in kernel");
         Ok(())
     }
@@ -139,29 +264,23 @@ impl Server {
             return Ok(());
         }
         trace!("Return buffers");
-        submitter.submit()?;
-        let mut rqs: Vec<Entry> = Vec::with_capacity(self.released_buffers.len());
+        let buffer_size: usize = self.buffers.buffer_size();
         for id in self.released_buffers.iter() {
-            trace!("    Return buffer {} ({} bytes)", id, BUFFER_SIZE);
-            let ptr: *mut u8 = self.buffers[*id as usize].as_mut_ptr();
-            rqs.push(provide_buffer(ptr, *id, BUFFER_SIZE as i32));
-        }
-        trace!("Send to kernel...");
-        // Spam the kernel until it finally takes our offering
-        loop {
-            match sq.push_multiple(rqs.as_slice()) {
-                Ok(_) => break,
-                Err(err) => {
-                    error!("Push buffers failed: {}", err);
-                }
+            trace!("    Return buffer {} ({} bytes)", id, buffer_size);
+            let ptr: *mut u8 = self.buffers.ptr(*id);
+            let rq: Entry = provide_buffer(ptr, *id, buffer_size as i32);
+            // SQ full? Hand the kernel what's there and try again. Never wait for the answers.
+            while sq.push(&rq).is_err() {
+                sq.sync();
+                submitter.submit()?;
+                sq.sync();
             }
         }
-        // Let the kernel know we’re done submitting. For now.
-        sq.sync();
-        // Block until the kernel acknowledges every last byte
-        submitter.submit_and_wait(self.released_buffers.len())?;
-        trace!("Buffers successfully registered in kernel");
+        // The main loop flushes it with everything else.
+        self.sync_now = true;
+        trace!("Buffers queued for the kernel");
         // The sacrifice is complete. Purge all traces.
+        self.buffers_in_use -= self.released_buffers.len() as u64;
         self.released_buffers.clear();
         Ok(())
     }
@@ -178,32 +297,67 @@ impl Server {
     /// We want to look directly into the kernel’s soul — or at least its socket buffer.
     ///
     /// Note: We also store the client’s output buffer (we’ll need it later to scream responses back).
+    /// `tcp` is false for connections off a Unix domain socket — same pipeline, fewer sockopts.
     unsafe fn process_entry_accept(
         &mut self,
         sq: &mut SubmissionQueue,
         result: i32,
+        tcp: bool,
     ) -> io::Result<()> {
         trace!("Accept request");
         let client_fd: i32 = result;
         if client_fd <= 0 {
+            // We hung up the listener ourselves. The pending accepts are just saying goodbye.
+            if self.draining {
+                trace!("Accept stopped: {client_fd}");
+                return Ok(());
+            }
             // Client tried to connect, kernel said "nope".
             error!("Connection accept error on FD:{client_fd}");
+            self.tally.accept_errors += 1;
             return Ok(());
         }
+        // Full house? Then they don't even get a connection ID.
+        let (peer, peer_counted): (Option<IpAddr>, bool) = match self.admit(client_fd, tcp) {
+            Some(peer) => peer,
+            None => {
+                self._rejected += 1;
+                turn_away(client_fd, self.reject_with_503.then_some(RESPONSE_OVERLOADED));
+                return Ok(());
+            }
+        };
         // Prep the client socket — disable Nagle, make it raw and fast and angry.
-        prepare_incoming_socket(client_fd);
+        prepare_incoming_socket(client_fd, tcp, self.sndbuf_size, self.busy_poll, self.caps.zerocopy);
         // Store the file descriptor and assign it a logical ID (our own personal client index).
-        let client_fd_id = self.client_fds.push(client_fd);
+        // Somebody's old one if there is one — otherwise the tables would only ever grow.
+        let client_fd_id: usize = match self.free_ids.pop() {
+            Some(id) => {
+                self.client_fds.insert(id, client_fd);
+                id
+            }
+            None => self.client_fds.push(client_fd),
+        };
+        // No connection limit means no telling how far the ids go. The side tables keep up.
+        self.client_out_buffers.reserve_for(client_fd_id);
+        self.connections.reserve_for(client_fd_id);
         // Allocate a per-client outgoing buffer. We don’t write yet, but it’s good to be ready.
         self.client_out_buffers
             .insert(client_fd_id, SmallLake::<DATA_LAKE_SIZE>::build());
+        // And a place to keep half-received requests between recv completions.
+        let mut connection: Connection = Connection::build(self.nano_clock);
+        connection.peer = peer;
+        connection.peer_counted = peer_counted;
+        connection.serial = self.serials;
+        self.serials += 1;
+        let generation: u16 = connection.generation();
+        self.connections.insert(client_fd_id, connection);
         trace!("Receive new accept on FD:{client_fd}. Connection ID: {client_fd_id}");
         // If UBDMA is enabled — we go turbo mode.
         // Instead of waiting for recv to finish, we slap a poll here and later just read the buffer directly.
         if self.ub_kernel_dma {
             let client_poll_flag = UserData {
                 client_id: client_fd_id as u32,
-                buffer_id: 0,
+                generation,
                 uniq_id: POLL_EVENT,
             };
             let poll: Entry = poll_add(client_fd, client_poll_flag.pack_user_data());
@@ -213,11 +367,11 @@ impl Server {
         // Schedule initial recv (multi-shot, of course — we’re not cavepeople).
         let user_data: u64 = UserData {
             client_id: client_fd_id as u32,
-            buffer_id: 0,
+            generation,
             uniq_id: INIT_REQUEST,
         }
         .pack_user_data();
-        sq.push(&recv_multi(client_fd, user_data)).unwrap_or(());
+        self.arm_recv(sq, client_fd, user_data);
         // Force the uring loop to flush to the kernel now.
         self.sync_now = true;
         Ok(())
@@ -239,34 +393,78 @@ impl Server {
         self.last_sync_time = self.nano_clock;
         // Collect all outgoing payloads and turn them into send entries.
         let mut entries: Vec<Entry> = Vec::with_capacity(100);
+        let mut finished_streams: Vec<usize> = Vec::new();
         for (client_id, data_lake) in self.client_out_buffers.iter_mut() {
-            if data_lake.len() == 0 {
+            // Do we still know this client? Or did it rage-quit the universe?
+            let (fd, connection): (RawFd, &mut Connection) =
+                match (self.client_fds.get(client_id), self.connections.get_mut(client_id)) {
+                    (Some(fd), Some(connection)) => (*fd, connection),
+                    _ => {
+                        error!("Buffer {} not found!", client_id);
+                        continue;
+                    }
+                };
+            // Whatever got staged since the last round joins the queue. The lake is free again.
+            if data_lake.len() != 0 {
+                connection.send.push(&data_lake.buf[..data_lake.pos]);
+                data_lake.reset_pos();
+            }
+            let generation: u16 = connection.generation();
+            let user_data = |uniq_id: u16| -> u64 {
+                UserData {
+                    client_id: client_id as u32,
+                    generation,
+                    uniq_id,
+                }
+                .pack_user_data()
+            };
+            // One send in the kernel's hands per client. Everything else waits its turn.
+            // A full socket buffer or a connection on its way out gets nothing at all.
+            if connection.sending() || connection.closing {
                 continue;
             }
-            // Do we still know this client? Or did it rage-quit the universe?
-            match self.client_fds.get(client_id) {
-                Some(fd) => {
-                    let fd: RawFd = *fd;
-                    // Build the sacred send entry.
-                    let send_entry: Entry = send(
-                        UserData {
-                            client_id: client_id as u32,
-                            buffer_id: 0,
-                            uniq_id: 0xCCCu16,
-                        }
-                        .pack_user_data(),
-                        fd,
-                        &data_lake.buf[..data_lake.pos],
-                        false,
-                    );
-                    entries.push(send_entry);
+            if let Some(data) = connection.send.begin() {
+                // Build the sacred send entry.
+                entries.push(send_tracked(user_data(SEND_EVENT), fd, data));
+                connection.send_since = self.nano_clock;
+                if let Some((wait, count)) = connection.timing.submit(self.nano_clock) {
+                    self.tally.submitted.record(wait, count);
                 }
-                None => {
-                    error!("Buffer {} not found!", client_id);
+                continue;
+            }
+            if connection.send.in_flight() {
+                continue;
+            }
+            // Queue is drained, so a streamed body may follow its head.
+            if let Some(stream) = connection.stream.as_deref_mut() {
+                let filled: io::Result<()> = match stream.pending().is_empty() && !stream.finished {
+                    true => stream.fill(),
+                    false => Ok(()),
+                };
+                if let Err(err) = filled {
+                    // Source broke, or ran dry short of its Content-Length. The framing is a lie
+                    // now — hang up.
+                    error!("Stream for client {} broke off: {}", client_id, err);
+                    connection.stream = None;
+                    connection.close_after_flush = true;
+                } else if stream.pending().is_empty() {
+                    finished_streams.push(client_id);
+                    continue;
+                } else {
+                    entries.push(send_tracked(user_data(STREAM_SEND), fd, stream.pending()));
+                    stream.in_flight = true;
+                    connection.send_since = self.nano_clock;
                     continue;
                 }
-            };
-            data_lake.reset_pos();
+            }
+            // Last words are out. Now the goodbye.
+            if connection.close_after_flush && !connection.hung_up {
+                entries.push(shutdown(fd, user_data(SHUTDOWN_EVENT)));
+                connection.hung_up = true;
+            }
+        }
+        for client_id in finished_streams {
+            self.finish_stream(client_id);
         }
         if entries.len() > 0 {
             sq.push_multiple(&entries).unwrap();
@@ -310,6 +508,7 @@ impl Server {
     /// If you're still reading this and thinking “that’s a bad idea” — yes, it is.
     /// That’s the point.
     ///
+    /// ```text
     ///        ☠ UBDMA ☠
     ///     ———————————————
     ///     |    DANGER!     |
@@ -318,6 +517,7 @@ impl Server {
     ///     |   and demons   |
     ///     ———————————————
     ///           (╯°□°）╯︵ ┻━┻
+    /// ```
     /// In conclusion: this works *only* because io_uring gives you dangerous freedom.
     /// It's not documented behavior. It’s just behavior. You saw the light turn green — so you stepped on the gas while the bus was still turning.
     unsafe fn ubdma(&mut self, client: UserData) -> io::Result<()> {
@@ -325,13 +525,14 @@ impl Server {
         // We begin our descent into madness. First, identify the chosen victim.
         let cid: usize = client.client_id as usize;
 
+        let buffer_size: usize = self.buffers.buffer_size();
         let buffer: Option<&[u8]> = std::panic::catch_unwind(|| {
             CURRENT_KERNEL_BUF.with(|cell| {
                 let ptr = cell.get();
                 if ptr.is_null() {
                     None
                 } else {
-                    Some(unsafe { std::slice::from_raw_parts(ptr, BUFFER_SIZE) })
+                    Some(unsafe { std::slice::from_raw_parts(ptr, buffer_size) })
                 }
             })
         })
@@ -346,7 +547,7 @@ impl Server {
         }
         let buffer = buffer.unwrap();
         // Try to make sense of the data inside while it’s still twitching. Extract HTTP requests.
-        let buffer: &[u8] = l_trim256(buffer);
+        let buffer: &[u8] = l_trim(buffer);
         let requests: ([RequestEntry; 50], usize) = parse_http_methods_paths(&buffer);
         // Hypothetically useful FDs (e.g. if we needed to set priority for latecomers).
         let useful: Vec<RawFd> = Vec::with_capacity(requests.1.saturating_sub(5) * DATA_LAKE_SIZE);
@@ -384,16 +585,18 @@ impl Server {
         for index in 0..requests.1 {
             self._rps += 1;
             let request: &RequestEntry = requests.0.get(index).unwrap();
-            let len: usize = Self::handler(
-                request.0,
-                request.1,
+            let mut response: Response = Response::new(
                 &self.date,
                 &mut self.hot_internal_cache[total_len..],
                 &mut self.hot_json_buf,
                 &mut self.hot_data_lake,
             );
+            self.handler
+                .handle(&Request::new(request.0, request.1), &mut response);
+            // No streams in UBDMA. The head goes out, the body goes to the void. You were warned.
+            drop(response.take_stream());
             // println!("{}", String::from_utf8_lossy(&self.hot_internal_cache));
-            total_len += len;
+            total_len += response.len();
         }
         // And now... PUSH! Like the buffer owes us money.
         let hot_slice = &self.hot_internal_cache[..total_len];
@@ -403,77 +606,527 @@ impl Server {
         self.sync_now = true;
         Ok(())
     }
+    /// Turn as many complete requests from `data` into responses as we can.
+    /// Returns how many bytes were eaten; the rest is an unfinished request.
+    unsafe fn serve_requests(&mut self, cid: usize, data: &[u8]) -> usize {
+        let mut offset: usize = 0;
+        let mut total_len: usize = 0;
+        while offset < data.len() {
+            // A streamed response owns the connection. The rest waits until it's out.
+            // And once we've said our last words, nothing else gets an answer.
+            match self.connections.get(cid) {
+                Some(connection) if connection.close_after_flush => {
+                    offset = data.len();
+                    break;
+                }
+                Some(connection) if connection.stream.is_some() => break,
+                _ => {}
+            }
+            // Stray CRLFs between pipelined requests are legal. Annoying, but legal.
+            if data[offset] == b'\r' || data[offset] == b'\n' {
+                offset += 1;
+                continue;
+            }
+            // Full head: request line and every header, straight from the kernel buffer.
+            let head: RequestHead = match parse_request_head(&data[offset..]) {
+                HeadParse::Complete(head) if head.head_len <= self.max_header_size => head,
+                // Half a head. The rest is on its way — keep it, within reason.
+                HeadParse::Partial if data.len() - offset <= self.max_header_size => {
+                    self.set_reading_body(cid, false);
+                    break;
+                }
+                HeadParse::Complete(_) | HeadParse::Partial | HeadParse::TooMany => {
+                    total_len = self.reject(cid, total_len, STATUS_HEADERS_TOO_LARGE);
+                    offset = data.len();
+                    break;
+                }
+                HeadParse::Invalid => {
+                    total_len = self.reject(cid, total_len, STATUS_BAD_REQUEST);
+                    offset = data.len();
+                    break;
+                }
+            };
+            let body_start: usize = offset + head.head_len;
+            // Framing decides where this request ends and the next one starts.
+            // Never scan a body for "GET" again.
+            let rejection: &[u8] = match head.headers.body_framing() {
+                BodyFraming::None => {
+                    total_len = self.dispatch(cid, total_len, &Request::from_head(&head, &[]));
+                    offset = body_start;
+                    continue;
+                }
+                BodyFraming::Length(len) if len <= self.max_body_size => {
+                    if data.len() - body_start < len {
+                        // Body is still on its way. Keep everything from the request line on.
+                        self.set_reading_body(cid, true);
+                        break;
+                    }
+                    let body: &[u8] = &data[body_start..body_start + len];
+                    total_len = self.dispatch(cid, total_len, &Request::from_head(&head, body));
+                    offset = body_start + len;
+                    continue;
+                }
+                BodyFraming::Chunked => {
+                    let mut decoder: ChunkedDecoder = ChunkedDecoder::new(self.max_body_size);
+                    match decoder.feed(&data[body_start..]) {
+                        ChunkedStatus::Done(used) => {
+                            total_len = self.dispatch_chunked(cid, total_len, &head, &decoder);
+                            offset = body_start + used;
+                            continue;
+                        }
+                        ChunkedStatus::NeedMore => {
+                            // Park head + decoder on the connection, the rest decodes as it arrives.
+                            if let Some(connection) = self.connections.get_mut(cid) {
+                                connection.chunked = Some(Box::new(PendingChunked {
+                                    head: data[offset..body_start].to_vec(),
+                                    decoder,
+                                }));
+                            }
+                            offset = data.len();
+                            break;
+                        }
+                        ChunkedStatus::TooLarge => STATUS_PAYLOAD_TOO_LARGE,
+                        ChunkedStatus::Invalid => STATUS_BAD_REQUEST,
+                    }
+                }
+                BodyFraming::Length(_) => STATUS_PAYLOAD_TOO_LARGE,
+                BodyFraming::Unsupported => STATUS_NOT_IMPLEMENTED,
+                BodyFraming::Invalid => STATUS_BAD_REQUEST,
+            };
+            total_len = self.reject(cid, total_len, rejection);
+            offset = data.len();
+            break;
+        }
+        // Fire the prepared response payload into the client's output buffer.
+        self.flush_hot_cache(cid, total_len);
+        offset
+    }
+
+    /// Run the handler for one request, appending the response to the hot cache at `total_len`.
+    /// Returns the new hot cache length.
+    #[inline(always)]
+    unsafe fn dispatch(&mut self, cid: usize, total_len: usize, request: &Request) -> usize {
+        self._rps += 1;
+        let total_len: usize = self.reserve(cid, total_len);
+        let metrics: bool = self.is_metrics_request(request);
+        // Last one on this connection? Then the client hears it from us, not from a FIN.
+        let last: bool = self.connections.get(cid).is_some_and(|connection| {
+            connection.close_after_flush || connection.requests + 1 >= KEEPALIVE_MAX_REQUESTS
+        });
+        let mut response: Response = Response::new(
+            &self.date,
+            &mut self.hot_internal_cache[total_len..],
+            &mut self.hot_json_buf,
+            &mut self.hot_data_lake,
+        );
+        if last {
+            response.set_close();
+        }
+        // Routes with limits of their own: one lookup tells the limits and runs the handler.
+        let router: Option<&Router> = self.rate_router.as_deref();
+        let matched: Option<RouteMatch> =
+            router.map(|router| router.lookup(request.method, request.path));
+        let class: u16 = match matched {
+            Some(RouteMatch::Found(_, _, _, class)) => class,
+            _ => 0,
+        };
+        // Too fast? The handler never hears about it.
+        match Self::throttle(
+            &mut self.rate_table,
+            router.and_then(|router| router.class_limits(class)),
+            class,
+            &self.rate_limits,
+            self.connections.get(cid),
+            self.nano_clock,
+        ) {
+            // Our own numbers. Way past 512 bytes, so they go out as a stream.
+            Ok(()) if metrics => {
+                let body: Bytes = Bytes::from(self.metrics.render());
+                response.stream(STATUS_SUCCESS, CONTENT_TYPE_METRICS, Some(body.len()), body);
+            }
+            Ok(()) => match (router, matched) {
+                (Some(router), Some(matched)) => router.respond(matched, request, &mut response),
+                _ => self.handler.handle(request, &mut response),
+            },
+            Err(retry_after) => {
+                self._throttled += 1;
+                let mut header: [u8; 32] = [0u8; 32];
+                let header: &[u8] = retry_after_header(&mut header, retry_after);
+                response.write_with_headers(
+                    STATUS_TOO_MANY_REQUESTS,
+                    CONTENT_TYPE_TEXT,
+                    header,
+                    b"Too Many Requests",
+                );
+            }
+        }
+        // println!("{}", String::from_utf8_lossy(&self.hot_internal_cache));
+        let len: usize = response.len();
+        let stream: Option<PendingStream> = response.take_stream();
+        self.tally.route(response.route());
+        let status: u16 = status_code(&self.hot_internal_cache[total_len..total_len + len]);
+        self.tally.status(status);
+        if self.access_log.is_active() {
+            let streamed: usize = stream.as_ref().and_then(|stream| stream.length).unwrap_or(0);
+            self.log_request(cid, request, status, (len + streamed) as u64);
+        }
+        if let Some(connection) = self.connections.get_mut(cid) {
+            // Handler only sent the head? The body follows from wideband_send, piece by piece.
+            if let Some(pending) = stream {
+                connection.stream = Some(Box::new(OutboundStream::new(pending)));
+            }
+            connection.timing.stage(self.nano_clock);
+            // `max=1000`, says the Keep-Alive header. That was the last one.
+            connection.requests += 1;
+            if connection.requests >= KEEPALIVE_MAX_REQUESTS {
+                connection.close_after_flush = true;
+            }
+        }
+        total_len + len
+    }
+
+    /// Stream is out. Whatever was pipelined behind it finally gets its turn.
+    unsafe fn finish_stream(&mut self, cid: usize) {
+        let mut carry: Vec<u8> = match self.connections.get_mut(cid) {
+            Some(connection) => {
+                connection.stream = None;
+                std::mem::take(&mut connection.carry)
+            }
+            None => return,
+        };
+        if !carry.is_empty() {
+            let data: &[u8] = std::slice::from_raw_parts(carry.as_ptr(), carry.len());
+            let consumed: usize = self.serve_requests(cid, data);
+            carry.drain(..consumed);
+        }
+        if let Some(connection) = self.connections.get_mut(cid) {
+            connection.carry = carry;
+            // Nothing pipelined left over? Then nothing's being read either.
+            if connection.carry.is_empty() && connection.chunked.is_none() {
+                connection.request_since = 0;
+            }
+        }
+        self.sync_now = true;
+    }
+
+    /// Completion of a queued send. Short write? The rest goes out next round.
+    unsafe fn queue_sent(&mut self, sq: &mut SubmissionQueue, cid: usize, result: i32) {
+        let connection: &mut Connection = match self.connections.get_mut(cid) {
+            Some(connection) if connection.send.in_flight() => connection,
+            _ => return,
+        };
+        self.sync_now = true;
+        if connection.closing {
+            // Our bytes are back. That was the last thing keeping it alive.
+            connection.send.retry();
+            self.close_connection(cid, true).unwrap_or(());
+            return;
+        }
+        if result == -libc::EINTR {
+            connection.send.retry();
+            return;
+        }
+        if result == -libc::EAGAIN {
+            connection.send.retry();
+            self.wait_writable(sq, cid);
+            return;
+        }
+        if result < 0 {
+            // Broken pipe, reset, you name it. Nobody's listening — stop talking.
+            trace!("Send failed on client {}: {}", cid, result);
+            connection.abandon();
+            return;
+        }
+        connection.send.complete(result as usize);
+        connection.last_active = self.nano_clock;
+        self.tally.bytes_out += result as u64;
+        if connection.send.flight_done() {
+            if let Some((wait, count)) = connection.timing.complete(self.nano_clock) {
+                self.tally.completed.record(wait, count);
+            }
+        }
+    }
+
+    /// Socket buffer's full. Instead of hammering it every round, sit tight until the kernel
+    /// says there's room. Can't even queue the poll? Then hammering it is.
+    unsafe fn wait_writable(&mut self, sq: &mut SubmissionQueue, cid: usize) {
+        let (fd, generation): (RawFd, u16) =
+            match (self.client_fds.get(cid), self.connections.get(cid)) {
+                (Some(fd), Some(connection)) => (*fd, connection.generation()),
+                _ => return,
+            };
+        let user_data: u64 = UserData {
+            client_id: cid as u32,
+            generation,
+            uniq_id: WRITABLE_EVENT,
+        }
+        .pack_user_data();
+        if sq.push(&poll_writable(fd, user_data)).is_ok() {
+            if let Some(connection) = self.connections.get_mut(cid) {
+                connection.awaiting_writable = true;
+            }
+        }
+    }
+
+    /// Room to write again. Or the socket is dead — either way, the send will tell.
+    unsafe fn writable(&mut self, cid: usize) {
+        let connection: &mut Connection = match self.connections.get_mut(cid) {
+            Some(connection) if connection.awaiting_writable => connection,
+            _ => return,
+        };
+        connection.awaiting_writable = false;
+        self.sync_now = true;
+        if connection.closing {
+            self.close_connection(cid, true).unwrap_or(());
+        }
+    }
+
+    /// Completion of a stream piece. The kernel tells us how much it actually took.
+    unsafe fn stream_sent(&mut self, sq: &mut SubmissionQueue, cid: usize, result: i32) {
+        let connection: &mut Connection = match self.connections.get_mut(cid) {
+            Some(connection) => connection,
+            None => return,
+        };
+        let stream: &mut OutboundStream = match connection.stream.as_deref_mut() {
+            Some(stream) if stream.in_flight => stream,
+            _ => return,
+        };
+        stream.in_flight = false;
+        self.sync_now = true;
+        if connection.closing {
+            self.close_connection(cid, true).unwrap_or(());
+            return;
+        }
+        if result == -libc::EINTR {
+            // Nothing went out. Same piece, next round.
+            return;
+        }
+        if result == -libc::EAGAIN {
+            // Same piece, once there's room for it.
+            self.wait_writable(sq, cid);
+            return;
+        }
+        if result < 0 {
+            // Client is gone, and the body it got is short. Nothing after it may follow — it
+            // would be read as more body. The recv side will bury the rest.
+            trace!("Stream send failed on client {}: {}", cid, result);
+            connection.abandon();
+            return;
+        }
+        stream.sent += result as usize;
+        connection.last_active = self.nano_clock;
+        self.tally.bytes_out += result as u64;
+        if stream.pending().is_empty() && stream.finished {
+            self.finish_stream(cid);
+        }
+    }
+
+    unsafe fn dispatch_chunked(
+        &mut self,
+        cid: usize,
+        total_len: usize,
+        head: &RequestHead,
+        decoder: &ChunkedDecoder,
+    ) -> usize {
+        let mut trailers: Headers = Headers::default();
+        match parse_headers(decoder.trailers(), 0, &mut trailers) {
+            HeadersParse::Complete(_) => {}
+            HeadersParse::TooMany => {
+                return self.reject(cid, total_len, STATUS_HEADERS_TOO_LARGE);
+            }
+            HeadersParse::Partial | HeadersParse::Invalid => {
+                return self.reject(cid, total_len, STATUS_BAD_REQUEST);
+            }
+        }
+        let mut request: Request = Request::from_head(head, decoder.body());
+        request.trailers = trailers;
+        self.dispatch(cid, total_len, &request)
+    }
+
+    /// Feed fresh bytes to a chunked body that's mid-flight. Returns what's left of `buffer`
+    /// once the body is complete — that's the next request.
+    unsafe fn continue_chunked<'a>(&mut self, cid: usize, buffer: &'a [u8]) -> &'a [u8] {
+        let pending: Option<Box<PendingChunked>> = match self.connections.get_mut(cid) {
+            Some(connection) => connection.chunked.take(),
+            None => None,
+        };
+        let mut pending: Box<PendingChunked> = match pending {
+            Some(pending) => pending,
+            None => return buffer,
+        };
+        let rejection: &[u8] = match pending.decoder.feed(buffer) {
+            ChunkedStatus::Done(used) => {
+                // Same bytes parsed fine the first time around, they'll parse fine again.
+                if let HeadParse::Complete(head) = parse_request_head(&pending.head) {
+                    let total_len: usize = self.dispatch_chunked(cid, 0, &head, &pending.decoder);
+                    self.flush_hot_cache(cid, total_len);
+                }
+                return &buffer[used..];
+            }
+            ChunkedStatus::NeedMore => {
+                if let Some(connection) = self.connections.get_mut(cid) {
+                    connection.chunked = Some(pending);
+                }
+                return &[];
+            }
+            ChunkedStatus::TooLarge => STATUS_PAYLOAD_TOO_LARGE,
+            ChunkedStatus::Invalid => STATUS_BAD_REQUEST,
+        };
+        let len: usize = self.reject(cid, 0, rejection);
+        self.flush_hot_cache(cid, len);
+        &[]
+    }
+
+    /// Reply with an error and mark the connection for shutdown once the reply is out.
+    /// Appends to the hot cache at `total_len` and returns the new length, like `dispatch`.
+    unsafe fn reject(&mut self, cid: usize, total_len: usize, status_line: &[u8]) -> usize {
+        trace!("Reject request on client {}", cid);
+        self.tally.status(status_code(status_line));
+        let total_len: usize = self.reserve(cid, total_len);
+        let mut response: Response = Response::new(
+            &self.date,
+            &mut self.hot_internal_cache[total_len..],
+            &mut self.hot_json_buf,
+            &mut self.hot_data_lake,
+        );
+        // "HTTP/1.1 400 Bad Request\r\n" -> "400 Bad Request"
+        let reason: &[u8] = &status_line[9..status_line.len() - 2];
+        response.set_close();
+        response.write(status_line, CONTENT_TYPE_TEXT, reason);
+        if let Some(connection) = self.connections.get_mut(cid) {
+            connection.timing.stage(self.nano_clock);
+            connection.close_after_flush = true;
+        }
+        total_len + response.len()
+    }
+
+    /// Make room for one more response in the hot cache: past `total_len` there has to be
+    /// `HOT_RESPONSE_RESERVE` left. If there isn't, what's there goes to the client first.
+    /// Returns where the next response starts.
+    #[inline(always)]
+    unsafe fn reserve(&mut self, cid: usize, total_len: usize) -> usize {
+        if total_len + HOT_RESPONSE_RESERVE > self.hot_internal_cache.len() {
+            self.flush_hot_cache(cid, total_len);
+            return 0;
+        }
+        total_len
+    }
+
+    #[inline(always)]
+    unsafe fn flush_hot_cache(&mut self, cid: usize, len: usize) {
+        if len == 0 {
+            return;
+        }
+        let hot_slice: &[u8] = &self.hot_internal_cache[..len];
+        if let Some(client_buffer) = self.client_out_buffers.get_mut(cid) {
+            // A SmallLake wraps around when overfilled. Tip it into the send queue before it does.
+            if client_buffer.len() + len > DATA_LAKE_SIZE {
+                if let Some(connection) = self.connections.get_mut(cid) {
+                    connection.send.push(&client_buffer.buf[..client_buffer.pos]);
+                    connection.send.push(hot_slice);
+                }
+                client_buffer.reset_pos();
+                return;
+            }
+            client_buffer.write(hot_slice.as_ptr(), hot_slice.len());
+        }
+    }
+
     unsafe fn request_reply(
         &mut self,
         flags: u32,
         user_data: UserData,
-        sq: &mut SubmissionQueue,
         result: i32,
-        submitter: &Submitter,
     ) -> io::Result<()> {
         // Extract buffer ID from upper 16 bits of flags. Not suspicious at all.
         let buf_id: u16 = (flags >> 16) as u16;
         let cid: usize = user_data.client_id as usize;
         // Sanity check: if result > buffer size, something has gone *very* wrong. Likely aliens.
-        if result > BUFFER_SIZE as i32 {
-            error!("Incorrect packet length > {}", BUFFER_SIZE);
+        if result > self.buffers.buffer_size() as i32 || buf_id as usize >= self.buffers.count() {
+            error!("Incorrect packet length > {}", self.buffers.buffer_size());
             return Ok(());
         }
+        // Ours now, until `give_back`.
+        self.buffers_in_use += 1;
         // Safely borrow the unholy slab of bytes the kernel just dumped on us.
-        let buffer: &[u8] = &self.buffers[buf_id as usize][..result as usize];
+        let buffer: &[u8] = &self.buffers.get(buf_id)[..result as usize];
 
         CURRENT_KERNEL_BUF.with(|cell| {
             cell.set(buffer.as_ptr());
         });
 
         trace!("New message incoming. Len: {}", buffer.len());
-        // Attempt to extract structured requests from the raw chaos.
-        let requests: ([RequestEntry; 50], usize) = parse_http_methods_paths(buffer);
-        // Begin preparing a response. Fast-path for success and not-so-fast for "not found".
-        let mut total_len: usize = 0;
-        for index in 0..requests.1 {
-            self._rps += 1;
-            let request: &RequestEntry = requests.0.get(index).unwrap();
-            let len: usize = Self::handler(
-                request.0,
-                request.1,
-                &self.date,
-                &mut self.hot_internal_cache[total_len..],
-                &mut self.hot_json_buf,
-                &mut self.hot_data_lake,
-            );
-            // println!("{}", String::from_utf8_lossy(&self.hot_internal_cache));
-            total_len += len;
+        self.tally.bytes_in += buffer.len() as u64;
+        // Cut the buffer loose from `self`: it stays put until we hand it back to the kernel below.
+        let buffer: &[u8] = std::slice::from_raw_parts(buffer.as_ptr(), buffer.len());
+        // Already said goodbye to this one. Whatever else it has to say — we're not listening.
+        let closing: bool = self
+            .connections
+            .get(cid)
+            .is_none_or(|connection| connection.close_after_flush);
+        // A chunked body in flight eats first.
+        let buffer: &[u8] = if closing {
+            &[]
+        } else {
+            self.continue_chunked(cid, buffer)
+        };
+        // Leftovers from the previous round go first.
+        let (mut carry, requests): (Vec<u8>, u32) = match self.connections.get_mut(cid) {
+            Some(connection) => (std::mem::take(&mut connection.carry), connection.requests),
+            None => (Vec::new(), 0),
+        };
+        if carry.is_empty() {
+            // Fast path: parse right out of the kernel buffer, copy only the unfinished tail.
+            let consumed: usize = self.serve_requests(cid, buffer);
+            carry.extend_from_slice(&buffer[consumed..]);
+        } else {
+            // Slow path: glue the new bytes onto the leftovers and try again.
+            carry.extend_from_slice(buffer);
+            let data: &[u8] = std::slice::from_raw_parts(carry.as_ptr(), carry.len());
+            let consumed: usize = self.serve_requests(cid, data);
+            carry.drain(..consumed);
+        }
+        if let Some(connection) = self.connections.get_mut(cid) {
+            connection.carry = carry;
+            connection.last_active = self.nano_clock;
+            // Still reading a request? Its clock starts with its first bytes — that's now, unless
+            // it's the same unfinished request as last time.
+            if connection.carry.is_empty() && connection.chunked.is_none() {
+                connection.request_since = 0;
+            } else if connection.request_since == 0 || connection.requests != requests {
+                connection.request_since = self.nano_clock;
+            }
         }
-        // Fire the prepared response payload into the client's output buffer.
-        let hot_slice: &[u8] = &self.hot_internal_cache[..total_len];
-        let client_buffer: &mut SmallLake<DATA_LAKE_SIZE> =
-            self.client_out_buffers.get_unchecked_mut(cid);
-        client_buffer.write(hot_slice.as_ptr(), hot_slice.len());
         // Flag for sync: this shall be pushed soon.
         self.sync_now = true;
-        // Return buffer to "available" list. Just not yet — batching is everything.
-        self.released_buffers.push(buf_id);
         // And now, the cursed part:
         if self.ub_kernel_dma {
             // Check the byte at the tail of the buffer. If it’s 0, assume "safe to shift".
-            let buf: &mut [u8; BUFFER_SIZE] = &mut self.buffers[buf_id as usize];
-            if buf[BUFFER_SIZE - 1] == 0 {
+            let buf: &mut [u8] = self.buffers.get_mut(buf_id);
+            if buf[buf.len() - 1] == 0 {
                 shift_ub_inplace(buf, result as usize);
             }
         }
-        // If we’ve hoarded enough buffers, release the Kraken.
-        let part: f64 = BUFFERS_COUNT as f64 / 1.5;
-        if self.released_buffers.len() == part as usize {
-            self.release_buffers(sq, submitter)?;
-        }
+        self.give_back(buf_id);
         Ok(())
     }
+
+    /// Done with a kernel buffer. The ring takes it back on the spot; old kernels get it at the
+    /// end of the round.
+    #[inline(always)]
+    unsafe fn give_back(&mut self, buf_id: u16) {
+        if self.buffers.has_ring() {
+            self.buffers.recycle(buf_id);
+            self.buffers_in_use -= 1;
+        } else {
+            self.released_buffers.push(buf_id);
+        }
+    }
     unsafe fn close_connection(&mut self, client_id: usize, force: bool) -> io::Result<()> {
         // Let the logs know this poor soul is being disconnected.
         trace!("FD closed for client ID: {client_id}");
-        // Default to a cursed value. If we use this, something has gone terribly wrong.
-        let mut cfd: RawFd = 0xFF;
+        // Nobody. If that's still who we have at the end, there's nothing to close.
+        let mut cfd: RawFd = -1;
         if !force {
             // If not forced, we’re just politely checking whether the client still exists.
             let fd: Option<RawFd> = self.client_fds.get(client_id).cloned();
@@ -481,7 +1134,21 @@ impl Server {
                 cfd = fd;
             }
         } else {
-            // Emergency disconnect. No questions asked.
+            // Emergency disconnect. Well — one question: is the kernel still reading our bytes?
+            // Then they stay put. The socket goes down now, so the send gives up quickly, and its
+            // completion comes back here to finish the job.
+            if let Some(connection) = self.connections.get_mut(client_id) {
+                if connection.sending() {
+                    if !connection.closing {
+                        trace!("Closing connection with ID {} once its send is back", client_id);
+                        connection.closing = true;
+                        if let Some(fd) = self.client_fds.get(client_id) {
+                            libc::shutdown(*fd, libc::SHUT_RDWR);
+                        }
+                    }
+                    return Ok(());
+                }
+            }
             trace!("Closing connection with ID {} FORCED", client_id);
             // Yank the file descriptor from the pool of the living.
             if let Some(fd) = self.client_fds.remove(client_id) {
@@ -489,6 +1156,16 @@ impl Server {
             }
             // Also delete their precious outbound buffer. We’re done being nice.
             self.client_out_buffers.remove(client_id);
+            // And give their seat back.
+            if let Some(connection) = self.connections.remove(client_id) {
+                self.release(connection.peer.filter(|_| connection.peer_counted));
+                // The id too. Whatever the kernel still has in the works for it carries the old
+                // generation and gets ignored.
+                self.free_ids.push(client_id);
+            }
+        }
+        if cfd < 0 {
+            return Ok(());
         }
         // If someone tries to close STDIN — we say no. Even if we're wild, we're not *that* wild.
         if cfd == 0 {
@@ -511,6 +1188,35 @@ impl Server {
         let user: UserData = UserData::unpack_user_data(user_data);
         let client_id: usize = user.client_id as usize;
 
+        // A ghost: the connection this was meant for is gone, its id maybe someone else's by now.
+        // All we want from it is the buffer, if it brought one.
+        let current: bool = self
+            .connections
+            .get(client_id)
+            .is_some_and(|connection| connection.generation() == user.generation);
+        if !current {
+            trace!("Stale completion {} for client {}", result, client_id);
+            if result > 0 && cqueue::buffer_select(flags).is_some() {
+                self.buffers_in_use += 1;
+                self.give_back((flags >> 16) as u16);
+            }
+            return Ok(());
+        }
+
+        // Sends keep their own books — even EAGAIN means something there.
+        if user.uniq_id == SEND_EVENT {
+            self.queue_sent(sq, client_id, result);
+            return Ok(());
+        }
+        if user.uniq_id == STREAM_SEND {
+            self.stream_sent(sq, client_id, result);
+            return Ok(());
+        }
+        if user.uniq_id == WRITABLE_EVENT {
+            self.writable(client_id);
+            return Ok(());
+        }
+
         // If the kernel gives us nothing or says "bad fd" — pretend we didn’t see anything.
         if result == -libc::EAGAIN || result == -libc::EBADF {
             return Ok(());
@@ -519,7 +1225,10 @@ impl Server {
         // Classic out-of-buffers panic. Don’t scream — just free some memory and pray.
         if result == -ENOBUFS {
             error!("Buffers end! Server feel bad :((((");
+            self.tally.enobufs += 1;
             self.release_buffers(sq, submitter)?;
+            // ENOBUFS ends a multishot recv. Re-arm it, or this client never gets heard again.
+            self.rearm_recv(sq, client_id, user_data);
             return Ok(());
         }
 
@@ -528,9 +1237,9 @@ impl Server {
             return Ok(());
         }
 
-        // Gracefully close connection if result is 0 (client closed) and it’s not a send op.
+        // Gracefully close connection if result is 0 (client closed).
         // Or if the kernel hits us with a connection reset slap.
-        if result == 0 && user.uniq_id != 0xCCC || result == -libc::ECONNRESET {
+        if result == 0 || result == -libc::ECONNRESET {
             trace!("Close CONN {} on client {} RESET", result, client_id);
             self.close_connection(client_id, true)?;
             return Ok(());
@@ -548,14 +1257,13 @@ impl Server {
             return Ok(());
         }
 
-        // If the result came from a send operation, do nothing. We don’t celebrate sending.
-        if user.uniq_id == 0xCCC {
-            return Ok(());
-        }
-
         // Otherwise, process the actual request and build a majestic reply.
         if result > 0 {
-            self.request_reply(flags, user, sq, result, submitter)?;
+            self.request_reply(flags, user, result)?;
+            // Single-shot recv is done after one buffer. A multishot one can run out too — no MORE flag.
+            if !cqueue::more(flags) {
+                self.rearm_recv(sq, client_id, user_data);
+            }
         }
         Ok(())
     }
@@ -578,7 +1286,30 @@ impl Server {
         // Now for the exciting part: routing this mysterious event to its handler.
         match user_data {
             // The birth of a connection: someone dared to connect to us.
-            CODE_ACCEPT => self.process_entry_accept(&mut sq, result)?,
+            CODE_ACCEPT => self.process_entry_accept(&mut sq, result, true)?,
+            // Same, but from down the hall — a Unix socket.
+            CODE_ACCEPT_UNIX => self.process_entry_accept(&mut sq, result, false)?,
+            // Old kernel, one accept at a time. Order the next one before serving this one.
+            user_data
+                if (CODE_ACCEPT_ONCE..CODE_ACCEPT_ONCE + self.listener_fds.len() as u64)
+                    .contains(&user_data) =>
+            {
+                let index: usize = (user_data - CODE_ACCEPT_ONCE) as usize;
+                if !self.draining {
+                    self.arm_accept(sq, index);
+                }
+                let tcp: bool = !is_unix_socket(self.listener_fds[index]);
+                self.process_entry_accept(&mut sq, result, tcp)?
+            }
+            // Someone upstairs wants us gone. Stop taking guests, finish the plates.
+            CODE_SHUTDOWN => self.begin_drain(&mut sq),
+            // Drain took too long. Whoever is still here gets cut off.
+            CODE_DRAIN_TIMEOUT => self.drain_expired = true,
+            // Tick. The loop woke up, that was the point. Wind the clock again.
+            CODE_TICK => {
+                sq.push(&timeout(&self.tick_ts, CODE_TICK)).unwrap_or(());
+                self.sync_now = true;
+            }
             // The main pipeline: recv, poll, ubdma, send – everything that happens after connect.
             user_data if user_data >= REQ_RESP_OFFSET => {
                 self.process_entry_response(user_data, result, flags, sq, submitter)?
@@ -590,69 +1321,440 @@ impl Server {
         };
         Ok(())
     }
-    fn reserve_writes_buffer(&mut self, reserve: usize) {
-        trace!("Reserve write buffer");
-        for _ in 0..reserve {
-            self.client_fds.push(0xFFFF);
+    /// Stop accepting and arm the drain deadline. Connections are wound down by `drain_connections`.
+    unsafe fn begin_drain(&mut self, sq: &mut SubmissionQueue) {
+        if self.draining {
+            return;
+        }
+        info!(
+            "Shutdown requested. Draining {} connections (timeout {:?})",
+            self.connections.num_elements(),
+            self.drain_timeout
+        );
+        self.draining = true;
+        // Call off our own accepts first. Unix listeners don't care for SHUT_RD, so for them
+        // this is the only thing that works.
+        if self.caps.multishot_accept {
+            sq.push(&cancel_accepts(CODE_ACCEPT)).unwrap_or(());
+            sq.push(&cancel_accepts(CODE_ACCEPT_UNIX)).unwrap_or(());
+        } else {
+            // No cancel-all on these kernels. One cancel per pending accept.
+            for index in 0..self.listener_fds.len() as u64 {
+                for _ in 0..self.accept_multiplicator {
+                    sq.push(&cancel(CODE_ACCEPT_ONCE + index)).unwrap_or(());
+                }
+            }
         }
+        // After a handoff the sockets live on in the new process. Shutting them down would
+        // shut them down over there too — so that's where we stop.
+        if !self.handed_off.load(Ordering::Relaxed) {
+            // Shutting a TCP listener down stops the kernel from queueing new connections for
+            // it. The other workers' sockets take over until they close too.
+            for fd in self.listener_fds.iter() {
+                libc::shutdown(*fd, libc::SHUT_RD);
+            }
+        }
+        self.drain_ts = Timespec::new()
+            .sec(self.drain_timeout.as_secs())
+            .nsec(self.drain_timeout.subsec_nanos());
+        sq.push(&timeout(&self.drain_ts, CODE_DRAIN_TIMEOUT))
+            .unwrap_or(());
+        self.sync_now = true;
     }
-    unsafe fn sq_poll(
-        &mut self,
-        listener: TcpListener,
-        sqpoll_idle: u32,
-        affinity: u32,
-    ) -> io::Result<()> {
-        // Welcome to the beating heart of the reactor — aka the Kernel Summoning Loop™.
-        // Here we prep the altar, draw the circles (Uring, SQ, CQ), and start the sacred dance of syscalls.
-        info!("Build Uring instance.");
-        let mut uring: Uring =
-            self.build_uring(self.uring_size, sqpoll_idle, affinity, self.sqpoll_enabled)?;
-        info!("Success. Split Uring.");
-        let (submitter, mut sq, mut cq): (Submitter, SubmissionQueue, CompletionQueue) =
-            uring.uring.split();
-        // Give the kernel its offering: a set of sacrificial buffers.
-        self.register_buffers(&mut sq, &submitter)?;
-        // Prepare the holy socket
-        let listener_fd: RawFd = listener.as_raw_fd();
-        attach_reuseport_cbpf(listener_fd as isize);
-        trace!("Listener fd: {listener_fd}");
-        info!("Start multi accept");
-        for i in 0..DEFAULT_ACCEPT_MULTIPLICATOR {
-            info!("    Start multi accept #{i}");
-            sq.push(&accept_multi(listener_fd)).unwrap(); // bless this socket with many accepts
+
+    /// Mark every connection that sits between requests for closing. `wideband_send` flushes
+    /// whatever they still have queued and then hangs up. Connections in the middle of a
+    /// request are left alone — they get marked on a later round, once the request is served.
+    fn drain_connections(&mut self) {
+        for (_, connection) in self.connections.iter_mut() {
+            if connection.close_after_flush {
+                continue;
+            }
+            if connection.carry.is_empty() && connection.chunked.is_none() {
+                connection.close_after_flush = true;
+                self.sync_now = true;
+            }
         }
-        info!("Submit all changes to kernel.");
-        submitter.submit()?; // hand everything over to the dark overlord
-        info!("Kernel ready");
-        // Pre-fill your outbound rocket launcher.
-        self.reserve_writes_buffer(100);
-        // Register dummy FDs — the kernel demands it. We comply. We always comply.
-        let fds: [RawFd; 1024] = [-1; 1024];
-        submitter.register_files(&fds)?;
-        self.synced = 0;
-        loop {
-            self.nano_clock = nano_timestamp();
-            self.clock = timestamp();
-            // Per-second metrics update
-            if self.clock != self.synced {
-                self.rps = self._rps;
-                self.hz = self._hz;
-                self._rps = 0;
-                self._hz = 0;
-                self.synced = self.clock;
-                // Format timestamp, broadcast our existence
+    }
+
+    /// Put `request` in this worker's access log ring. Latency counts from the request's first
+    /// bytes — an earlier round if it came in pieces, this one otherwise.
+    fn log_request(&self, cid: usize, request: &Request, status: u16, bytes: u64) {
+        let (peer, since): (Option<IpAddr>, i64) = match self.connections.get(cid) {
+            Some(connection) if connection.request_since != 0 => {
+                (connection.peer, connection.request_since)
+            }
+            Some(connection) => (connection.peer, self.nano_clock),
+            None => (None, self.nano_clock),
+        };
+        self.access_log.record(self.worker, |entry| {
+            entry.time = self.clock;
+            entry.peer = peer;
+            entry.method.set(request.method);
+            entry.path.set(request.path);
+            entry.version.set(request.version);
+            entry.status = status;
+            entry.bytes = bytes;
+            entry.latency = (self.nano_clock - since).max(0) as u64;
+            entry.referer.set(request.header(b"referer").unwrap_or_default());
+            entry.user_agent.set(request.header(b"user-agent").unwrap_or_default());
+        });
+    }
+
+    /// `GET` on the metrics path, and no admin port to take it instead. Query string or not.
+    #[inline(always)]
+    fn is_metrics_request(&self, request: &Request) -> bool {
+        let path: &'static str = match self.metrics_path {
+            Some(path) if self.metrics_addr.is_none() => path,
+            _ => return false,
+        };
+        let requested: &[u8] = match memchr::memchr(b'?', request.path) {
+            Some(q) => &request.path[..q],
+            None => request.path,
+        };
+        request.method == b"GET" && requested == path.as_bytes()
+    }
+
+    /// Check a request against the rate limits: `route`'s own (of limit `class`) if it has them,
+    /// the server's otherwise. Per-IP bucket first, then the connection's. `Err` holds
+    /// Retry-After seconds. Takes the fields it needs one by one — the caller holds a response
+    /// into the hot cache.
+    #[inline(always)]
+    fn throttle(
+        table: &mut RateTable,
+        route: Option<Limits>,
+        class: u16,
+        defaults: &Limits,
+        connection: Option<&Connection>,
+        now: i64,
+    ) -> Result<(), u32> {
+        let (class, limits): (u16, Limits) = match route {
+            Some(limits) => (class, limits),
+            None => (0, *defaults),
+        };
+        if !limits.is_active() {
+            return Ok(());
+        }
+        let connection: &Connection = match connection {
+            Some(connection) => connection,
+            None => return Ok(()),
+        };
+        if let (true, Some(ip)) = (limits.ip.is_active(), connection.peer) {
+            table.take(Subject::Ip(ip), class, limits.ip, now)?;
+        }
+        if limits.connection.is_active() {
+            table.take(Subject::Connection(connection.serial), class, limits.connection, now)?;
+        }
+        Ok(())
+    }
+
+    /// Let `client_fd` in, or not. Checks the worker's limit, then the global one, then the
+    /// per-IP one — and takes a seat in each it passes. Returns the peer address, if anyone
+    /// wants it, and whether the per-IP count took a seat for it.
+    unsafe fn admit(&mut self, client_fd: RawFd, tcp: bool) -> Option<(Option<IpAddr>, bool)> {
+        if self.max_worker_connections != 0
+            && self.connections.num_elements() >= self.max_worker_connections
+        {
+            trace!("Worker connection limit hit");
+            return None;
+        }
+        if !self.admission.enter(self.max_connections) {
+            trace!("Global connection limit hit");
+            return None;
+        }
+        let wants_ip: bool = self.max_connections_per_ip != 0
+            || self.rate_limits.ip.is_active()
+            || self.rate_router.is_some()
+            || self.access_log.is_active();
+        if !tcp || !wants_ip {
+            return Some((None, false));
+        }
+        let ip: IpAddr = match peer_ip(client_fd) {
+            Some(ip) => ip,
+            None => return Some((None, false)),
+        };
+        if self.max_connections_per_ip == 0 {
+            return Some((Some(ip), false));
+        }
+        if !self.admission.enter_ip(ip, self.max_connections_per_ip) {
+            trace!("Connection limit hit for {}", ip);
+            self.admission.leave();
+            return None;
+        }
+        Some((Some(ip), true))
+    }
+
+    /// Give back what `admit` took.
+    #[inline(always)]
+    fn release(&self, peer: Option<IpAddr>) {
+        self.admission.leave();
+        if let Some(ip) = peer {
+            self.admission.leave_ip(ip);
+        }
+    }
+
+    /// A worker instance that died with clients still on it never closes them properly.
+    /// Their seats go back here, or the limits would shrink with every crash. Their sockets get
+    /// closed too — the ring that knew about them is gone, and nobody else ever will.
+    pub(crate) fn release_all(&mut self) {
+        let peers: Vec<Option<IpAddr>> = self
+            .connections
+            .iter()
+            .map(|(_, connection)| connection.peer.filter(|_| connection.peer_counted))
+            .collect();
+        for peer in peers {
+            self.release(peer);
+        }
+        // Only seats with somebody in them. The 0xFFFF placeholders were never sockets, and STDIN
+        // never was ours either.
+        for (cid, _) in self.connections.iter() {
+            match self.client_fds.get(cid) {
+                Some(fd) if *fd > 0 => unsafe {
+                    libc::shutdown(*fd, libc::SHUT_RDWR);
+                    libc::close(*fd);
+                },
+                _ => {}
+            }
+        }
+        self.client_fds.clear();
+        self.client_out_buffers.clear();
+        self.connections.clear();
+        self.free_ids.clear();
+    }
+
+    /// Hang up on connections that overstayed. Idle keep-alive ones get a quiet goodbye, requests
+    /// that take too long to arrive (slowloris and friends) a 408 first.
+    /// Header timeout counts from the first byte of the request, body timeout from the last one.
+    unsafe fn expire_connections(&mut self) {
+        let now: i64 = self.nano_clock;
+        let keepalive: i64 = KEEPALIVE_TIMEOUT.as_nanos() as i64;
+        let header: i64 = self.header_timeout.as_nanos() as i64;
+        let body: i64 = self.body_timeout.as_nanos() as i64;
+        let send: i64 = self.send_timeout.as_nanos() as i64;
+        let mut late: Vec<usize> = Vec::new();
+        let mut stalled: Vec<usize> = Vec::new();
+        for (cid, connection) in self.connections.iter_mut() {
+            if connection.closing {
+                continue;
+            }
+            // Our turn to talk. The client's clocks don't run meanwhile — the send clock does.
+            // A client that never reads doesn't get to keep its seat, or our buffers, forever.
+            if connection.sending() || connection.stream.is_some() {
+                if send > 0 && connection.sending() && now - connection.send_since > send {
+                    stalled.push(cid);
+                    continue;
+                }
+                connection.last_active = now;
+                if connection.request_since != 0 {
+                    connection.request_since = now;
+                }
+                continue;
+            }
+            if connection.close_after_flush {
+                continue;
+            }
+            if connection.request_since == 0 {
+                if now - connection.last_active > keepalive {
+                    trace!("Keep-alive timeout on client {}", cid);
+                    connection.close_after_flush = true;
+                    self.sync_now = true;
+                }
+                continue;
+            }
+            let expired: bool = if connection.chunked.is_some() || connection.reading_body {
+                body > 0 && now - connection.last_active > body
+            } else {
+                header > 0 && now - connection.request_since > header
+            };
+            if expired {
+                late.push(cid);
+            }
+        }
+        for cid in late {
+            trace!("Request timeout on client {}", cid);
+            if let Some(connection) = self.connections.get_mut(cid) {
+                connection.carry.clear();
+                connection.chunked = None;
+            }
+            let len: usize = self.reject(cid, 0, STATUS_REQUEST_TIMEOUT);
+            self.flush_hot_cache(cid, len);
+            self.sync_now = true;
+        }
+        for cid in stalled {
+            trace!("Send timeout on client {}", cid);
+            self.close_connection(cid, true).unwrap_or(());
+        }
+    }
+
+    #[inline(always)]
+    fn set_reading_body(&mut self, cid: usize, reading_body: bool) {
+        if let Some(connection) = self.connections.get_mut(cid) {
+            connection.reading_body = reading_body;
+        }
+    }
+
+    /// Order an accept on listener `index`. Multishot if the kernel has it, otherwise a single
+    /// one that `process_entry` re-arms after every connection.
+    unsafe fn arm_accept(&mut self, sq: &mut SubmissionQueue, index: usize) {
+        let listener_fd: RawFd = self.listener_fds[index];
+        let rq: Entry = if !self.caps.multishot_accept {
+            accept_once(listener_fd, CODE_ACCEPT_ONCE + index as u64)
+        } else if is_unix_socket(listener_fd) {
+            accept_multi(listener_fd, CODE_ACCEPT_UNIX)
+        } else {
+            accept_multi(listener_fd, CODE_ACCEPT)
+        };
+        sq.push(&rq).unwrap_or(());
+        self.sync_now = true;
+    }
+
+    /// Order a recv on `client_fd`. Single-shot ones take at most a buffer's worth.
+    unsafe fn arm_recv(&mut self, sq: &mut SubmissionQueue, client_fd: RawFd, user_data: u64) {
+        let rq: Entry = if self.caps.multishot_recv {
+            recv_multi(client_fd, user_data)
+        } else {
+            recv_buf_group(client_fd, user_data, self.buffers.buffer_size() as u32)
+        };
+        sq.push(&rq).unwrap_or(());
+        self.sync_now = true;
+    }
+
+    /// Same, for a client we already know. Gone already? Then nobody's listening anyway.
+    unsafe fn rearm_recv(&mut self, sq: &mut SubmissionQueue, client_id: usize, user_data: u64) {
+        if let Some(client_fd) = self.client_fds.get(client_id) {
+            let client_fd: RawFd = *client_fd;
+            self.arm_recv(sq, client_fd, user_data);
+        }
+    }
+
+    fn reserve_writes_buffer(&mut self, reserve: usize) {
+        trace!("Reserve write buffer");
+        for _ in 0..reserve {
+            self.client_fds.push(0xFFFF);
+        }
+    }
+    unsafe fn sq_poll(
+        &mut self,
+        listeners: Vec<OwnedFd>,
+        sqpoll_idle: u32,
+        affinity: u32,
+    ) -> io::Result<()> {
+        // Welcome to the beating heart of the reactor — aka the Kernel Summoning Loop™.
+        // Here we prep the altar, draw the circles (Uring, SQ, CQ), and start the sacred dance of syscalls.
+        info!("Build Uring instance.");
+        let mut uring: Uring =
+            self.build_uring(self.uring_size, sqpoll_idle, affinity, self.sqpoll_enabled)?;
+        self.caps = uring.caps;
+        info!("Success. Split Uring.");
+        let (submitter, mut sq, mut cq): (Submitter, SubmissionQueue, CompletionQueue) =
+            uring.uring.split();
+        // Give the kernel its offering: a set of sacrificial buffers. Fresh ones for every ring.
+        self.buffers = BufferPool::new(self.buffer_size, self.buffers_count, self.huge_pages)?;
+        // Ring first. If the kernel has never heard of buffer rings, do it the old way.
+        if !self.caps.buf_ring {
+            info!("No buffer ring support. Falling back to ProvideBuffers");
+            self.register_buffers(&mut sq, &submitter)?;
+        } else if let Err(err) = self.buffers.register_ring(&submitter, BUF_GROUP) {
+            info!("No buffer ring ({}). Falling back to ProvideBuffers", err);
+            self.register_buffers(&mut sq, &submitter)?;
+        }
+        // Fresh tally. Whatever a crashed instance counted and never published is lost — one second's worth.
+        self.tally = self.metrics.tally();
+        // Token buckets, if anything is rate limited. Salted with the clock — good enough to
+        // keep anyone from guessing which addresses share a set.
+        if self.rate_limits.is_active() || self.rate_router.is_some() {
+            self.rate_table = RateTable::new(nano_timestamp() as u64);
+        }
+        // Prepare the holy sockets
+        self.listener_fds = listeners.iter().map(|listener| listener.as_raw_fd()).collect();
+        for (index, listener_fd) in self.listener_fds.clone().into_iter().enumerate() {
+            // Unix sockets have no reuseport group to steer, and their clients no TCP to tune.
+            if !is_unix_socket(listener_fd) {
+                attach_reuseport_cbpf(listener_fd as isize);
+            }
+            trace!("Listener fd: {listener_fd}");
+            info!("Start multi accept");
+            for i in 0..self.accept_multiplicator {
+                info!("    Start multi accept #{i}");
+                self.arm_accept(&mut sq, index); // bless this socket with many accepts
+            }
+        }
+        // Ear to the wall: the shutdown eventfd turns readable when it's time to go.
+        if self.shutdown_fd >= 0 {
+            sq.push(&poll_once(self.shutdown_fd, CODE_SHUTDOWN)).unwrap();
+        }
+        // And an alarm clock, so the per-second chores happen even when nobody's talking.
+        sq.push(&timeout(&self.tick_ts, CODE_TICK)).unwrap();
+        info!("Submit all changes to kernel.");
+        submitter.submit()?; // hand everything over to the dark overlord
+        info!("Kernel ready");
+        // Pre-fill your outbound rocket launcher.
+        self.reserve_writes_buffer(100);
+        // Register dummy FDs — the kernel demands it. We comply. We always comply.
+        let fds: [RawFd; 1024] = [-1; 1024];
+        submitter.register_files(&fds)?;
+        self.synced = 0;
+        loop {
+            self.nano_clock = nano_timestamp();
+            self.clock = timestamp();
+            // Per-second metrics update
+            if self.clock != self.synced {
+                self.rps = self._rps;
+                self.rejected = self._rejected;
+                self._rejected = 0;
+                self.throttled = self._throttled;
+                self._throttled = 0;
+                self.hz = self._hz;
+                self._rps = 0;
+                self._hz = 0;
+                self.synced = self.clock;
+                // Format timestamp, broadcast our existence
                 let mut date: [u8; 35] = [0; 35];
                 nano_http_date(&mut date, false);
                 self.date = date;
+                // Anyone who overstayed their welcome gets shown the door.
+                self.expire_connections();
                 let conns = self.client_fds.iter().len() - 100; // forgive the arbitrary 100, it knows what it did
+                // Last second's latencies, in microseconds. Read before publishing wipes them.
+                let micros =
+                    |histogram: &Histogram| QUANTILES.map(|q| histogram.quantile(q) / 1000);
+                let submitted: [u64; 3] = micros(&self.tally.submitted);
+                let completed: [u64; 3] = micros(&self.tally.completed);
+                // Same numbers, and then some, for whoever scrapes /metrics.
+                if self.metrics.is_active() {
+                    let second: Second = Second {
+                        rps: self.rps,
+                        hz: self.hz,
+                        cq: cq.len() as u64,
+                        sq: sq.len() as u64,
+                        connections: self.connections.num_elements() as u64,
+                        buffers: self.buffers.count() as u64,
+                        buffers_in_use: self.buffers_in_use,
+                        rejected: self.rejected as u64,
+                        throttled: self.throttled as u64,
+                    };
+                    self.metrics.publish(self.worker, &mut self.tally, &second);
+                } else {
+                    self.tally.submitted.clear();
+                    self.tally.completed.clear();
+                }
                 info!(
-                    "Server: RPS: {} kHz: {} CQ: {} SQ: {} CONNS: {} UC: {}",
+                    "Server: RPS: {} kHz: {} CQ: {} SQ: {} CONNS: {} ALL: {} REJECTED: {} THROTTLED: {} UC: {} SUBMIT µs p50/p99/p999: {}/{}/{} DONE µs p50/p99/p999: {}/{}/{}",
                     self.rps,
                     self.hz / 1000,
                     cq.len(),
                     sq.len(),
                     conns,
-                    self.universal_counter
+                    self.admission.open(),
+                    self.rejected,
+                    self.throttled,
+                    self.universal_counter,
+                    submitted[0],
+                    submitted[1],
+                    submitted[2],
+                    completed[0],
+                    completed[1],
+                    completed[2]
                 );
             }
             // If no CQE yet, try to flush outbound sends
@@ -686,6 +1788,8 @@ impl Server {
             if cq.is_empty() {
                 submitter.submit_and_wait(1)?;
                 self.io_send_busy = false;
+                // We may have slept for a while. Whatever just arrived, arrived now — not then.
+                self.nano_clock = nano_timestamp();
             } else if sq.len() >= (sq.capacity() - 4) {
                 submitter.submit()?;
             }
@@ -698,9 +1802,31 @@ impl Server {
                 self.process_entry(cqe, &mut sq, &submitter)?;
                 self._hz += 1;
             }
-            // If we’re bored (0 RPS), clean up the kitchen
-            if self.rps == 0 {
-                self.release_buffers(&mut sq, &submitter)?;
+            // Whatever the ring didn't take back goes home the slow way. No-op with a buffer ring.
+            self.release_buffers(&mut sq, &submitter)?;
+            // Closing time. Last one out turns off the lights.
+            if self.draining {
+                self.drain_connections();
+                if self.drain_expired {
+                    let stragglers: Vec<usize> = self
+                        .connections
+                        .iter()
+                        .filter(|(_, connection)| !connection.closing)
+                        .map(|(client_id, _)| client_id)
+                        .collect();
+                    if !stragglers.is_empty() {
+                        error!("Drain timeout. Closing {} connections", stragglers.len());
+                    }
+                    for client_id in stragglers {
+                        self.close_connection(client_id, true)?;
+                    }
+                }
+                // Not while the kernel still holds bytes of ours. Their sends were cut short
+                // above, so the completions aren't far off.
+                if self.connections.num_elements() == 0 {
+                    info!("Drained. Worker out.");
+                    return Ok(());
+                }
             }
         }
     }
@@ -709,24 +1835,78 @@ impl Server {
 impl Server {
     pub fn new(addr: &'static str) -> Server {
         Server {
-            addr,
+            addrs: vec![addr],
+            ipv6_only: None,
             workers: num_cpus::get().max(1) as u8,
             uring_size: DEFAULT_URING_SIZE,
             sqpoll_idle: DEFAULT_SQPOLL_IDLE,
             sqpoll_enabled: false,
             realtime: false,
             ub_kernel_dma: false,
+            handler: Arc::new(NotFound),
+            routes: Vec::new(),
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+            max_header_size: DEFAULT_MAX_HEADER_SIZE,
+            drain_timeout: DEFAULT_DRAIN_TIMEOUT,
+            header_timeout: DEFAULT_HEADER_TIMEOUT,
+            body_timeout: DEFAULT_BODY_TIMEOUT,
+            send_timeout: DEFAULT_SEND_TIMEOUT,
+            handoff_path: None,
+            unix_paths: Vec::new(),
+            unix_mode: None,
+            unix_owner: None,
+            accept_multiplicator: DEFAULT_ACCEPT_MULTIPLICATOR,
+            listen_backlog: DEFAULT_LISTEN_BACKLOG,
+            sndbuf_size: DEFAULT_SNDBUF_SIZE,
+            busy_poll: DEFAULT_BUSY_POLL,
+            buffer_size: BUFFER_SIZE,
+            buffers_count: DEFAULT_BUFFERS_COUNT,
+            huge_pages: false,
+            max_connections: 0,
+            max_worker_connections: DEFAULT_MAX_WORKER_CONNECTIONS,
+            max_connections_per_ip: 0,
+            reject_with_503: true,
+            rate_limits: Limits::default(),
+            metrics_path: None,
+            metrics_addr: None,
+            access_log_path: None,
+            access_log_format: AccessLogFormat::Common,
             client_fds: ExternStableVec::new(),
+            connections: ExternStableVec::with_capacity(u16::MAX as usize),
+            free_ids: Vec::new(),
             date: [0u8; 35],
             hot_json_buf: TachyonBuffer::<100>::default(),
             hot_data_lake: SmallLake::<512>::build(),
             sync_now: true,
-            buffers: [[0u8; BUFFER_SIZE]; BUFFERS_COUNT],
-            released_buffers: Vec::with_capacity(BUFFERS_COUNT),
+            buffers: BufferPool::empty(),
+            released_buffers: Vec::with_capacity(DEFAULT_BUFFERS_COUNT),
+            buffers_in_use: 0,
             client_out_buffers: ExternStableVec::with_capacity(u16::MAX as usize),
             hot_internal_cache: [0u8; BUFFER_SIZE * 2],
             io_send_busy: false,
             universal_counter: 0,
+            shutdown_fd: -1,
+            listener_fds: Vec::new(),
+            caps: Capabilities::default(),
+            admission: Admission::default(),
+            rate_router: None,
+            rate_table: RateTable::default(),
+            serials: 0,
+            metrics: Metrics::default(),
+            tally: Tally::default(),
+            worker: 0,
+            access_log: AccessLog::default(),
+            _throttled: 0,
+            throttled: 0,
+            _rejected: 0,
+            rejected: 0,
+            handed_off: Arc::new(AtomicBool::new(false)),
+            draining: false,
+            drain_expired: false,
+            drain_ts: Timespec::new(),
+            tick_ts: Timespec::new()
+                .sec(TICK_INTERVAL.as_secs())
+                .nsec(TICK_INTERVAL.subsec_nanos()),
             nano_clock: unsafe { nano_timestamp() },
             clock: unsafe { timestamp() },
             last_sync_time: 0,
@@ -762,6 +1942,10 @@ impl Server {
         self
     }
     #[inline(always)]
+    pub fn get_uring_size(&self) -> u32 {
+        self.uring_size
+    }
+    #[inline(always)]
     pub fn set_uring_size(&mut self, uring_size: u32) -> &mut Self {
         self.uring_size = uring_size;
         self
@@ -790,15 +1974,471 @@ impl Server {
         self
     }
     #[inline(always)]
+    pub fn get_max_body_size(&self) -> usize {
+        self.max_body_size
+    }
+    /// Upper bound for request bodies, `Content-Length` and chunked alike. Anything bigger gets a 413.
+    #[inline(always)]
+    pub fn set_max_body_size(&mut self, max_body_size: usize) -> &mut Self {
+        self.max_body_size = max_body_size;
+        self
+    }
+    #[inline(always)]
+    pub fn get_max_header_size(&self) -> usize {
+        self.max_header_size
+    }
+    /// Upper bound for a request line plus headers. A head may arrive split across any number
+    /// of recv buffers; one that's still incomplete past this many bytes gets a 431.
+    #[inline(always)]
+    pub fn set_max_header_size(&mut self, max_header_size: usize) -> &mut Self {
+        self.max_header_size = max_header_size;
+        self
+    }
+    #[inline(always)]
+    pub fn get_drain_timeout(&self) -> Duration {
+        self.drain_timeout
+    }
+    /// How long in-flight connections get to finish after SIGTERM/SIGINT.
+    /// Whoever is still around after that gets cut off.
+    #[inline(always)]
+    pub fn set_drain_timeout(&mut self, drain_timeout: Duration) -> &mut Self {
+        self.drain_timeout = drain_timeout;
+        self
+    }
+    #[inline(always)]
+    pub fn get_header_timeout(&self) -> Duration {
+        self.header_timeout
+    }
+    /// How long a client gets to send a complete request head, counted from its first byte.
+    /// Too slow and it gets a 408. Zero turns it off.
+    #[inline(always)]
+    pub fn set_header_timeout(&mut self, header_timeout: Duration) -> &mut Self {
+        self.header_timeout = header_timeout;
+        self
+    }
+    #[inline(always)]
+    pub fn get_body_timeout(&self) -> Duration {
+        self.body_timeout
+    }
+    /// How long a request body may go without a single new byte. 408 after that. Zero turns it off.
+    #[inline(always)]
+    pub fn set_body_timeout(&mut self, body_timeout: Duration) -> &mut Self {
+        self.body_timeout = body_timeout;
+        self
+    }
+    #[inline(always)]
+    pub fn get_send_timeout(&self) -> Duration {
+        self.send_timeout
+    }
+    /// How long a response may sit in the kernel without a single byte getting through — the
+    /// client isn't reading. The connection is cut after that. Zero turns it off.
+    #[inline(always)]
+    pub fn set_send_timeout(&mut self, send_timeout: Duration) -> &mut Self {
+        self.send_timeout = send_timeout;
+        self
+    }
+    #[inline(always)]
+    pub fn get_addrs(&self) -> &[&'static str] {
+        &self.addrs
+    }
+    /// Listen on one more address, on top of the one given to `new`. Every address gets its
+    /// own socket and its own accepts in every worker.
+    #[inline(always)]
+    pub fn listen(&mut self, addr: &'static str) -> &mut Self {
+        self.addrs.push(addr);
+        self
+    }
+    /// Replace every TCP address, the one given to `new` included. Empty is fine as long as
+    /// there's a Unix socket to listen on.
+    #[inline(always)]
+    pub fn set_addrs(&mut self, addrs: Vec<&'static str>) -> &mut Self {
+        self.addrs = addrs;
+        self
+    }
+    #[inline(always)]
+    pub fn get_ipv6_only(&self) -> Option<bool> {
+        self.ipv6_only
+    }
+    /// `IPV6_V6ONLY` for IPv6 listeners. Unset means the system default — on most Linux boxes
+    /// that's dual-stack, so `[::]:8080` takes IPv4 too. Listening on both `0.0.0.0:8080` and
+    /// `[::]:8080`? Set this to `true`, or the two will fight over the port.
+    #[inline(always)]
+    pub fn set_ipv6_only(&mut self, ipv6_only: bool) -> &mut Self {
+        self.ipv6_only = Some(ipv6_only);
+        self
+    }
+    #[inline(always)]
+    pub fn get_handoff_path(&self) -> Option<&'static str> {
+        self.handoff_path
+    }
+    /// Unix socket for zero-downtime restarts. On start we ask whoever listens there for its
+    /// listening sockets; then we listen there ourselves. Start the new binary with the same
+    /// path and the old one hands everything over and drains once the new one is up.
+    #[inline(always)]
+    pub fn set_handoff_path(&mut self, path: &'static str) -> &mut Self {
+        self.handoff_path = Some(path);
+        self
+    }
+    #[inline(always)]
+    pub fn get_unix_paths(&self) -> &[&'static str] {
+        &self.unix_paths
+    }
+    /// Listen on a Unix domain socket at `path` as well, e.g. behind a local reverse proxy.
+    /// A stale socket file from a crashed run is cleaned up; a live one is an error.
+    /// The file is removed again on shutdown.
+    #[inline(always)]
+    pub fn listen_unix(&mut self, path: &'static str) -> &mut Self {
+        self.unix_paths.push(path);
+        self
+    }
+    #[inline(always)]
+    pub fn get_unix_mode(&self) -> Option<u32> {
+        self.unix_mode
+    }
+    /// Permission bits for the Unix socket files, e.g. `0o660`. Unset means whatever the umask says.
+    #[inline(always)]
+    pub fn set_unix_mode(&mut self, mode: u32) -> &mut Self {
+        self.unix_mode = Some(mode);
+        self
+    }
+    #[inline(always)]
+    pub fn get_unix_owner(&self) -> Option<(u32, u32)> {
+        self.unix_owner
+    }
+    /// Owner and group for the Unix socket files. Changing the owner needs root (or `CAP_CHOWN`).
+    #[inline(always)]
+    pub fn set_unix_owner(&mut self, uid: u32, gid: u32) -> &mut Self {
+        self.unix_owner = Some((uid, gid));
+        self
+    }
+    #[inline(always)]
+    pub fn get_accept_multiplicator(&self) -> u8 {
+        self.accept_multiplicator
+    }
+    /// How many multishot accepts every worker keeps armed per listener.
+    #[inline(always)]
+    pub fn set_accept_multiplicator(&mut self, accept_multiplicator: u8) -> &mut Self {
+        self.accept_multiplicator = accept_multiplicator;
+        self
+    }
+    #[inline(always)]
+    pub fn get_listen_backlog(&self) -> i32 {
+        self.listen_backlog
+    }
+    /// `listen()` backlog for every socket we bind. The kernel quietly caps it at `net.core.somaxconn`.
+    #[inline(always)]
+    pub fn set_listen_backlog(&mut self, listen_backlog: i32) -> &mut Self {
+        self.listen_backlog = listen_backlog;
+        self
+    }
+    #[inline(always)]
+    pub fn get_sndbuf_size(&self) -> i32 {
+        self.sndbuf_size
+    }
+    /// `SO_SNDBUF` for client sockets, in bytes. Capped by `net.core.wmem_max`, then doubled by the kernel.
+    #[inline(always)]
+    pub fn set_sndbuf_size(&mut self, sndbuf_size: i32) -> &mut Self {
+        self.sndbuf_size = sndbuf_size;
+        self
+    }
+    #[inline(always)]
+    pub fn get_busy_poll(&self) -> i32 {
+        self.busy_poll
+    }
+    /// `SO_BUSY_POLL` for TCP clients, in microseconds. 0 leaves it off.
+    #[inline(always)]
+    pub fn set_busy_poll(&mut self, busy_poll: i32) -> &mut Self {
+        self.busy_poll = busy_poll;
+        self
+    }
+    #[inline(always)]
+    pub fn get_buffer_size(&self) -> usize {
+        self.buffer_size
+    }
+    /// Size of every recv buffer, in bytes. A multiple of 64. The bigger, the fewer recv
+    /// completions per large request — and the more memory parked per buffer.
+    #[inline(always)]
+    pub fn set_buffer_size(&mut self, buffer_size: usize) -> &mut Self {
+        self.buffer_size = buffer_size;
+        self
+    }
+    #[inline(always)]
+    pub fn get_buffers_count(&self) -> usize {
+        self.buffers_count
+    }
+    /// Recv buffers per worker. Run out and the kernel answers `ENOBUFS` until some come back.
+    #[inline(always)]
+    pub fn set_buffers_count(&mut self, buffers_count: usize) -> &mut Self {
+        self.buffers_count = buffers_count;
+        self
+    }
+    #[inline(always)]
+    pub fn get_huge_pages(&self) -> bool {
+        self.huge_pages
+    }
+    /// Back the buffer pool with huge pages: reserved ones (`vm.nr_hugepages`) if there are
+    /// any, transparent ones otherwise. Fewer TLB misses when the pool is big.
+    #[inline(always)]
+    pub fn set_huge_pages(&mut self, enabled: bool) -> &mut Self {
+        self.huge_pages = enabled;
+        self
+    }
+    #[inline(always)]
+    pub fn get_max_connections(&self) -> usize {
+        self.max_connections
+    }
+    /// Open connections allowed across all workers. One more and it's turned away. 0 = no limit.
+    #[inline(always)]
+    pub fn set_max_connections(&mut self, max_connections: usize) -> &mut Self {
+        self.max_connections = max_connections;
+        self
+    }
+    #[inline(always)]
+    pub fn get_max_worker_connections(&self) -> usize {
+        self.max_worker_connections
+    }
+    /// Same, for a single worker. 0 = no limit.
+    #[inline(always)]
+    pub fn set_max_worker_connections(&mut self, max_worker_connections: usize) -> &mut Self {
+        self.max_worker_connections = max_worker_connections;
+        self
+    }
+    #[inline(always)]
+    pub fn get_max_connections_per_ip(&self) -> u32 {
+        self.max_connections_per_ip
+    }
+    /// Open connections allowed from one source address, all workers together. IPv4-mapped
+    /// IPv6 addresses count as the IPv4 ones. Unix socket clients don't count. 0 = no limit.
+    #[inline(always)]
+    pub fn set_max_connections_per_ip(&mut self, max_connections_per_ip: u32) -> &mut Self {
+        self.max_connections_per_ip = max_connections_per_ip;
+        self
+    }
+    #[inline(always)]
+    pub fn get_reject_with_503(&self) -> bool {
+        self.reject_with_503
+    }
+    /// Whether a client turned away by a connection limit gets a 503 before the door shuts.
+    /// Best effort: a single non-blocking send. Off means it just gets closed.
+    #[inline(always)]
+    pub fn set_reject_with_503(&mut self, enabled: bool) -> &mut Self {
+        self.reject_with_503 = enabled;
+        self
+    }
+    #[inline(always)]
+    pub fn get_metrics_path(&self) -> Option<&'static str> {
+        self.metrics_path
+    }
+    /// Serve Prometheus metrics at `path`, e.g. `/metrics`. Requests there never reach the
+    /// handler — or the rate limits. Off by default.
+    #[inline(always)]
+    pub fn set_metrics_path(&mut self, path: &'static str) -> &mut Self {
+        self.metrics_path = Some(path);
+        self
+    }
+    #[inline(always)]
+    pub fn get_metrics_addr(&self) -> Option<&'static str> {
+        self.metrics_addr
+    }
+    /// Serve the metrics on an admin port of their own instead, e.g. `127.0.0.1:9090`.
+    /// Path is `metrics_path`, or `/metrics` if that's not set. The public port won't have them.
+    #[inline(always)]
+    pub fn set_metrics_addr(&mut self, addr: &'static str) -> &mut Self {
+        self.metrics_addr = Some(addr);
+        self
+    }
+    #[inline(always)]
+    pub fn get_access_log(&self) -> Option<&'static str> {
+        self.access_log_path
+    }
+    /// Log every served request to `path`, appending — or to stdout, with `-`. Workers never
+    /// wait for it: entries go through a ring per worker and a thread of its own writes them.
+    /// Can't keep up? Entries get dropped, and the error log says how many.
+    #[inline(always)]
+    pub fn set_access_log(&mut self, path: &'static str) -> &mut Self {
+        self.access_log_path = Some(path);
+        self
+    }
+    #[inline(always)]
+    pub fn get_access_log_format(&self) -> AccessLogFormat {
+        self.access_log_format
+    }
+    #[inline(always)]
+    pub fn set_access_log_format(&mut self, format: AccessLogFormat) -> &mut Self {
+        self.access_log_format = format;
+        self
+    }
+    #[inline(always)]
+    pub fn set_handler<H: Handler + 'static>(&mut self, handler: H) -> &mut Self {
+        self.handler = Arc::new(handler);
+        self
+    }
+    /// Declare a route. `path` supports exact segments, `:param` segments and a trailing
+    /// `*` / `*name` wildcard. Routes are compiled once in `build()` and replace any handler
+    /// set via `set_handler`.
+    #[inline(always)]
+    pub fn route(&mut self, method: &'static str, path: &'static str, handler: RouteFn) -> &mut Self {
+        self.routes.push(Route {
+            method,
+            path,
+            handler,
+            limits: None,
+        });
+        self
+    }
+    /// Same as `route`, with rate limits of its own instead of the server-wide ones.
+    /// Buckets are per route: requests here don't drain the server-wide ones, or the other way round.
+    #[inline(always)]
+    pub fn route_limited(
+        &mut self,
+        method: &'static str,
+        path: &'static str,
+        handler: RouteFn,
+        limits: Limits,
+    ) -> &mut Self {
+        self.routes.push(Route {
+            method,
+            path,
+            handler,
+            limits: Some(limits),
+        });
+        self
+    }
+    #[inline(always)]
+    pub fn get_rate_limit(&self) -> RateLimit {
+        self.rate_limits.connection
+    }
+    /// Requests per second a single connection may send, token bucket style. Past that it
+    /// gets a 429 with Retry-After. Checked before the handler ever sees the request.
+    #[inline(always)]
+    pub fn set_rate_limit(&mut self, limit: RateLimit) -> &mut Self {
+        self.rate_limits.connection = limit;
+        self
+    }
+    #[inline(always)]
+    pub fn get_ip_rate_limit(&self) -> RateLimit {
+        self.rate_limits.ip
+    }
+    /// Same, for all connections from one source address together. Per worker: a client
+    /// spread over several workers gets a bucket in each.
+    #[inline(always)]
+    pub fn set_ip_rate_limit(&mut self, limit: RateLimit) -> &mut Self {
+        self.rate_limits.ip = limit;
+        self
+    }
+    #[inline(always)]
     pub fn build(&mut self) -> Self {
+        if !self.routes.is_empty() {
+            // A broken route table is a programming error. Die loudly before binding anything.
+            let router: Arc<Router> = Arc::new(
+                Router::compile(&self.routes)
+                    .unwrap_or_else(|err| panic!("Invalid route table: {err}")),
+            );
+            self.rate_router = router.has_limits().then(|| router.clone());
+            self.handler = router;
+        }
         self.clone()
     }
+    /// Check the settings before anything gets bound. `run` calls this too, so a bad combination
+    /// never makes it past startup — but calling it early gets you the error before the logs do.
+    pub fn validate(&self) -> io::Result<()> {
+        let invalid = |reason: String| Err(io::Error::new(io::ErrorKind::InvalidInput, reason));
+        if self.realtime && self.ub_kernel_dma {
+            return invalid("realtime and ub_kernel_dma are mutually exclusive: pick one".into());
+        }
+        let cores: usize = core_affinity::get_core_ids().map_or(0, |ids| ids.len());
+        if self.workers == 0 {
+            return invalid("workers must be at least 1".into());
+        }
+        if cores > 0 && self.workers as usize > cores {
+            return invalid(format!(
+                "workers = {}, cores available = {}: every worker is pinned to a core of its own",
+                self.workers, cores
+            ));
+        }
+        if !self.uring_size.is_power_of_two() || self.uring_size > MAX_URING_SIZE {
+            return invalid(format!(
+                "uring_size = {} must be a power of two no bigger than {}",
+                self.uring_size, MAX_URING_SIZE
+            ));
+        }
+        if self.accept_multiplicator == 0 {
+            return invalid("accept_multiplicator must be at least 1, or nobody gets accepted".into());
+        }
+        if self.listen_backlog <= 0 {
+            return invalid(format!("listen_backlog = {} must be positive", self.listen_backlog));
+        }
+        if self.sndbuf_size <= 0 {
+            return invalid(format!("sndbuf_size = {} must be positive", self.sndbuf_size));
+        }
+        if self.busy_poll < 0 {
+            return invalid(format!("busy_poll = {} can't be negative", self.busy_poll));
+        }
+        if self.buffer_size == 0 || self.buffer_size % 64 != 0 || self.buffer_size > i32::MAX as usize {
+            return invalid(format!(
+                "buffer_size = {} must be a positive multiple of 64",
+                self.buffer_size
+            ));
+        }
+        if self.buffers_count == 0 || self.buffers_count > MAX_BUFFERS_COUNT {
+            return invalid(format!(
+                "buffers_count = {} must be between 1 and {}",
+                self.buffers_count, MAX_BUFFERS_COUNT
+            ));
+        }
+        if self.max_header_size == 0 {
+            return invalid("max_header_size must be positive".into());
+        }
+        if self.addrs.is_empty() && self.unix_paths.is_empty() {
+            return invalid("nothing to listen on: no TCP address and no Unix socket".into());
+        }
+        for addr in self.addrs.iter() {
+            if let Err(err) = addr.parse::<std::net::SocketAddr>() {
+                return invalid(format!("invalid listen address {addr:?}: {err}"));
+            }
+        }
+        if let Some(mode) = self.unix_mode {
+            if mode > 0o7777 {
+                return invalid(format!("unix_mode = {mode:#o} is not a permission mode"));
+            }
+        }
+        if let Some(path) = self.metrics_path {
+            if !path.starts_with('/') {
+                return invalid(format!("metrics_path = {path:?} must start with '/'"));
+            }
+        }
+        if let Some(addr) = self.metrics_addr {
+            if let Err(err) = addr.parse::<std::net::SocketAddr>() {
+                return invalid(format!("invalid metrics address {addr:?}: {err}"));
+            }
+        }
+        Ok(())
+    }
 }
-pub fn run(server: Server) -> io::Result<()> {
-    // Check for mutually exclusive flags — UBDMA cannot run in RT-safe environments
-    if server.realtime && server.ub_kernel_dma {
-        unsafe { log_kernel_error("realtime and ub dma is incompatible.", "RT_DMA_CONFLICT") };
-        return Ok(());
+
+/// `Retry-After: <secs>\r\n`, written into `buf`. Any u32 fits.
+#[inline(always)]
+fn retry_after_header(buf: &mut [u8; 32], secs: u32) -> &[u8] {
+    let mut cursor: io::Cursor<&mut [u8]> = io::Cursor::new(&mut buf[..]);
+    let _ = write!(cursor, "Retry-After: {}\r\n", secs);
+    let len: usize = cursor.position() as usize;
+    &buf[..len]
+}
+
+/// Start the workers and block until SIGTERM or SIGINT — or until a successor takes over.
+///
+/// The signal doesn't kill anything: every worker stops accepting, finishes what's in flight,
+/// closes idle keep-alive connections and exits. `run` returns once they all have — or once
+/// the drain timeout made them. SIGTERM/SIGINT stay blocked on the calling thread while we run,
+/// so threads you spawned before `run` should block them too if they want no part of it.
+///
+/// With a handoff path set, a new process started with the same path inherits our listening
+/// sockets and we drain the same way once it reports ready. See `set_handoff_path`.
+pub fn run(mut server: Server) -> io::Result<()> {
+    // Check for mutually exclusive flags — UBDMA cannot run in RT-safe environments, and friends
+    if let Err(err) = server.validate() {
+        unsafe { log_kernel_error(&err.to_string(), "INVALID_CONFIG") };
+        return Err(err);
     }
     // Yell at the user if UBDMA is enabled (because that's what you do before summoning chaos)
     if server.ub_kernel_dma {
@@ -808,38 +2448,229 @@ pub fn run(server: Server) -> io::Result<()> {
         error!("*******************************");
         thread::sleep(Duration::from_secs(5)); // Give the user time to regret
     }
+    // Pick the SIMD flavour once, before the workers start hammering the parser.
+    init_simd_level();
+    // Block the signals before spawning anyone. Workers inherit the mask and never get interrupted.
+    let signal: ShutdownSignal = ShutdownSignal::install()?;
+    server.shutdown_fd = signal.event_fd();
+    let workers_count: usize = (server.get_workers() as usize).max(1);
+    // Metrics wanted? Then every worker gets a seat to publish into.
+    if server.metrics_path.is_some() || server.metrics_addr.is_some() {
+        server.metrics = Metrics::new(workers_count, &server.routes);
+    }
+    // Sockets from the previous generation first, then whatever systemd passed in.
+    // Otherwise, every worker binds its own.
+    let mut inherited: Option<Inherited> = match server.handoff_path {
+        Some(path) => inherit_listeners(path)?,
+        None => None,
+    };
+    let adopted: Vec<OwnedFd> = match inherited.as_mut() {
+        Some(inherited) => std::mem::take(&mut inherited.listeners),
+        None => listen_fds()?,
+    };
+    let mut shares: Vec<Vec<OwnedFd>> = (0..workers_count).map(|_| Vec::new()).collect();
+    let adopted_count: usize = adopted.len();
+    // Every adopted socket needs someone accepting on it — the kernel keeps routing
+    // connections to all of them. More sockets than workers? Some workers take two.
+    for (i, listener) in adopted.into_iter().enumerate() {
+        shares[i % workers_count].push(listener);
+    }
+    if adopted_count == 0 {
+        // Fresh start: every worker binds every address. SO_REUSEPORT lets the kernel spread
+        // connections across the workers' sockets.
+        for share in shares.iter_mut() {
+            for addr in server.addrs.iter() {
+                info!("Creating base listener on {}", addr);
+                let listener = server.build_listener(addr, server.ipv6_only, server.listen_backlog)?;
+                share.push(listener.into());
+            }
+        }
+        // A Unix socket path can only be bound once. The first worker owns it, the rest
+        // get clones below.
+        for path in server.unix_paths.iter() {
+            info!("Creating unix listener on {}", path);
+            let listener = server.build_unix_listener(
+                path,
+                server.unix_mode,
+                server.unix_owner,
+                server.listen_backlog,
+            )?;
+            shares[0].push(listener.into());
+        }
+    }
+    // These are what a successor gets. Clones below are just extra hands on the same sockets.
+    let listener_fds: Vec<RawFd> = shares
+        .iter()
+        .flatten()
+        .map(|listener| listener.as_raw_fd())
+        .collect();
+    // Fewer adopted sockets than workers: the rest share them. Several rings accepting on one
+    // socket is fine — every connection still lands in exactly one of them.
+    if adopted_count > 0 {
+        for worker in adopted_count..workers_count {
+            let listener: OwnedFd = shares[worker % adopted_count][0].try_clone()?;
+            shares[worker].push(listener);
+        }
+    } else {
+        let unix_count: usize = server.unix_paths.len();
+        let primaries: usize = shares[0].len();
+        for worker in 1..workers_count {
+            for i in primaries - unix_count..primaries {
+                let listener: OwnedFd = shares[0][i].try_clone()?;
+                shares[worker].push(listener);
+            }
+        }
+    }
+    let handoff: Option<UnixListener> = match server.handoff_path {
+        Some(path) => Some(bind_handoff(path)?),
+        None => None,
+    };
+    // The access log gets a ring per worker and a thread to empty them.
+    let log_writer: Option<thread::JoinHandle<()>> = match server.access_log_path {
+        Some(path) => {
+            info!("Access log: {} ({:?})", path, server.access_log_format);
+            server.access_log = AccessLog::new(workers_count);
+            Some(server.access_log.start(path, server.access_log_format)?)
+        }
+        None => None,
+    };
+    // The admin port. A plain blocking thread — scrapes come every few seconds, not by the million.
+    if let Some(addr) = server.metrics_addr {
+        info!("Serving metrics on {}", addr);
+        let listener: TcpListener = TcpListener::bind(addr)?;
+        let metrics: Metrics = server.metrics.clone();
+        let path: &'static str = server.metrics_path.unwrap_or("/metrics");
+        thread::Builder::new()
+            .name("Tachyon-admin".into())
+            .spawn(move || serve_metrics(listener, metrics, path))?;
+    }
+    let mut workers: Vec<thread::JoinHandle<()>> = Vec::with_capacity(workers_count);
     // Spawn workers, bind to dedicated cores with max thread priority
-    for thread in 0..server.get_workers() {
+    for (thread, share) in shares.into_iter().enumerate() {
         let core_ids: Vec<CoreId> = core_affinity::get_core_ids().unwrap();
         info!("Thread {} starting", thread);
-        let server = server.clone(); // yes, cloning entire server per-thread
-        thread::Builder::new()
+        let mut server = server.clone(); // yes, cloning entire server per-thread
+        server.worker = thread;
+        let worker = thread::Builder::new()
             .name(format!("Tachyon-{}", thread)) // Tachyon — fast, radioactive, and very real
-            .stack_size(BUFFER_SIZE * BUFFERS_COUNT * 10) // big boy stack for big boy packets
+            .stack_size(WORKER_STACK_SIZE) // big boy stack for big boy servers
             .spawn_with_priority(ThreadPriority::Max, move |_| unsafe {
-                let res = core_affinity::set_for_current(core_ids[thread as usize]);
+                let res = core_affinity::set_for_current(core_ids[thread]);
                 if !res {
                     error!("Failed to set core affinity");
                 } else {
-                    info!(
-                        "Core {} set affinity to {:?}",
-                        thread, core_ids[thread as usize]
-                    );
+                    info!("Core {} set affinity to {:?}", thread, core_ids[thread]);
                 }
-                // The thread lives forever, unless panic takes it to Valhalla
+                // The thread lives until shutdown, unless panic takes it to Valhalla first.
+                // A clean return from `sq_poll` means we drained. Errors get a fresh instance —
+                // and if the shutdown already fired, that one drains on its first breath.
                 loop {
                     info!("Creating server instance");
                     let mut instance: Server = server.clone();
-                    info!("Creating base listener");
-                    let listener: TcpListener = instance.build_listener(instance.addr).unwrap();
-                    if let Err(e) = instance.sq_poll(listener, server.sqpoll_idle, thread as u32) {
-                        error!("worker error: {e}");
+                    // The originals stay here, so a crashed instance can't take a socket with it.
+                    let listeners: Vec<OwnedFd> = share
+                        .iter()
+                        .map(|listener| listener.try_clone())
+                        .collect::<io::Result<_>>()
+                        .unwrap();
+                    match instance.sq_poll(listeners, server.sqpoll_idle, thread as u32) {
+                        Ok(()) => break,
+                        Err(e) => {
+                            error!("worker error: {e}");
+                            instance.release_all();
+                        }
                     }
                 }
             })?;
+        workers.push(worker);
+    }
+    // We're up. If someone handed us their sockets, they can go home now.
+    if let Some(inherited) = inherited {
+        inherited.ready()?;
+    }
+    // The predecessor is draining. Only now is the path ours — the next upgrade finds us there.
+    if let Some(path) = server.handoff_path {
+        publish_handoff(path)?;
     }
-    // Main thread parks itself like a good coordinator
+    // Running under a `Type=notify` unit? MAINPID matters after a handoff: the unit's main
+    // process is us now, not whoever systemd started.
+    let notifier: Option<Notifier> = Notifier::from_env();
+    let watchdog: Option<Duration> = notifier.as_ref().and(watchdog_interval());
+    if let Some(notifier) = notifier.as_ref() {
+        notifier.notify(&format!("READY=1\nMAINPID={}", std::process::id()));
+    }
+    // Mid-upgrade: the successor has our listeners and we're waiting to hear it's up.
+    let mut successor: Option<Successor> = None;
+    // Main thread sleeps on the signalfd like a good coordinator
     loop {
-        thread::park();
+        // A successor on the line gets listened to instead of the handoff socket — one at a time.
+        // Its deadline is a wakeup too, watchdog or not.
+        let (fd, timeout): (Option<RawFd>, Option<Duration>) = match successor.as_ref() {
+            Some(successor) => {
+                let left: Duration = successor.time_left();
+                (
+                    Some(successor.as_raw_fd()),
+                    Some(watchdog.map_or(left, |watchdog| watchdog.min(left))),
+                )
+            }
+            None => (handoff.as_ref().map(|handoff| handoff.as_raw_fd()), watchdog),
+        };
+        match signal.wait(fd, timeout)? {
+            Wakeup::Signal(signo) => {
+                info!("Caught signal {}. Shutting down", signo);
+                if let Some(notifier) = notifier.as_ref() {
+                    notifier.notify("STOPPING=1");
+                }
+                // Nobody to hand over to anymore. Don't leave dead sockets behind.
+                for path in server.handoff_path.iter().chain(server.unix_paths.iter()) {
+                    let _ = std::fs::remove_file(path);
+                }
+                break;
+            }
+            Wakeup::Timeout => {
+                // Only vouch for workers that are actually alive. A dead one means it's time
+                // for systemd to restart us.
+                if let (Some(notifier), Some(_)) = (notifier.as_ref(), watchdog) {
+                    if workers.iter().all(|worker| !worker.is_finished()) {
+                        notifier.notify("WATCHDOG=1");
+                    } else {
+                        error!("A worker is gone. Watchdog stays hungry");
+                    }
+                }
+                if successor.as_ref().is_some_and(|successor| successor.time_left().is_zero()) {
+                    error!("Successor never got ready. Staying up");
+                    successor = None;
+                }
+            }
+            // No STOPPING=1 here: after the handoff the unit is the successor's, and it isn't stopping.
+            Wakeup::Fd => match successor.take() {
+                Some(successor) => {
+                    if successor.answer() {
+                        // The path belongs to the successor now. Leave it alone.
+                        server.handed_off.store(true, Ordering::Relaxed);
+                        break;
+                    }
+                }
+                None => {
+                    let handoff: &UnixListener = handoff.as_ref().unwrap();
+                    successor = hand_over(handoff, &listener_fds);
+                }
+            },
+        }
+    }
+    signal.trigger()?;
+    for worker in workers {
+        if worker.join().is_err() {
+            error!("Worker panicked on the way out");
+        }
+    }
+    // Workers are gone, so is anything new to log. Whatever's left gets written down first.
+    if let Some(log_writer) = log_writer {
+        server.access_log.stop();
+        if log_writer.join().is_err() {
+            error!("Access log writer panicked");
+        }
     }
+    info!("All workers stopped. Bye.");
+    Ok(())
 }
@@ -1,18 +1,42 @@
 use crate::library::uring::Uring;
 use socket2::{Domain, Protocol, SockAddr, Socket, Type};
-use std::{io, net::TcpListener};
+use std::{
+    fs, io,
+    net::{SocketAddr, TcpListener},
+    os::unix::{
+        fs::{chown, FileTypeExt, PermissionsExt},
+        net::{UnixListener, UnixStream},
+    },
+};
+use tracing::info;
 
 pub const BUF_GROUP: u16 = 42;
 pub const REQ_RESP_OFFSET: u64 = u64::MAX / 2;
 pub const BUFFER_REGISTER_CODE: u64 = 0xFAB;
 pub const INIT_REQUEST: u16 = 0xCCA;
 pub const POLL_EVENT: u16 = 0xAAA;
+pub const SEND_EVENT: u16 = 0xCCC;
+pub const SHUTDOWN_EVENT: u16 = 0xDDD;
+pub const STREAM_SEND: u16 = 0xCCD;
+// The socket buffer was full. Fires once there's room again.
+pub const WRITABLE_EVENT: u16 = 0xCCE;
 pub const CODE_ACCEPT: u64 = 0xA;
+pub const CODE_SHUTDOWN: u64 = 0xB;
+pub const CODE_DRAIN_TIMEOUT: u64 = 0xC;
+// Once a second. Wakes the loop up so overstaying connections get noticed even when it's quiet.
+pub const CODE_TICK: u64 = 0xD;
+// Accepts on Unix domain sockets. No TCP knobs to turn on those.
+pub const CODE_ACCEPT_UNIX: u64 = 0xE;
+// Single-shot accepts, for kernels without multishot. Plus the listener's index — we have to
+// know which one to re-arm.
+pub const CODE_ACCEPT_ONCE: u64 = 0x10000;
 
 #[derive(Debug, Clone, Copy)]
 pub struct UserData {
     pub client_id: u32,
-    pub buffer_id: u16,
+    // Client ids get handed out again. This tells the current holder from whoever had it before,
+    // so a late completion for the old one doesn't land on the new one.
+    pub generation: u16,
     pub uniq_id: u16,
 }
 
@@ -20,30 +44,92 @@ impl UserData {
     #[inline(always)]
     pub const fn pack_user_data(&self) -> u64 {
         ((self.uniq_id as u64) << 48)
-            | ((self.buffer_id as u64) << 32)
+            | ((self.generation as u64) << 32)
             | (self.client_id as u64) + REQ_RESP_OFFSET
     }
     pub fn unpack_user_data(user_data: u64) -> Self {
         let raw = user_data - REQ_RESP_OFFSET;
         Self {
             client_id: (raw & 0xFFFF_FFFF) as u32,
-            buffer_id: ((raw >> 32) & 0xFFFF) as u16,
+            generation: ((raw >> 32) & 0xFFFF) as u16,
             uniq_id: ((raw >> 48) & 0xFFFF) as u16,
         }
     }
 }
 
 pub trait ServerInternal {
-    fn build_listener(&self, addr: &str) -> io::Result<TcpListener> {
-        let listener = Socket::new(Domain::IPV4, Type::STREAM, Some(Protocol::TCP))?;
+    /// Bound, listening, non-blocking socket for `addr` — IPv4 (`0.0.0.0:8080`) or IPv6
+    /// (`[::]:8080`). `ipv6_only` sets `IPV6_V6ONLY` on IPv6 sockets; `None` leaves the
+    /// system default (`net.ipv6.bindv6only`, usually dual-stack).
+    fn build_listener(
+        &self,
+        addr: &str,
+        ipv6_only: Option<bool>,
+        backlog: i32,
+    ) -> io::Result<TcpListener> {
+        let addr: SocketAddr = addr.parse().map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid listen address {addr:?}: {err}"),
+            )
+        })?;
+        let listener = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
         // listener.set_tcp_nodelay(true)?;
         listener.set_reuse_address(true)?;
         listener.set_reuse_port(true)?;
-        listener.bind(&SockAddr::from(
-            addr.parse::<std::net::SocketAddr>().unwrap(),
-        ))?;
-        listener.listen(32768)?;
+        if let (SocketAddr::V6(_), Some(only)) = (addr, ipv6_only) {
+            listener.set_only_v6(only)?;
+        }
+        listener.bind(&SockAddr::from(addr))?;
+        listener.listen(backlog)?;
+        listener.set_nonblocking(true)?;
+        Ok(listener.into())
+    }
+
+    /// Bound, listening, non-blocking Unix stream socket on `path`. A socket file nobody
+    /// answers on is a leftover from a crashed run and gets removed; one somebody answers on
+    /// is `AddrInUse`; anything that isn't a socket is left alone and reported.
+    /// `mode` and `owner` (uid, gid) are applied to the socket file after binding.
+    fn build_unix_listener(
+        &self,
+        path: &str,
+        mode: Option<u32>,
+        owner: Option<(u32, u32)>,
+        backlog: i32,
+    ) -> io::Result<UnixListener> {
+        match fs::symlink_metadata(path) {
+            Ok(meta) if meta.file_type().is_socket() => match UnixStream::connect(path) {
+                Ok(_) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::AddrInUse,
+                        format!("someone is already listening on {path}"),
+                    ));
+                }
+                Err(err) if err.kind() == io::ErrorKind::ConnectionRefused => {
+                    info!("Removing stale socket {}", path);
+                    fs::remove_file(path)?;
+                }
+                Err(err) => return Err(err),
+            },
+            Ok(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{path} exists and is not a socket"),
+                ));
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+        let listener = Socket::new(Domain::UNIX, Type::STREAM, None)?;
+        listener.bind(&SockAddr::unix(path)?)?;
+        listener.listen(backlog)?;
         listener.set_nonblocking(true)?;
+        if let Some(mode) = mode {
+            fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+        }
+        if let Some((uid, gid)) = owner {
+            chown(path, Some(uid), Some(gid))?;
+        }
         Ok(listener.into())
     }
 
@@ -1,7 +1,4 @@
-use crate::library::{
-    server::BUFFER_SIZE,
-    server_internals::{BUF_GROUP, BUFFER_REGISTER_CODE, CODE_ACCEPT},
-};
+use crate::library::server_internals::{BUF_GROUP, BUFFER_REGISTER_CODE};
 use io_uring::{opcode, squeue, squeue::Flags, types};
 use libc::{MSG_DONTWAIT, SOCK_NONBLOCK, msghdr};
 use std::os::fd::RawFd;
@@ -17,13 +14,32 @@ pub fn provide_buffer(buffer: *mut u8, buffer_id: u16, buffer_size: i32) -> sque
 }
 
 #[inline(always)]
-pub fn accept_multi(fd: RawFd) -> squeue::Entry {
+pub fn provide_buffers(base: *mut u8, buffer_size: i32, count: u16) -> squeue::Entry {
+    // The whole pool in one go: `count` buffers back to back, ids 0..count. Bulk discount.
+    trace!("Kernel Call: ProvideBuffers ({count})");
+    opcode::ProvideBuffers::new(base, buffer_size, count, BUF_GROUP, 0)
+        .build()
+        .user_data(BUFFER_REGISTER_CODE)
+}
+
+#[inline(always)]
+pub fn accept_multi(fd: RawFd, user_data: u64) -> squeue::Entry {
     // Accept multiple connections in a single syscall, like a bouncer at a very busy nightclub
     trace!("Kernel Call: AcceptMulti");
     opcode::AcceptMulti::new(types::Fd(fd))
         .flags(libc::SOCK_CLOEXEC | SOCK_NONBLOCK)
         .build()
-        .user_data(CODE_ACCEPT)
+        .user_data(user_data)
+}
+
+#[inline(always)]
+pub fn accept_once(fd: RawFd, user_data: u64) -> squeue::Entry {
+    // One connection, then the bouncer goes home. Kernels without multishot accept need a new one each time.
+    trace!("Kernel Call: Accept");
+    opcode::Accept::new(types::Fd(fd), std::ptr::null_mut(), std::ptr::null_mut())
+        .flags(libc::SOCK_CLOEXEC | SOCK_NONBLOCK)
+        .build()
+        .user_data(user_data)
 }
 
 #[inline(always)]
@@ -56,6 +72,16 @@ pub unsafe fn send(user_data: u64, client_fd: RawFd, data: &[u8], more: bool) ->
     }
 }
 
+#[inline(always)]
+pub unsafe fn send_tracked(user_data: u64, client_fd: RawFd, data: &[u8]) -> squeue::Entry {
+    // The send that actually wants to hear back: the completion says how much the kernel took.
+    trace!("Kernel Call: Send (tracked)");
+    trace!("    Write {} bytes", data.len());
+    opcode::Send::new(types::Fd(client_fd), data.as_ptr(), data.len() as u32)
+        .build()
+        .user_data(user_data)
+}
+
 #[inline(always)]
 pub unsafe fn send_zero_copy(user_data: u64, client_fd: RawFd, data: &[u8]) -> squeue::Entry {
     // Send, but cooler — zero-copy style. Don’t move bytes, just wave at them meaningfully.
@@ -85,13 +111,13 @@ pub unsafe fn recv_multi(client_fd: RawFd, user_data: u64) -> squeue::Entry {
 }
 
 #[inline(always)]
-pub unsafe fn recv_buf_group(client_fd: RawFd, user_data: u64) -> squeue::Entry {
+pub unsafe fn recv_buf_group(client_fd: RawFd, user_data: u64, len: u32) -> squeue::Entry {
     // Ask the kernel to give you *any* buffer from your magical group of registered ones.
     trace!("Kernel Call: Recv (buf group)");
     opcode::Recv::new(
         types::Fd(client_fd),
         std::ptr::null_mut(), // "You choose the buffer, I trust you"
-        BUFFER_SIZE as u32,
+        len,
     )
     .buf_group(BUF_GROUP)
     .build()
@@ -122,6 +148,61 @@ pub unsafe fn poll_add(client_fd: RawFd, user_data: u64) -> squeue::Entry {
     .user_data(user_data)
 }
 
+#[inline(always)]
+pub unsafe fn poll_once(fd: RawFd, user_data: u64) -> squeue::Entry {
+    // Single-shot sensor. Fires once when `fd` turns readable, then it's done.
+    trace!("Kernel Call: PollAdd");
+    opcode::PollAdd::new(types::Fd(fd), libc::POLLIN as u32)
+        .build()
+        .user_data(user_data)
+}
+
+#[inline(always)]
+pub unsafe fn poll_writable(fd: RawFd, user_data: u64) -> squeue::Entry {
+    // Single-shot. Fires once `fd` has room to write — or once it never will again.
+    trace!("Kernel Call: PollAdd (writable)");
+    opcode::PollAdd::new(types::Fd(fd), libc::POLLOUT as u32)
+        .build()
+        .user_data(user_data)
+}
+
+#[inline(always)]
+pub unsafe fn timeout(ts: &types::Timespec, user_data: u64) -> squeue::Entry {
+    // Alarm clock. `ts` has to stay where it is until the alarm goes off.
+    trace!("Kernel Call: Timeout");
+    opcode::Timeout::new(ts).build().user_data(user_data)
+}
+
+#[inline(always)]
+pub unsafe fn shutdown(client_fd: RawFd, user_data: u64) -> squeue::Entry {
+    // Slam the door (both directions). The pending recv wakes up with 0 and cleans up after us.
+    trace!("Kernel Call: Shutdown");
+    opcode::Shutdown::new(types::Fd(client_fd), libc::SHUT_RDWR)
+        .build()
+        .user_data(user_data)
+        .flags(Flags::SKIP_SUCCESS)
+}
+
+#[inline(always)]
+pub unsafe fn cancel_accepts(user_data: u64) -> squeue::Entry {
+    // Call off every pending accept tagged `user_data` on this ring. The listener itself stays untouched.
+    trace!("Kernel Call: AsyncCancel (accepts)");
+    opcode::AsyncCancel2::new(types::CancelBuilder::user_data(user_data).all())
+        .build()
+        .user_data(user_data)
+        .flags(Flags::SKIP_SUCCESS)
+}
+
+#[inline(always)]
+pub unsafe fn cancel(user_data: u64) -> squeue::Entry {
+    // Call off the first pending request tagged `user_data`. Old-school: one at a time.
+    trace!("Kernel Call: AsyncCancel");
+    opcode::AsyncCancel::new(user_data)
+        .build()
+        .user_data(user_data)
+        .flags(Flags::SKIP_SUCCESS)
+}
+
 #[inline(always)]
 pub unsafe fn async_cancel() -> squeue::Entry {
     // Yeet all async operations. No cleanup. No goodbyes. Just vanish.
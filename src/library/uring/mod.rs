@@ -1,11 +1,14 @@
 pub mod kernel_cmds;
+pub mod probe;
 
 use io_uring::{Builder, IoUring, cqueue, squeue}; // The magic portal to kernel-space IO wizardry.
+use probe::Capabilities;
 use std::io;
 use tracing::info;
 
 pub struct Uring {
     pub uring: IoUring<squeue::Entry, cqueue::Entry>,
+    pub caps: Capabilities,
 }
 
 impl Uring {
@@ -15,10 +18,52 @@ impl Uring {
         affinity: u32,    // CPU core to pin the SQPOLL thread to. Because cache locality is king.
         sqpoll_enabled: bool, // Whether to unleash the SQPOLL daemon.
     ) -> io::Result<Uring> {
+        // Newest setup flags first. An older kernel rejects what it doesn't know with EINVAL,
+        // so we drop them one by one: single issuer is 6.0, submit all is 5.18.
+        let mut single_issuer: bool = true;
+        let mut submit_all: bool = !sqpoll_enabled;
+        let uring: IoUring<squeue::Entry, cqueue::Entry> = loop {
+            match Self::build(
+                size,
+                sqpoll_idle,
+                affinity,
+                sqpoll_enabled,
+                single_issuer,
+                submit_all,
+            ) {
+                Ok(uring) => break uring,
+                Err(err) if err.raw_os_error() == Some(libc::EINVAL) && single_issuer => {
+                    info!("Kernel refused SINGLE_ISSUER. Retrying without");
+                    single_issuer = false;
+                }
+                Err(err) if err.raw_os_error() == Some(libc::EINVAL) && submit_all => {
+                    info!("Kernel refused SUBMIT_ALL. Retrying without");
+                    submit_all = false;
+                }
+                // Try to build the uring. If this fails, you probably forgot to sacrifice a goat to the kernel.
+                Err(err) => return Err(err),
+            }
+        };
+        let caps: Capabilities = Capabilities::probe(&uring, single_issuer, submit_all);
+        info!("Uring capabilities: {}", caps);
+        caps.check()?;
+        Ok(Uring { uring, caps })
+    }
+
+    fn build(
+        size: u32,
+        sqpoll_idle: u32,
+        affinity: u32,
+        sqpoll_enabled: bool,
+        single_issuer: bool,
+        submit_all: bool,
+    ) -> io::Result<IoUring<squeue::Entry, cqueue::Entry>> {
         let mut builder: Builder = IoUring::builder();
-        // This makes sure only one thread submits SQEs at a time.
-        // Think of it as "no cut in line" for submissions.
-        builder.setup_single_issuer();
+        if single_issuer {
+            // This makes sure only one thread submits SQEs at a time.
+            // Think of it as "no cut in line" for submissions.
+            builder.setup_single_issuer();
+        }
         if sqpoll_enabled {
             // Welcome to SQPOLL mode: where the kernel spawns a thread to babysit your submission queue.
             info!("SQPOLL enabled");
@@ -31,13 +76,11 @@ impl Uring {
             // Prevent the ring from being inherited across fork().
             // Because zombies and file descriptors don’t mix.
             builder.dontfork();
-        } else {
+        } else if submit_all {
             // Without SQPOLL, we want every submission to be flushed immediately.
             // No lazy batching — just send it!
             builder.setup_submit_all();
         }
-        // Try to build the uring. If this fails, you probably forgot to sacrifice a goat to the kernel.
-        let uring: IoUring<squeue::Entry, cqueue::Entry> = builder.build(size)?;
-        Ok(Uring { uring })
+        builder.build(size)
     }
 }
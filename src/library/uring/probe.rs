@@ -0,0 +1,119 @@
+use io_uring::{IoUring, opcode, register::Probe};
+use std::{fmt, io};
+use tracing::trace;
+
+/// What the kernel under us can actually do. Filled in once per ring, right after it's built.
+///
+/// Some of it the kernel tells us straight (opcodes via `IORING_REGISTER_PROBE`, feature flags
+/// from setup). Multishot accept/recv and buffer rings are flags and register ops, not opcodes —
+/// the probe can't see them. We go by an opcode that shipped in the same release instead.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Capabilities {
+    /// `IORING_SETUP_SINGLE_ISSUER` was accepted (6.0+).
+    pub single_issuer: bool,
+    /// `IORING_SETUP_SUBMIT_ALL` was accepted (5.18+).
+    pub submit_all: bool,
+    /// `IORING_OP_PROVIDE_BUFFERS`. No recv without it.
+    pub provide_buffers: bool,
+    /// `IOSQE_CQE_SKIP_SUCCESS`. Tracked sends don't need it any more, but the fire-and-forget
+    /// entries in `kernel_cmds` still do: `shutdown`, `cancel`, `cancel_accepts` and the untracked
+    /// `send`/`send_raw_hdr`/`send_msg_zero_copy`. An older kernel fails them all with EINVAL.
+    pub skip_success: bool,
+    /// Multishot accept and cancel-all (5.19, same as `IORING_OP_SOCKET`).
+    pub multishot_accept: bool,
+    /// Multishot recv (6.0, same as `IORING_OP_SEND_ZC`).
+    pub multishot_recv: bool,
+    /// `IORING_REGISTER_PBUF_RING` (5.19). Registration still gets the final word.
+    pub buf_ring: bool,
+    /// `IORING_FEAT_FAST_POLL`: no worker thread for sockets that aren't ready yet.
+    pub fast_poll: bool,
+    /// `SO_ZEROCOPY` on TCP sockets.
+    pub zerocopy: bool,
+}
+
+impl Capabilities {
+    /// Ask the kernel behind `uring`. `single_issuer` and `submit_all` are whatever setup ended with.
+    pub fn probe(uring: &IoUring, single_issuer: bool, submit_all: bool) -> Capabilities {
+        let mut probe: Probe = Probe::new();
+        // Pre-5.6 kernels can't even answer. Everything below then stays "no".
+        let probed: bool = match uring.submitter().register_probe(&mut probe) {
+            Ok(_) => true,
+            Err(err) => {
+                trace!("IORING_REGISTER_PROBE failed: {}", err);
+                false
+            }
+        };
+        let has = |code: u8| probed && probe.is_supported(code);
+        let params = uring.params();
+        Capabilities {
+            single_issuer,
+            submit_all,
+            provide_buffers: has(opcode::ProvideBuffers::CODE),
+            skip_success: params.is_feature_skip_cqe_on_success(),
+            multishot_accept: has(opcode::Socket::CODE),
+            multishot_recv: has(opcode::SendZc::CODE),
+            buf_ring: has(opcode::Socket::CODE),
+            fast_poll: params.is_feature_fast_poll(),
+            zerocopy: probe_zerocopy(),
+        }
+    }
+
+    /// The things we can't work around. Anything else just gets slower.
+    pub fn check(&self) -> io::Result<()> {
+        let missing: &str = if !self.provide_buffers {
+            "IORING_OP_PROVIDE_BUFFERS"
+        } else if !self.skip_success {
+            "IOSQE_CQE_SKIP_SUCCESS"
+        } else {
+            return Ok(());
+        };
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("kernel lacks {missing}. Linux 5.17 or newer required"),
+        ))
+    }
+}
+
+// One line for the log. `+` has it, `-` makes do without.
+impl fmt::Display for Capabilities {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let flags: [(&str, bool); 9] = [
+            ("single_issuer", self.single_issuer),
+            ("submit_all", self.submit_all),
+            ("provide_buffers", self.provide_buffers),
+            ("skip_success", self.skip_success),
+            ("multishot_accept", self.multishot_accept),
+            ("multishot_recv", self.multishot_recv),
+            ("buf_ring", self.buf_ring),
+            ("fast_poll", self.fast_poll),
+            ("zerocopy", self.zerocopy),
+        ];
+        for (i, (name, on)) in flags.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            write!(f, "{}{}", if *on { '+' } else { '-' }, name)?;
+        }
+        Ok(())
+    }
+}
+
+// A throwaway TCP socket tells us whether SO_ZEROCOPY is a thing here (4.14+).
+fn probe_zerocopy() -> bool {
+    unsafe {
+        let fd: i32 = libc::socket(libc::AF_INET, libc::SOCK_STREAM | libc::SOCK_CLOEXEC, 0);
+        if fd < 0 {
+            return false;
+        }
+        let enable: libc::c_int = 1;
+        let ret: i32 = libc::setsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            libc::SO_ZEROCOPY,
+            &enable as *const _ as *const libc::c_void,
+            size_of_val(&enable) as _,
+        );
+        libc::close(fd);
+        ret == 0
+    }
+}
@@ -0,0 +1,224 @@
+use libc::{
+    madvise, mmap, munmap, MADV_HUGEPAGE, MAP_ANONYMOUS, MAP_FAILED, MAP_HUGETLB, MAP_POPULATE,
+    MAP_PRIVATE, PROT_READ, PROT_WRITE,
+};
+use io_uring::{types::BufRingEntry, Submitter};
+use std::{
+    io, ptr,
+    sync::atomic::{AtomicU16, Ordering},
+};
+use tracing::{info, trace};
+
+// The slab the kernel drops incoming bytes into. One per ring, sized at runtime.
+//
+// One big anonymous mapping, carved into `count` buffers of `buffer_size` bytes each.
+// Buffer `id` starts at `id * buffer_size`. That's the whole data structure.
+// Huge pages if asked for: explicit ones (hugetlbfs) first, transparent ones if none are reserved.
+//
+// Getting buffers back to the kernel: with a registered buffer ring (5.19+) a used buffer is
+// one entry written at the tail plus a store to the tail index — no syscall, no SQE.
+// Older kernels get the buffers back one `ProvideBuffers` SQE at a time. The server does that part.
+
+// 2 MB. Explicit huge page mappings must be a multiple of it.
+const HUGE_PAGE_SIZE: usize = 2 * 1024 * 1024;
+
+pub struct BufferPool {
+    ptr: *mut u8,
+    len: usize,
+    buffer_size: usize,
+    count: usize,
+    ring: Option<BufRing>,
+}
+
+// The ring the kernel picks buffers from. We own the tail, the kernel owns the head.
+struct BufRing {
+    entries: *mut BufRingEntry,
+    len: usize,
+    mask: u16,
+    tail: u16,
+}
+
+impl BufferPool {
+    /// Map `count` buffers of `buffer_size` bytes. Prefaulted, so the first packets don't pay for page faults.
+    pub fn new(buffer_size: usize, count: usize, huge_pages: bool) -> io::Result<Self> {
+        let size: usize = buffer_size.checked_mul(count).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "buffer pool size overflows")
+        })?;
+        if size == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "empty buffer pool"));
+        }
+        let flags: i32 = MAP_PRIVATE | MAP_ANONYMOUS | MAP_POPULATE;
+        if huge_pages {
+            let len: usize = size.next_multiple_of(HUGE_PAGE_SIZE);
+            match Self::map(len, flags | MAP_HUGETLB) {
+                Ok(ptr) => {
+                    info!("Buffer pool: {} x {} bytes on explicit huge pages", count, buffer_size);
+                    return Ok(Self {
+                        ptr,
+                        len,
+                        buffer_size,
+                        count,
+                        ring: None,
+                    });
+                }
+                // No huge pages reserved (vm.nr_hugepages = 0). Ask for transparent ones instead.
+                Err(err) => trace!("MAP_HUGETLB failed: {}. Falling back to THP", err),
+            }
+        }
+        let ptr: *mut u8 = Self::map(size, flags)?;
+        if huge_pages {
+            unsafe { madvise(ptr as *mut libc::c_void, size, MADV_HUGEPAGE) };
+        }
+        info!("Buffer pool: {} x {} bytes", count, buffer_size);
+        Ok(Self {
+            ptr,
+            len: size,
+            buffer_size,
+            count,
+            ring: None,
+        })
+    }
+
+    /// No memory at all. What a cloned `Server` gets until its ring builds a pool of its own.
+    pub const fn empty() -> Self {
+        Self {
+            ptr: ptr::null_mut(),
+            len: 0,
+            buffer_size: 0,
+            count: 0,
+            ring: None,
+        }
+    }
+
+    /// Register a buffer ring for group `bgid` and put every buffer in it.
+    /// An error most likely means the kernel predates `IORING_REGISTER_PBUF_RING` — the pool
+    /// is untouched then, and `ProvideBuffers` still works with it.
+    ///
+    /// # Safety:
+    /// The pool must outlive the ring registration — i.e. the `io_uring` instance behind `submitter`.
+    pub unsafe fn register_ring(&mut self, submitter: &Submitter, bgid: u16) -> io::Result<()> {
+        // The kernel wants a power of two, and the memory page aligned. mmap does the aligning.
+        let entries: usize = self.count.next_power_of_two();
+        let len: usize = entries * size_of::<BufRingEntry>();
+        let ring_ptr: *mut BufRingEntry = Self::map(len, MAP_PRIVATE | MAP_ANONYMOUS | MAP_POPULATE)?
+            as *mut BufRingEntry;
+        if let Err(err) = submitter.register_buf_ring(ring_ptr as u64, entries as u16, bgid) {
+            munmap(ring_ptr as *mut libc::c_void, len);
+            return Err(err);
+        }
+        self.ring = Some(BufRing {
+            entries: ring_ptr,
+            len,
+            mask: (entries - 1) as u16,
+            tail: 0,
+        });
+        for id in 0..self.count {
+            self.stage(id as u16);
+        }
+        self.publish();
+        info!("Buffer ring: {} entries for group {}", entries, bgid);
+        Ok(())
+    }
+
+    /// True once `register_ring` worked. Recycle with `recycle`, not `ProvideBuffers`, then.
+    #[inline(always)]
+    pub fn has_ring(&self) -> bool {
+        self.ring.is_some()
+    }
+
+    /// Hand buffer `id` back to the kernel through the ring.
+    ///
+    /// # Safety:
+    /// `id` must be below `count()`, owned by us (picked by the kernel, not yet recycled), and the
+    /// pool must have a ring.
+    #[inline(always)]
+    pub unsafe fn recycle(&mut self, id: u16) {
+        self.stage(id);
+        self.publish();
+    }
+
+    // Write the entry at our private tail. The kernel doesn't see it until `publish`.
+    #[inline(always)]
+    unsafe fn stage(&mut self, id: u16) {
+        let addr: u64 = self.ptr(id) as u64;
+        let buffer_size: u32 = self.buffer_size as u32;
+        let ring: &mut BufRing = self.ring.as_mut().unwrap_unchecked();
+        let entry: &mut BufRingEntry = &mut *ring.entries.add((ring.tail & ring.mask) as usize);
+        entry.set_addr(addr);
+        entry.set_len(buffer_size);
+        entry.set_bid(id);
+        ring.tail = ring.tail.wrapping_add(1);
+    }
+
+    // Move the shared tail. Release, so the kernel sees the entries before it sees the tail.
+    #[inline(always)]
+    unsafe fn publish(&self) {
+        let ring: &BufRing = self.ring.as_ref().unwrap_unchecked();
+        let tail: *mut u16 = BufRingEntry::tail(ring.entries) as *mut u16;
+        AtomicU16::from_ptr(tail).store(ring.tail, Ordering::Release);
+    }
+
+    fn map(len: usize, flags: i32) -> io::Result<*mut u8> {
+        let ptr: *mut libc::c_void =
+            unsafe { mmap(ptr::null_mut(), len, PROT_READ | PROT_WRITE, flags, -1, 0) };
+        if ptr == MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(ptr as *mut u8)
+    }
+
+    #[inline(always)]
+    pub fn buffer_size(&self) -> usize {
+        self.buffer_size
+    }
+
+    #[inline(always)]
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Start of the whole slab. Buffer 0 lives here.
+    #[inline(always)]
+    pub fn base(&self) -> *mut u8 {
+        self.ptr
+    }
+
+    /// # Safety:
+    /// `id` must be below `count()`.
+    #[inline(always)]
+    pub unsafe fn ptr(&self, id: u16) -> *mut u8 {
+        self.ptr.add(id as usize * self.buffer_size)
+    }
+
+    /// # Safety:
+    /// `id` must be below `count()`, and the kernel must be done writing to it.
+    #[inline(always)]
+    pub unsafe fn get(&self, id: u16) -> &[u8] {
+        std::slice::from_raw_parts(self.ptr(id), self.buffer_size)
+    }
+
+    /// # Safety:
+    /// Same as `get`. Plus nobody else holds a slice of this buffer.
+    #[inline(always)]
+    pub unsafe fn get_mut(&mut self, id: u16) -> &mut [u8] {
+        std::slice::from_raw_parts_mut(self.ptr(id), self.buffer_size)
+    }
+}
+
+// Buffers belong to the ring they were handed to. A copy of the server gets none of them.
+impl Clone for BufferPool {
+    fn clone(&self) -> Self {
+        Self::empty()
+    }
+}
+
+impl Drop for BufferPool {
+    fn drop(&mut self) {
+        if let Some(ring) = self.ring.take() {
+            unsafe { munmap(ring.entries as *mut libc::c_void, ring.len) };
+        }
+        if !self.ptr.is_null() {
+            unsafe { munmap(self.ptr as *mut libc::c_void, self.len) };
+        }
+    }
+}
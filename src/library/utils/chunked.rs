@@ -0,0 +1,178 @@
+// Incremental `Transfer-Encoding: chunked` decoder.
+//
+// Feed it whatever the kernel hands over — one byte or a whole buffer at a time —
+// and it stitches the chunks back into a single body. The state survives between
+// `recv` completions, so a chunk header split across two buffers is no drama.
+
+// Trailers are a rarely used corner of the spec. A rarely used corner is exactly
+// where someone will try to stash 10 MB of garbage, so they get a hard cap.
+const MAX_TRAILERS_SIZE: usize = 8 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum State {
+    // Reading hex digits of the chunk size.
+    Size,
+    // Whitespace after the size. Only an extension may follow — `1 0` is not sixteen, and
+    // not one either.
+    SizeSpace,
+    // Skipping `;name=value` extensions. Nobody uses them. We skip them anyway.
+    Extension,
+    // Got `\r` after the size line, waiting for `\n`.
+    SizeLf,
+    // Copying chunk payload.
+    Data,
+    // Payload done, expecting `\r\n`.
+    DataCr,
+    DataLf,
+    // After the `0` chunk: trailer lines until the empty one.
+    Trailers,
+    Done,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ChunkedStatus {
+    /// Body complete. Value is how many bytes of the last input were eaten.
+    Done(usize),
+    /// Everything was eaten, and we're still hungry.
+    NeedMore,
+    /// Body went over the configured limit.
+    TooLarge,
+    /// Not chunked encoding. Or a very creative interpretation of it.
+    Invalid,
+}
+
+#[derive(Clone, Debug)]
+pub struct ChunkedDecoder {
+    state: State,
+    // Bytes left in the current chunk, or the size being parsed in `Size` state.
+    remaining: usize,
+    size_digits: usize,
+    max_body_size: usize,
+    body: Vec<u8>,
+    trailers: Vec<u8>,
+}
+
+impl ChunkedDecoder {
+    pub fn new(max_body_size: usize) -> Self {
+        Self {
+            state: State::Size,
+            remaining: 0,
+            size_digits: 0,
+            max_body_size,
+            body: Vec::new(),
+            trailers: Vec::new(),
+        }
+    }
+
+    /// Decoded body so far (complete once `feed` returned `Done`).
+    #[inline(always)]
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// Raw trailer block, including the terminating empty line. Just `\r\n` when there are none.
+    #[inline(always)]
+    pub fn trailers(&self) -> &[u8] {
+        &self.trailers
+    }
+
+    pub fn feed(&mut self, input: &[u8]) -> ChunkedStatus {
+        let mut i: usize = 0;
+        while i < input.len() {
+            let byte: u8 = input[i];
+            match self.state {
+                State::Size => match byte {
+                    b'0'..=b'9' | b'a'..=b'f' | b'A'..=b'F' => {
+                        let digit: usize = (byte as char).to_digit(16).unwrap() as usize;
+                        // 15 hex digits is already more than anyone will ever send. Or store.
+                        if self.size_digits == 15 {
+                            return ChunkedStatus::Invalid;
+                        }
+                        self.remaining = self.remaining * 16 + digit;
+                        self.size_digits += 1;
+                    }
+                    b';' if self.size_digits > 0 => self.state = State::Extension,
+                    b' ' | b'\t' if self.size_digits > 0 => self.state = State::SizeSpace,
+                    b'\r' if self.size_digits > 0 => self.state = State::SizeLf,
+                    _ => return ChunkedStatus::Invalid,
+                },
+                State::SizeSpace => match byte {
+                    b' ' | b'\t' => {}
+                    b';' => self.state = State::Extension,
+                    _ => return ChunkedStatus::Invalid,
+                },
+                // Lines end in CRLF, full stop. A proxy in front of us may not take a bare LF
+                // the way we would, and then we'd disagree on where the body ends.
+                State::Extension => match byte {
+                    b'\r' => self.state = State::SizeLf,
+                    b'\n' => return ChunkedStatus::Invalid,
+                    _ => {}
+                },
+                State::SizeLf => {
+                    if byte != b'\n' {
+                        return ChunkedStatus::Invalid;
+                    }
+                    self.end_size_line();
+                }
+                State::Data => {
+                    let take: usize = self.remaining.min(input.len() - i);
+                    self.body.extend_from_slice(&input[i..i + take]);
+                    self.remaining -= take;
+                    if self.remaining == 0 {
+                        self.state = State::DataCr;
+                    }
+                    i += take;
+                    continue;
+                }
+                State::DataCr => match byte {
+                    b'\r' => self.state = State::DataLf,
+                    _ => return ChunkedStatus::Invalid,
+                },
+                State::DataLf => {
+                    if byte != b'\n' {
+                        return ChunkedStatus::Invalid;
+                    }
+                    self.state = State::Size;
+                }
+                State::Trailers => {
+                    if self.trailers.len() == MAX_TRAILERS_SIZE {
+                        return ChunkedStatus::TooLarge;
+                    }
+                    if byte == b'\n' && self.trailers.last() != Some(&b'\r') {
+                        return ChunkedStatus::Invalid;
+                    }
+                    self.trailers.push(byte);
+                    if self.trailers_complete() {
+                        self.state = State::Done;
+                        return ChunkedStatus::Done(i + 1);
+                    }
+                }
+                State::Done => return ChunkedStatus::Done(i),
+            }
+            if self.state == State::Data && self.body.len() + self.remaining > self.max_body_size {
+                return ChunkedStatus::TooLarge;
+            }
+            i += 1;
+        }
+        if self.state == State::Done {
+            return ChunkedStatus::Done(i);
+        }
+        ChunkedStatus::NeedMore
+    }
+
+    #[inline(always)]
+    fn end_size_line(&mut self) {
+        self.size_digits = 0;
+        self.state = if self.remaining == 0 {
+            State::Trailers
+        } else {
+            State::Data
+        };
+    }
+
+    #[inline(always)]
+    fn trailers_complete(&self) -> bool {
+        let t: &[u8] = &self.trailers;
+        t == b"\r\n" || t.ends_with(b"\r\n\r\n")
+    }
+}
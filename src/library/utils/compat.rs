@@ -0,0 +1,149 @@
+// Platform glue.
+//
+// On x86-64 this is just a re-export of the hand-tuned crates (`lake` writes with AVX2,
+// `nano_clock` formats dates in inline asm). Everywhere else — hello, ARM fleet — the same
+// API is provided in plain Rust. Slower on paper, identical on the wire.
+
+#[cfg(target_arch = "x86_64")]
+pub use lake::{lake::memory::LakeTools, small_lake::SmallLake};
+#[cfg(target_arch = "x86_64")]
+pub use nano_clock::{nano_http_date, nano_timestamp, timestamp};
+
+#[cfg(not(target_arch = "x86_64"))]
+pub use portable::*;
+
+#[cfg(not(target_arch = "x86_64"))]
+mod portable {
+    use libc::{CLOCK_MONOTONIC, clock_gettime, timespec};
+    use std::ptr;
+
+    /// Drop-in for `lake::small_lake::SmallLake`: fixed buffer plus a write position.
+    /// Same ring behaviour too — a write that doesn't fit starts over from zero.
+    #[repr(C)]
+    #[derive(Clone)]
+    pub struct SmallLake<const N: usize> {
+        pub buf: [u8; N],
+        pub pos: usize,
+    }
+
+    impl<const N: usize> SmallLake<N> {
+        #[inline(always)]
+        pub const fn build() -> Self {
+            Self {
+                buf: [0u8; N],
+                pos: 0,
+            }
+        }
+        #[inline(always)]
+        pub fn reset_pos(&mut self) {
+            self.pos = 0;
+        }
+        #[inline(always)]
+        pub fn len(&self) -> usize {
+            self.pos
+        }
+        #[inline(always)]
+        pub unsafe fn as_slice(&self) -> &[u8] {
+            &self.buf[..self.pos]
+        }
+        #[inline(always)]
+        pub unsafe fn as_ptr(&self) -> *const u8 {
+            self.buf.as_ptr()
+        }
+        #[inline(always)]
+        pub unsafe fn write(&mut self, src: *const u8, len: usize) {
+            if len > N {
+                panic!("DataLake overflow: trying to write {len}, but buffer is only {N}");
+            }
+            if len > N - self.pos {
+                self.pos = 0;
+            }
+            ptr::copy_nonoverlapping(src, self.buf.as_mut_ptr().add(self.pos), len);
+            self.pos += len;
+        }
+        #[inline(always)]
+        pub unsafe fn write_num_str(&mut self, mut value: usize) {
+            let mut tmp: [u8; 20] = [0u8; 20];
+            let mut curr: usize = tmp.len();
+            loop {
+                curr -= 1;
+                tmp[curr] = (value % 10) as u8 + b'0';
+                value /= 10;
+                if value == 0 {
+                    break;
+                }
+            }
+            self.write(tmp.as_ptr().add(curr), tmp.len() - curr);
+        }
+    }
+
+    pub struct LakeTools;
+
+    impl LakeTools {
+        #[inline(always)]
+        pub unsafe fn write_to(dst: *mut u8, src: *const u8, len: usize) {
+            ptr::copy_nonoverlapping(src, dst, len);
+        }
+    }
+
+    #[inline(always)]
+    pub unsafe fn timestamp() -> i64 {
+        libc::time(ptr::null_mut()) as i64
+    }
+
+    #[inline(always)]
+    pub unsafe fn nano_timestamp() -> i64 {
+        let mut ts: timespec = std::mem::zeroed();
+        clock_gettime(CLOCK_MONOTONIC, &mut ts);
+        ts.tv_sec as i64 * 1_000_000_000 + ts.tv_nsec as i64
+    }
+
+    const WEEKDAYS: [&[u8; 3]; 7] = [b"Sun", b"Mon", b"Tue", b"Wed", b"Thu", b"Fri", b"Sat"];
+    const MONTHS: [&[u8; 3]; 12] = [
+        b"Jan", b"Feb", b"Mar", b"Apr", b"May", b"Jun", b"Jul", b"Aug", b"Sep", b"Oct", b"Nov",
+        b"Dec",
+    ];
+
+    #[inline(always)]
+    fn put2(buf: &mut [u8; 35], at: usize, value: u32) {
+        buf[at] = b'0' + (value / 10) as u8;
+        buf[at + 1] = b'0' + (value % 10) as u8;
+    }
+
+    /// `Date: Sun, 06 Nov 1994 08:49:37 GMT`, byte for byte what `nano_clock` produces.
+    /// `_use_asm` is accepted for API parity. There's no asm here to use.
+    pub unsafe fn nano_http_date(buf: &mut [u8; 35], _use_asm: bool) {
+        let now: i64 = timestamp();
+        let days: i64 = now.div_euclid(86_400);
+        let secs: u32 = now.rem_euclid(86_400) as u32;
+        // Howard Hinnant's civil_from_days, minus the assembly theatrics.
+        let z: i64 = days + 719_468;
+        let era: i64 = z.div_euclid(146_097);
+        let doe: i64 = z - era * 146_097;
+        let yoe: i64 = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy: i64 = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp: i64 = (5 * doy + 2) / 153;
+        let day: u32 = (doy - (153 * mp + 2) / 5 + 1) as u32;
+        let month: usize = if mp < 10 { mp + 3 } else { mp - 9 } as usize;
+        let year: u32 = (yoe + era * 400 + (month <= 2) as i64) as u32;
+        // 1970-01-01 was a Thursday.
+        let weekday: usize = (days + 4).rem_euclid(7) as usize;
+
+        buf[..6].copy_from_slice(b"Date: ");
+        buf[6..9].copy_from_slice(WEEKDAYS[weekday]);
+        buf[9..11].copy_from_slice(b", ");
+        put2(buf, 11, day);
+        buf[13] = b' ';
+        buf[14..17].copy_from_slice(MONTHS[month - 1]);
+        buf[17] = b' ';
+        put2(buf, 18, year / 100);
+        put2(buf, 20, year % 100);
+        buf[22] = b' ';
+        put2(buf, 23, secs / 3600);
+        buf[25] = b':';
+        put2(buf, 26, secs / 60 % 60);
+        buf[28] = b':';
+        put2(buf, 29, secs % 60);
+        buf[31..35].copy_from_slice(b" GMT");
+    }
+}
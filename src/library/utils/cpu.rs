@@ -0,0 +1,84 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+use tracing::info;
+
+// Which SIMD flavour the hot paths run on.
+//
+// Detected once (first call, or `run()` at startup — whichever comes first) and cached.
+// After that every dispatch is one relaxed load and a jump. The branch predictor
+// figures it out before you finish reading this sentence.
+
+/// Widest instruction set we're allowed to use on this CPU.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum SimdLevel {
+    /// Byte by byte. Slow, honest, runs on a toaster.
+    Scalar = 1,
+    /// 16 bytes at a time. Baseline for every x86-64 ever made.
+    Sse2 = 2,
+    /// 32 bytes at a time. What the benchmarks were run on.
+    Avx2 = 3,
+    /// 16 bytes at a time, ARM style. Baseline for every aarch64 ever made.
+    Neon = 4,
+}
+
+// 0 = not detected yet.
+static LEVEL: AtomicU8 = AtomicU8::new(0);
+
+/// Override for the detected level: `TACHYON_SIMD=scalar|sse2|avx2|neon`.
+/// Anything the CPU can't do is ignored — asking for AVX2 without AVX2 gets you what the CPU has.
+pub const SIMD_ENV: &str = "TACHYON_SIMD";
+
+#[inline(always)]
+pub fn simd_level() -> SimdLevel {
+    match LEVEL.load(Ordering::Relaxed) {
+        1 => SimdLevel::Scalar,
+        2 => SimdLevel::Sse2,
+        3 => SimdLevel::Avx2,
+        4 => SimdLevel::Neon,
+        _ => init_simd_level(),
+    }
+}
+
+/// Detect (once) and log what we ended up with.
+#[cold]
+pub fn init_simd_level() -> SimdLevel {
+    let detected: SimdLevel = detect();
+    let wanted: Option<SimdLevel> = match std::env::var(SIMD_ENV).as_deref() {
+        Ok("scalar") => Some(SimdLevel::Scalar),
+        Ok("sse2") => Some(SimdLevel::Sse2),
+        Ok("avx2") => Some(SimdLevel::Avx2),
+        Ok("neon") => Some(SimdLevel::Neon),
+        _ => None,
+    };
+    let level: SimdLevel = match wanted {
+        Some(wanted) if supported(wanted) => wanted,
+        _ => detected,
+    };
+    if LEVEL.swap(level as u8, Ordering::Relaxed) != level as u8 {
+        info!("SIMD level: {:?} (detected {:?})", level, detected);
+    }
+    level
+}
+
+#[inline(always)]
+fn supported(level: SimdLevel) -> bool {
+    match level {
+        SimdLevel::Scalar => true,
+        #[cfg(target_arch = "x86_64")]
+        SimdLevel::Sse2 => true,
+        #[cfg(target_arch = "x86_64")]
+        SimdLevel::Avx2 => std::arch::is_x86_feature_detected!("avx2"),
+        #[cfg(target_arch = "aarch64")]
+        SimdLevel::Neon => std::arch::is_aarch64_feature_detected!("neon"),
+        #[allow(unreachable_patterns)]
+        _ => false,
+    }
+}
+
+#[inline(always)]
+fn detect() -> SimdLevel {
+    [SimdLevel::Avx2, SimdLevel::Sse2, SimdLevel::Neon]
+        .into_iter()
+        .find(|level| supported(*level))
+        .unwrap_or(SimdLevel::Scalar)
+}
@@ -1,3 +1,4 @@
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
 use core::arch::asm;
 use tracing::info;
 
@@ -6,6 +7,7 @@ use tracing::info;
 // You can find the original genius here: https://github.com/errantmind/faf
 // I didn’t write this, I just had the good sense to copy it.
 
+#[cfg(target_arch = "x86_64")]
 #[inline(always)]
 pub fn sys_call5(
     mut num: isize,
@@ -33,6 +35,46 @@ pub fn sys_call5(
     }
 }
 
+// Same thing on ARM: number in x8, arguments in x0..x4, `svc #0`, result comes back in x0.
+#[cfg(target_arch = "aarch64")]
+#[inline(always)]
+pub fn sys_call5(
+    num: isize,
+    arg1: isize,
+    arg2: isize,
+    arg3: isize,
+    arg4: isize,
+    arg5: isize,
+) -> isize {
+    let ret: isize;
+    unsafe {
+        asm!(
+        "svc #0",
+        in("x8") num,
+        inlateout("x0") arg1 => ret,
+        in("x1") arg2,
+        in("x2") arg3,
+        in("x3") arg4,
+        in("x4") arg5,
+        options(nostack, preserves_flags));
+    }
+    ret
+}
+
+// Anything else: fine, libc. Cowardice is portable.
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+#[inline(always)]
+pub fn sys_call5(
+    num: isize,
+    arg1: isize,
+    arg2: isize,
+    arg3: isize,
+    arg4: isize,
+    arg5: isize,
+) -> isize {
+    unsafe { libc::syscall(num as libc::c_long, arg1, arg2, arg3, arg4, arg5) as isize }
+}
+
 // Thanks a lot errantmind for cool sock solution!
 // Author: https://github.com/errantmind/faf
 
@@ -50,7 +92,8 @@ struct SockFprog {
     pub filter: *mut SockFilter,
 }
 
-pub const SYS_SETSOCKOPT: u32 = 54;
+// 54 on x86-64, 208 on aarch64. libc knows which one you're on.
+pub const SYS_SETSOCKOPT: u32 = libc::SYS_setsockopt as u32;
 pub const SOL_SOCKET: i32 = 1;
 pub const SO_ATTACH_REUSEPORT_CBPF: i32 = 51;
 
@@ -0,0 +1,134 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// HDR-style latency histogram: exact below 64, then every power of two split into 64 buckets.
+// Any value lands within 1/64 (~1.6%) of where it belongs, all the way up to u64::MAX,
+// in a fixed 30K of counters. No allocation and no float math on record.
+
+const SUB_BITS: u32 = 6;
+const SUB_BUCKETS: u64 = 1 << SUB_BITS;
+// 64 exact slots, then 64 per power of two from 2^6 to 2^63.
+const BUCKETS: usize = (SUB_BUCKETS + (64 - SUB_BITS as u64) * SUB_BUCKETS) as usize;
+
+#[inline(always)]
+fn bucket(value: u64) -> usize {
+    if value < SUB_BUCKETS {
+        return value as usize;
+    }
+    let shift: u32 = 63 - value.leading_zeros() - SUB_BITS;
+    (SUB_BUCKETS + shift as u64 * SUB_BUCKETS + (value >> shift) - SUB_BUCKETS) as usize
+}
+
+// Largest value that lands in `index`. Quantiles err on the pessimistic side.
+fn highest(index: usize) -> u64 {
+    let index: u64 = index as u64;
+    if index < SUB_BUCKETS {
+        return index;
+    }
+    let shift: u64 = (index - SUB_BUCKETS) / SUB_BUCKETS;
+    let top: u64 = (index - SUB_BUCKETS) % SUB_BUCKETS + SUB_BUCKETS;
+    ((top + 1) << shift).wrapping_sub(1)
+}
+
+/// Counts of values, one owner. A worker's last second, or everyone's merged on demand.
+#[derive(Clone)]
+pub struct Histogram {
+    counts: Box<[u64]>,
+    total: u64,
+    sum: u64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            counts: vec![0; BUCKETS].into_boxed_slice(),
+            total: 0,
+            sum: 0,
+        }
+    }
+}
+
+impl Histogram {
+    /// `count` samples of `value` at once. Batched responses share their timing.
+    #[inline(always)]
+    pub fn record(&mut self, value: u64, count: u64) {
+        self.counts[bucket(value)] += count;
+        self.total += count;
+        self.sum = self.sum.saturating_add(value.saturating_mul(count));
+    }
+
+    #[inline(always)]
+    pub fn count(&self) -> u64 {
+        self.total
+    }
+
+    /// All samples added up. Saturates instead of wrapping, not that anyone waits that long.
+    #[inline(always)]
+    pub fn sum(&self) -> u64 {
+        self.sum
+    }
+
+    /// Value at quantile `q` (0.0 to 1.0). 0 if there's nothing recorded.
+    pub fn quantile(&self, q: f64) -> u64 {
+        if self.total == 0 {
+            return 0;
+        }
+        let rank: u64 = ((q.clamp(0.0, 1.0) * self.total as f64).ceil() as u64).max(1);
+        let mut seen: u64 = 0;
+        for (index, count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return highest(index);
+            }
+        }
+        highest(BUCKETS - 1)
+    }
+
+    pub fn clear(&mut self) {
+        if self.total != 0 {
+            self.counts.fill(0);
+            self.total = 0;
+            self.sum = 0;
+        }
+    }
+}
+
+/// Same counters, shared. One writer tips a `Histogram` in, anyone reads.
+pub struct AtomicHistogram {
+    counts: Box<[AtomicU64]>,
+    sum: AtomicU64,
+}
+
+impl Default for AtomicHistogram {
+    fn default() -> Self {
+        Self {
+            counts: (0..BUCKETS).map(|_| AtomicU64::new(0)).collect(),
+            sum: AtomicU64::new(0),
+        }
+    }
+}
+
+impl AtomicHistogram {
+    /// Add everything in `local`. Empty buckets are skipped, which is most of them.
+    pub fn add(&self, local: &Histogram) {
+        if local.total == 0 {
+            return;
+        }
+        for (counter, count) in self.counts.iter().zip(local.counts.iter()) {
+            if *count != 0 {
+                counter.fetch_add(*count, Ordering::Relaxed);
+            }
+        }
+        self.sum.fetch_add(local.sum, Ordering::Relaxed);
+    }
+
+    /// Pour a snapshot into `into`. A racing writer may leave `sum` a second ahead of the
+    /// counts. The total is recounted from the buckets, so quantiles never walk off the end.
+    pub fn merge_into(&self, into: &mut Histogram) {
+        for (count, counter) in into.counts.iter_mut().zip(self.counts.iter()) {
+            let loaded: u64 = counter.load(Ordering::Relaxed);
+            *count += loaded;
+            into.total += loaded;
+        }
+        into.sum = into.sum.saturating_add(self.sum.load(Ordering::Relaxed));
+    }
+}
@@ -1,7 +1,16 @@
+use crate::library::utils::cpu::{simd_level, SimdLevel};
+#[cfg(target_arch = "x86_64")]
 use std::arch::x86_64::{
-    __m256i, _mm256_and_si256, _mm256_cmpeq_epi8,
-    _mm256_loadu_si256, _mm256_movemask_epi8, _mm256_set1_epi8,
+    __m128i, __m256i, _mm256_and_si256, _mm256_cmpeq_epi8, _mm256_loadu_si256,
+    _mm256_movemask_epi8, _mm256_set1_epi8, _mm_and_si128, _mm_cmpeq_epi8, _mm_loadu_si128,
+    _mm_movemask_epi8, _mm_set1_epi8,
 };
+#[cfg(target_arch = "aarch64")]
+use std::arch::aarch64::{
+    uint8x16_t, vaddv_u8, vandq_u8, vceqq_u8, vdupq_n_u8, vget_high_u8, vget_low_u8, vld1q_u8,
+};
+
+pub const MAX_HEADERS: usize = 32;
 // Welcome to the hot path. This function lives in a tight loop and eats CPU for breakfast.
 // Touch it, and the benchmark gods will smite you.
 //
@@ -10,8 +19,85 @@ use std::arch::x86_64::{
 
 pub type RequestEntry<'a> = (&'a [u8], &'a [u8]); // (method, path). Everything else is lies.
 
-#[target_feature(enable = "avx2")]
+/// Method and path of the request line at `ptr`, found within a 32-byte window.
+/// Returns `((method_off, method_len), (path_off, path_len), _)`.
+///
+/// # Safety:
+/// `ptr` must be readable for 32 bytes.
+#[inline(always)]
 pub unsafe fn parse_one_manual(ptr: *const u8) -> Option<((usize, u8), (usize, u8), usize)> {
+    match simd_level() {
+        #[cfg(target_arch = "x86_64")]
+        SimdLevel::Avx2 => parse_one_manual_avx2(ptr),
+        #[cfg(target_arch = "x86_64")]
+        SimdLevel::Sse2 => parse_one_manual_sse2(ptr),
+        #[cfg(target_arch = "aarch64")]
+        SimdLevel::Neon => parse_one_manual_neon(ptr),
+        _ => parse_one_manual_scalar(ptr),
+    }
+}
+
+// Whatever flavour found the spaces, the answer is decided the same way:
+// method ends at the first space, path at the next one. Both inside the window.
+#[inline(always)]
+fn split_request_line(space_mask: u32) -> Option<((usize, u8), (usize, u8), usize)> {
+    if space_mask == 0 {
+        return None;
+    }
+    let method_len: u8 = space_mask.trailing_zeros() as u8;
+    let path_start: usize = method_len as usize + 1;
+    let mask2: u32 = space_mask.checked_shr(path_start as u32).unwrap_or(0);
+    if mask2 == 0 {
+        return None;
+    }
+    let path_len: u8 = mask2.trailing_zeros() as u8;
+    Some(((0, method_len), (path_start, path_len), 0))
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse2")]
+pub unsafe fn parse_one_manual_sse2(ptr: *const u8) -> Option<((usize, u8), (usize, u8), usize)> {
+    let space: __m128i = _mm_set1_epi8(b' ' as i8);
+    let lo: __m128i = _mm_loadu_si128(ptr as *const __m128i);
+    let hi: __m128i = _mm_loadu_si128(ptr.add(16) as *const __m128i);
+    let mask: u32 = (_mm_movemask_epi8(_mm_cmpeq_epi8(lo, space)) as u32 & 0xFFFF)
+        | ((_mm_movemask_epi8(_mm_cmpeq_epi8(hi, space)) as u32 & 0xFFFF) << 16);
+    split_request_line(mask)
+}
+
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "neon")]
+pub unsafe fn parse_one_manual_neon(ptr: *const u8) -> Option<((usize, u8), (usize, u8), usize)> {
+    let space: uint8x16_t = vdupq_n_u8(b' ');
+    let lo: u32 = movemask_neon(vceqq_u8(vld1q_u8(ptr), space));
+    let hi: u32 = movemask_neon(vceqq_u8(vld1q_u8(ptr.add(16)), space));
+    split_request_line(lo | (hi << 16))
+}
+
+// NEON has no movemask. Keep one weight bit per lane, then add each half up —
+// the sum of distinct powers of two is just the mask. Ugly, but three instructions.
+#[cfg(target_arch = "aarch64")]
+#[inline(always)]
+unsafe fn movemask_neon(eq: uint8x16_t) -> u32 {
+    const WEIGHTS: [u8; 16] = [1, 2, 4, 8, 16, 32, 64, 128, 1, 2, 4, 8, 16, 32, 64, 128];
+    let bits: uint8x16_t = vandq_u8(eq, vld1q_u8(WEIGHTS.as_ptr()));
+    let lo: u32 = vaddv_u8(vget_low_u8(bits)) as u32;
+    let hi: u32 = vaddv_u8(vget_high_u8(bits)) as u32;
+    lo | (hi << 8)
+}
+
+pub unsafe fn parse_one_manual_scalar(ptr: *const u8) -> Option<((usize, u8), (usize, u8), usize)> {
+    let window: &[u8] = std::slice::from_raw_parts(ptr, 32);
+    let mut mask: u32 = 0;
+    for (i, byte) in window.iter().enumerate() {
+        mask |= ((*byte == b' ') as u32) << i;
+    }
+    split_request_line(mask)
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+pub unsafe fn parse_one_manual_avx2(ptr: *const u8) -> Option<((usize, u8), (usize, u8), usize)> {
     let first8: u64 = *(ptr as *const u64);
     const GET_MASK: u64 = u64::from_le_bytes(*b"GET \0\0\0\0");
     const POST_MASK: u64 = u64::from_le_bytes(*b"POST \0\0\0");
@@ -47,19 +133,120 @@ pub unsafe fn parse_one_manual(ptr: *const u8) -> Option<((usize, u8), (usize, u
     let space: __m256i = _mm256_set1_epi8(b' ' as i8);
     let cmp: __m256i = _mm256_cmpeq_epi8(chunk, space);
     let mask: u32 = _mm256_movemask_epi8(cmp) as u32;
-    if mask == 0 {
-        return None;
+    split_request_line(mask)
+}
+
+/// Offsets of everything that looks like the start of a `GET`/`POST` request,
+/// in order, at most `N` of them.
+#[inline(always)]
+pub fn find_request_starts<const N: usize>(buf: &[u8]) -> ([usize; N], usize) {
+    unsafe {
+        match simd_level() {
+            #[cfg(target_arch = "x86_64")]
+            SimdLevel::Avx2 => find_request_starts_avx2::<N>(buf),
+            #[cfg(target_arch = "x86_64")]
+            SimdLevel::Sse2 => find_request_starts_sse2::<N>(buf),
+            #[cfg(target_arch = "aarch64")]
+            SimdLevel::Neon => find_request_starts_neon::<N>(buf),
+            _ => find_request_starts_scalar::<N>(buf),
+        }
     }
-    let method_len: u8 = mask.trailing_zeros() as u8;
-    let path_start: usize = method_len as usize + 1;
-    let mask2: u32 = mask >> path_start;
-    if mask2 == 0 {
-        return None;
+}
+
+#[inline(always)]
+pub fn find_request_starts_scalar<const N: usize>(buf: &[u8]) -> ([usize; N], usize) {
+    find_request_starts_from::<N>(buf, 0, [0usize; N], 0)
+}
+
+// Byte-at-a-time search from `i` on. Also mops up the tail after the vector loops.
+#[inline(always)]
+fn find_request_starts_from<const N: usize>(
+    buf: &[u8],
+    mut i: usize,
+    mut found: [usize; N],
+    mut count: usize,
+) -> ([usize; N], usize) {
+    while i + 3 <= buf.len() && count < N {
+        let head: &[u8] = &buf[i..i + 3];
+        if head == b"GET" || head == b"POS" {
+            found[count] = i;
+            count += 1;
+        }
+        i += 1;
     }
-    let path_len: u8 = mask2.trailing_zeros() as u8;
-    Some(((0, method_len), (path_start, path_len), 0))
+    (found, count)
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse2")]
+pub unsafe fn find_request_starts_sse2<const N: usize>(buf: &[u8]) -> ([usize; N], usize) {
+    let mut found: [usize; N] = [0usize; N];
+    let mut count: usize = 0;
+    let len: usize = buf.len();
+    let ptr: *const u8 = buf.as_ptr();
+    let mut i: usize = 0;
+    while i + 18 <= len && count < N {
+        let c0: __m128i = _mm_loadu_si128(ptr.add(i) as *const __m128i);
+        let c1: __m128i = _mm_loadu_si128(ptr.add(i + 1) as *const __m128i);
+        let c2: __m128i = _mm_loadu_si128(ptr.add(i + 2) as *const __m128i);
+        let get_mask: u32 = _mm_movemask_epi8(_mm_and_si128(
+            _mm_cmpeq_epi8(c0, _mm_set1_epi8(b'G' as i8)),
+            _mm_and_si128(
+                _mm_cmpeq_epi8(c1, _mm_set1_epi8(b'E' as i8)),
+                _mm_cmpeq_epi8(c2, _mm_set1_epi8(b'T' as i8)),
+            ),
+        )) as u32;
+        let post_mask: u32 = _mm_movemask_epi8(_mm_and_si128(
+            _mm_cmpeq_epi8(c0, _mm_set1_epi8(b'P' as i8)),
+            _mm_and_si128(
+                _mm_cmpeq_epi8(c1, _mm_set1_epi8(b'O' as i8)),
+                _mm_cmpeq_epi8(c2, _mm_set1_epi8(b'S' as i8)),
+            ),
+        )) as u32;
+        let mut bits: u32 = get_mask | post_mask;
+        while bits != 0 && count < N {
+            found[count] = i + bits.trailing_zeros() as usize;
+            count += 1;
+            bits &= bits - 1;
+        }
+        i += 16;
+    }
+    find_request_starts_from::<N>(buf, i, found, count)
+}
+
+/// `find_request_starts_sse2` for ARM. Same 16-byte steps, same tail.
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "neon")]
+pub unsafe fn find_request_starts_neon<const N: usize>(buf: &[u8]) -> ([usize; N], usize) {
+    let mut found: [usize; N] = [0usize; N];
+    let mut count: usize = 0;
+    let len: usize = buf.len();
+    let ptr: *const u8 = buf.as_ptr();
+    let mut i: usize = 0;
+    while i + 18 <= len && count < N {
+        let c0: uint8x16_t = vld1q_u8(ptr.add(i));
+        let c1: uint8x16_t = vld1q_u8(ptr.add(i + 1));
+        let c2: uint8x16_t = vld1q_u8(ptr.add(i + 2));
+        let get: uint8x16_t = vandq_u8(
+            vceqq_u8(c0, vdupq_n_u8(b'G')),
+            vandq_u8(vceqq_u8(c1, vdupq_n_u8(b'E')), vceqq_u8(c2, vdupq_n_u8(b'T'))),
+        );
+        let post: uint8x16_t = vandq_u8(
+            vceqq_u8(c0, vdupq_n_u8(b'P')),
+            vandq_u8(vceqq_u8(c1, vdupq_n_u8(b'O')), vceqq_u8(c2, vdupq_n_u8(b'S'))),
+        );
+        let mut bits: u32 = movemask_neon(get) | movemask_neon(post);
+        while bits != 0 && count < N {
+            found[count] = i + bits.trailing_zeros() as usize;
+            count += 1;
+            bits &= bits - 1;
+        }
+        i += 16;
+    }
+    find_request_starts_from::<N>(buf, i, found, count)
 }
 
+#[cfg(target_arch = "x86_64")]
 #[target_feature(enable = "avx2")]
 pub unsafe fn find_request_starts_avx2<const N: usize>(buf: &[u8]) -> ([usize; N], usize) {
     let mut found: [usize; N] = [0usize; N];
@@ -159,7 +346,21 @@ pub unsafe fn find_request_starts_avx2<const N: usize>(buf: &[u8]) -> ([usize; N
         }
         i += 32;
     }
-    (found, count)
+    // The last few bytes are too short for a vector load. Finish them the boring way.
+    find_request_starts_from::<N>(buf, i, found, count)
+}
+
+/// Slow lane for what `parse_one_manual` can't see: a request that starts less than
+/// 32 bytes before the end of the buffer, or a path that runs past the 32-byte window.
+#[inline(always)]
+pub fn parse_one_scalar(buf: &[u8]) -> Option<RequestEntry<'_>> {
+    let method_len: usize = memchr::memchr(b' ', buf)?;
+    let rest: &[u8] = &buf[method_len + 1..];
+    let path_len: usize = memchr::memchr(b' ', rest)?;
+    if method_len == 0 || path_len == 0 {
+        return None;
+    }
+    Some((&buf[..method_len], &rest[..path_len]))
 }
 #[derive(Copy, Clone, Default, Debug)]
 pub struct RequestRawEntry {
@@ -175,24 +376,373 @@ pub unsafe fn parse_http_methods_paths<'a, const N: usize>(
 ) -> ([RequestEntry<'a>; N], usize) {
     let mut out: [RequestEntry<'a>; N] = [(&[][..], &[][..]); N];
     let mut count: usize = 0;
-    let (starts, total): ([usize; N], usize) = find_request_starts_avx2::<N>(buf);
+    let (starts, total): ([usize; N], usize) = find_request_starts::<N>(buf);
 
     for i in 0..total {
         let start: usize = starts[i];
-        if start + 32 > buf.len() {
-            break;
-        }
-        let raw: *const u8 = buf.as_ptr().add(start);
-        if let Some(((m_off, m_len), (p_off, p_len), _adv)) = parse_one_manual(raw) {
-            let m_ptr = buf.as_ptr().add(start + m_off);
-            let p_ptr = buf.as_ptr().add(start + p_off);
-            out[count] = (
-                core::slice::from_raw_parts(m_ptr, m_len as usize),
-                core::slice::from_raw_parts(p_ptr, p_len as usize),
-            );
+        if start + 32 <= buf.len() {
+            let raw: *const u8 = buf.as_ptr().add(start);
+            if let Some(((m_off, m_len), (p_off, p_len), _adv)) = parse_one_manual(raw) {
+                let m_ptr = buf.as_ptr().add(start + m_off);
+                let p_ptr = buf.as_ptr().add(start + p_off);
+                out[count] = (
+                    core::slice::from_raw_parts(m_ptr, m_len as usize),
+                    core::slice::from_raw_parts(p_ptr, p_len as usize),
+                );
+                count += 1;
+                continue;
+            }
+        }
+        // Too close to the end, or the path is longer than the window. Take the stairs.
+        if let Some(entry) = parse_one_scalar(&buf[start..]) {
+            out[count] = entry;
             count += 1;
         }
     }
 
     (out, count)
 }
+
+/// Fixed-capacity header table. Names and values are borrowed straight from the kernel
+/// buffer, values are already stripped of surrounding whitespace.
+#[derive(Copy, Clone, Debug)]
+pub struct Headers<'a> {
+    entries: [(&'a [u8], &'a [u8]); MAX_HEADERS],
+    len: usize,
+}
+
+impl<'a> Default for Headers<'a> {
+    #[inline(always)]
+    fn default() -> Self {
+        Self {
+            entries: [(&[][..], &[][..]); MAX_HEADERS],
+            len: 0,
+        }
+    }
+}
+
+impl<'a> Headers<'a> {
+    /// Case-insensitive lookup, first match wins.
+    #[inline(always)]
+    pub fn get(&self, name: &[u8]) -> Option<&'a [u8]> {
+        self.entries[..self.len]
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| *v)
+    }
+    #[inline(always)]
+    pub fn iter(&self) -> impl Iterator<Item = (&'a [u8], &'a [u8])> + '_ {
+        self.entries[..self.len].iter().copied()
+    }
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    /// How the body of this request is delimited. Conflicting or garbage
+    /// `Content-Length` values are `Invalid` — that's how request smuggling starts.
+    #[inline(always)]
+    pub fn body_framing(&self) -> BodyFraming {
+        let mut length: Option<usize> = None;
+        let mut chunked: Option<bool> = None;
+        for (name, value) in self.iter() {
+            if name.eq_ignore_ascii_case(b"transfer-encoding") {
+                // Only the last coding matters for framing, and it'd better be `chunked`.
+                let last: &[u8] = value.rsplit(|b| *b == b',').next().unwrap_or(value);
+                chunked = Some(trim_ows(last).eq_ignore_ascii_case(b"chunked"));
+                continue;
+            }
+            if !name.eq_ignore_ascii_case(b"content-length") {
+                continue;
+            }
+            let len: usize = match parse_decimal(value) {
+                Some(len) => len,
+                None => return BodyFraming::Invalid,
+            };
+            match length {
+                Some(prev) if prev != len => return BodyFraming::Invalid,
+                _ => length = Some(len),
+            }
+        }
+        match (chunked, length) {
+            // Both at once is the classic smuggling setup. Hard no.
+            (Some(_), Some(_)) => BodyFraming::Invalid,
+            (Some(true), None) => BodyFraming::Chunked,
+            (Some(false), None) => BodyFraming::Unsupported,
+            (None, Some(len)) => BodyFraming::Length(len),
+            (None, None) => BodyFraming::None,
+        }
+    }
+    #[inline(always)]
+    fn push(&mut self, name: &'a [u8], value: &'a [u8]) -> bool {
+        if self.len == MAX_HEADERS {
+            return false;
+        }
+        self.entries[self.len] = (name, value);
+        self.len += 1;
+        true
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BodyFraming {
+    None,
+    Length(usize),
+    Chunked,
+    /// `Transfer-Encoding` we don't speak (anything not ending in `chunked`).
+    Unsupported,
+    Invalid,
+}
+
+#[inline(always)]
+fn parse_decimal(value: &[u8]) -> Option<usize> {
+    if value.is_empty() || value.len() > 19 {
+        return None;
+    }
+    let mut result: usize = 0;
+    for &b in value {
+        if !b.is_ascii_digit() {
+            return None;
+        }
+        result = result * 10 + (b - b'0') as usize;
+    }
+    Some(result)
+}
+
+/// Request line + headers of a single request. `head_len` covers everything up to and
+/// including the empty line, so the body (if any) starts at `buf[head_len..]`.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct RequestHead<'a> {
+    pub method: &'a [u8],
+    pub path: &'a [u8],
+    pub version: &'a [u8],
+    pub headers: Headers<'a>,
+    pub head_len: usize,
+}
+
+#[derive(Debug)]
+pub enum HeadParse<'a> {
+    Complete(RequestHead<'a>),
+    /// Ran out of bytes before the empty line. Come back with more.
+    Partial,
+    /// Not HTTP. Not our problem anymore.
+    Invalid,
+    /// More than MAX_HEADERS headers. HTTP, just too much of it.
+    TooMany,
+}
+
+/// Finds the first `\n` at or after `from`, plus the first `:` before it (if any).
+#[inline(always)]
+pub fn scan_line(buf: &[u8], from: usize) -> (Option<usize>, Option<usize>) {
+    unsafe {
+        match simd_level() {
+            #[cfg(target_arch = "x86_64")]
+            SimdLevel::Avx2 => scan_line_avx2(buf, from),
+            #[cfg(target_arch = "x86_64")]
+            SimdLevel::Sse2 => scan_line_sse2(buf, from),
+            #[cfg(target_arch = "aarch64")]
+            SimdLevel::Neon => scan_line_neon(buf, from),
+            _ => scan_line_scalar(buf, from),
+        }
+    }
+}
+
+#[inline(always)]
+pub fn scan_line_scalar(buf: &[u8], from: usize) -> (Option<usize>, Option<usize>) {
+    scan_line_from(buf, from, None)
+}
+
+// One byte at a time from `from`, with whatever colon the caller already saw.
+#[inline(always)]
+fn scan_line_from(
+    buf: &[u8],
+    from: usize,
+    mut colon_at: Option<usize>,
+) -> (Option<usize>, Option<usize>) {
+    for (i, byte) in buf.iter().enumerate().skip(from) {
+        match *byte {
+            b'\n' => return (Some(i), colon_at),
+            b':' if colon_at.is_none() => colon_at = Some(i),
+            _ => {}
+        }
+    }
+    (None, colon_at)
+}
+
+/// Same as `scan_line_avx2`, 16 bytes per step.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse2")]
+pub unsafe fn scan_line_sse2(buf: &[u8], from: usize) -> (Option<usize>, Option<usize>) {
+    let len: usize = buf.len();
+    let ptr: *const u8 = buf.as_ptr();
+    let nl: __m128i = _mm_set1_epi8(b'\n' as i8);
+    let colon: __m128i = _mm_set1_epi8(b':' as i8);
+    let mut colon_at: Option<usize> = None;
+    let mut i: usize = from;
+    while i + 16 <= len {
+        let chunk: __m128i = _mm_loadu_si128(ptr.add(i) as *const __m128i);
+        let nl_mask: u32 = _mm_movemask_epi8(_mm_cmpeq_epi8(chunk, nl)) as u32;
+        let colon_mask: u32 = _mm_movemask_epi8(_mm_cmpeq_epi8(chunk, colon)) as u32;
+        if colon_at.is_none() && colon_mask != 0 {
+            let tz: usize = colon_mask.trailing_zeros() as usize;
+            if nl_mask == 0 || tz < nl_mask.trailing_zeros() as usize {
+                colon_at = Some(i + tz);
+            }
+        }
+        if nl_mask != 0 {
+            return (Some(i + nl_mask.trailing_zeros() as usize), colon_at);
+        }
+        i += 16;
+    }
+    scan_line_from(buf, i, colon_at)
+}
+
+/// Same as `scan_line_sse2`, NEON edition.
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "neon")]
+pub unsafe fn scan_line_neon(buf: &[u8], from: usize) -> (Option<usize>, Option<usize>) {
+    let len: usize = buf.len();
+    let ptr: *const u8 = buf.as_ptr();
+    let nl: uint8x16_t = vdupq_n_u8(b'\n');
+    let colon: uint8x16_t = vdupq_n_u8(b':');
+    let mut colon_at: Option<usize> = None;
+    let mut i: usize = from;
+    while i + 16 <= len {
+        let chunk: uint8x16_t = vld1q_u8(ptr.add(i));
+        let nl_mask: u32 = movemask_neon(vceqq_u8(chunk, nl));
+        let colon_mask: u32 = movemask_neon(vceqq_u8(chunk, colon));
+        if colon_at.is_none() && colon_mask != 0 {
+            let tz: usize = colon_mask.trailing_zeros() as usize;
+            if nl_mask == 0 || tz < nl_mask.trailing_zeros() as usize {
+                colon_at = Some(i + tz);
+            }
+        }
+        if nl_mask != 0 {
+            return (Some(i + nl_mask.trailing_zeros() as usize), colon_at);
+        }
+        i += 16;
+    }
+    scan_line_from(buf, i, colon_at)
+}
+
+/// 32 bytes per step, the tail is done byte by byte like in the old days.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+pub unsafe fn scan_line_avx2(buf: &[u8], from: usize) -> (Option<usize>, Option<usize>) {
+    let len: usize = buf.len();
+    let ptr: *const u8 = buf.as_ptr();
+    let nl: __m256i = _mm256_set1_epi8(b'\n' as i8);
+    let colon: __m256i = _mm256_set1_epi8(b':' as i8);
+    let mut colon_at: Option<usize> = None;
+    let mut i: usize = from;
+    while i + 32 <= len {
+        let chunk: __m256i = _mm256_loadu_si256(ptr.add(i) as *const __m256i);
+        let nl_mask: u32 = _mm256_movemask_epi8(_mm256_cmpeq_epi8(chunk, nl)) as u32;
+        let colon_mask: u32 = _mm256_movemask_epi8(_mm256_cmpeq_epi8(chunk, colon)) as u32;
+        if colon_at.is_none() && colon_mask != 0 {
+            let tz: usize = colon_mask.trailing_zeros() as usize;
+            // Only counts if it shows up before the end of the line.
+            if nl_mask == 0 || tz < nl_mask.trailing_zeros() as usize {
+                colon_at = Some(i + tz);
+            }
+        }
+        if nl_mask != 0 {
+            return (Some(i + nl_mask.trailing_zeros() as usize), colon_at);
+        }
+        i += 32;
+    }
+    scan_line_from(buf, i, colon_at)
+}
+
+#[inline(always)]
+fn trim_ows(mut value: &[u8]) -> &[u8] {
+    while let [b' ' | b'\t' | b'\r', rest @ ..] = value {
+        value = rest;
+    }
+    while let [rest @ .., b' ' | b'\t' | b'\r'] = value {
+        value = rest;
+    }
+    value
+}
+
+/// Parses the request line and all headers of the request starting at `buf[0]`.
+pub fn parse_request_head(buf: &[u8]) -> HeadParse<'_> {
+    let mut head: RequestHead = RequestHead::default();
+    // Request line: METHOD SP PATH SP VERSION CRLF
+    let eol: usize = match scan_line(buf, 0).0 {
+        Some(eol) => eol,
+        None => return HeadParse::Partial,
+    };
+    let line: &[u8] = trim_ows(&buf[..eol]);
+    let method_end: usize = match memchr::memchr(b' ', line) {
+        Some(end) if end > 0 => end,
+        _ => return HeadParse::Invalid,
+    };
+    let rest: &[u8] = &line[method_end + 1..];
+    let path_end: usize = match memchr::memchr(b' ', rest) {
+        Some(end) if end > 0 => end,
+        _ => return HeadParse::Invalid,
+    };
+    head.method = &line[..method_end];
+    head.path = &rest[..path_end];
+    head.version = &rest[path_end + 1..];
+    if !head.version.starts_with(b"HTTP/") {
+        return HeadParse::Invalid;
+    }
+    // Header lines until the empty one.
+    match parse_headers(buf, eol + 1, &mut head.headers) {
+        HeadersParse::Complete(end) => {
+            head.head_len = end;
+            HeadParse::Complete(head)
+        }
+        HeadersParse::Partial => HeadParse::Partial,
+        HeadersParse::Invalid => HeadParse::Invalid,
+        HeadersParse::TooMany => HeadParse::TooMany,
+    }
+}
+
+#[derive(Debug)]
+pub enum HeadersParse {
+    /// Offset right past the terminating empty line.
+    Complete(usize),
+    Partial,
+    Invalid,
+    /// More than MAX_HEADERS of them.
+    TooMany,
+}
+
+/// Parses `Name: value` lines starting at `buf[pos]` until the empty line.
+/// Shared by request heads and chunked trailers.
+pub fn parse_headers<'a>(buf: &'a [u8], mut pos: usize, headers: &mut Headers<'a>) -> HeadersParse {
+    loop {
+        match buf.get(pos..pos + 2) {
+            Some(b"\r\n") => return HeadersParse::Complete(pos + 2),
+            Some([b'\n', _]) => return HeadersParse::Complete(pos + 1),
+            Some(_) => {}
+            None if buf.get(pos) == Some(&b'\n') => return HeadersParse::Complete(pos + 1),
+            None => return HeadersParse::Partial,
+        }
+        let (eol, colon): (Option<usize>, Option<usize>) = scan_line(buf, pos);
+        let eol: usize = match eol {
+            Some(eol) => eol,
+            None => return HeadersParse::Partial,
+        };
+        let colon: usize = match colon {
+            Some(colon) if colon > pos => colon,
+            _ => return HeadersParse::Invalid,
+        };
+        // `Content-Length : 5` is not Content-Length, and quietly treating it as some other
+        // header is how bodies turn into requests. RFC 9112 says 400, so 400 it is.
+        let name: &[u8] = &buf[pos..colon];
+        if name.iter().any(|b| matches!(b, b' ' | b'\t')) {
+            return HeadersParse::Invalid;
+        }
+        if !headers.push(name, trim_ows(&buf[colon + 1..eol])) {
+            return HeadersParse::TooMany;
+        }
+        pos = eol + 1;
+    }
+}
@@ -1,5 +1,5 @@
-use crate::library::server::BUFFER_SIZE;
 use libc::{LOG_EMERG, LOG_USER, PR_SET_NAME, closelog, openlog, prctl, syslog};
+#[cfg(target_arch = "x86_64")]
 use std::arch::asm;
 use std::ffi::CString;
 use std::fs::OpenOptions;
@@ -12,23 +12,25 @@ use tracing::error;
 // It copies a fixed-size kernel-owned buffer into `dst` using unaligned vector loads/stores.
 //
 // Notes:
-// - BUFFER_SIZE is assumed to be 6272 bytes
-// - Each iteration copies 32 bytes using `vmovdqu` (so we do 196 iterations)
+// - `buffer_size` comes from the pool and must be a multiple of 32 (the pool insists on 64)
+// - Each iteration copies 32 bytes using `vmovdqu` (so 7168 bytes is 224 iterations)
 // - We don’t care about alignment, borrow checker, or safety. Only speed and fire.
+#[cfg(target_arch = "x86_64")]
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn get_kernel_owned_buffer(
-    index: usize,                      // Which buffer to extract from the pool
-    buffers: *const [u8; BUFFER_SIZE], // Pointer to big slab of buffers
-    dst: *mut u8,                      // Destination to yeet the data into
+    index: usize,       // Which buffer to extract from the pool
+    buffers: *const u8, // Pointer to big slab of buffers
+    buffer_size: usize, // How big each of them is
+    dst: *mut u8,       // Destination to yeet the data into
 ) {
     asm!(
-        // Calculate offset: rcx = index * 6272
-        "imul rcx, rdi, 6272", // Because multiplying by BUFFER_SIZE manually is faster than thinking
+        // Calculate offset: rcx = index * buffer_size
+        "imul rdi, rcx", // Multiplying at runtime. Still faster than thinking
         // r10 = &buffers[index]
-        "lea r10, [rsi + rcx]", // Just casually pointer-walking in raw AVX land
-        "xor r8, r8", // r8 = offset counter (from 0 to 6272)
+        "lea r10, [rsi + rdi]", // Just casually pointer-walking in raw AVX land
+        "xor r8, r8", // r8 = offset counter (from 0 to buffer_size)
         "2:", // Label: start copy loop
-        "cmp r8, 6272", // Have we copied it all?
+        "cmp r8, rcx", // Have we copied it all?
         "jge 3f", // If yes, jump to done
         // ymm0 = *(r10 + r8)
         "vmovdqu ymm0, [r10 + r8]", // Load 32 bytes from source (unaligned, because life is too short to align)
@@ -38,23 +40,35 @@ pub unsafe extern "C" fn get_kernel_owned_buffer(
         "3:", // Label: done
         // Clean up AVX state because AVX-512 people get cranky
         "vzeroupper", // Avoid transition penalty into legacy SSE code. Intel says this matters. We believe them.
-        in("rdi") index, // buffer index
+        inout("rdi") index => _, // buffer index, then its offset
         in("rsi") buffers, // &buffers
         in("rdx") dst,  // destination pointer
-        out("rcx") _, // Clobbered: offset multiplier
+        in("rcx") buffer_size, // bytes per buffer
         out("r10") _, // Clobbered: actual buffer pointer
         out("r8") _, // Clobbered: loop counter
         out("ymm0") _,  // Clobbered: temp vector register
-        options(nostack, preserves_flags), // We solemnly swear not to mess with the stack
+        options(nostack), // We solemnly swear not to mess with the stack. Flags are fair game
     );
 }
 
+// Same heist for everyone without `vmovdqu`. memcpy is plenty fast on ARM.
+#[cfg(not(target_arch = "x86_64"))]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn get_kernel_owned_buffer(
+    index: usize,
+    buffers: *const u8,
+    buffer_size: usize,
+    dst: *mut u8,
+) {
+    std::ptr::copy_nonoverlapping(buffers.add(index * buffer_size), dst, buffer_size);
+}
+
 pub unsafe fn log_kernel_error(message: &str, code: &str) {
     openlog(std::ptr::null(), 0, LOG_USER);
     syslog(
         LOG_EMERG,
-        b"%s\0".as_ptr() as *const i8,
-        message.as_ptr() as *const i8,
+        b"%s\0".as_ptr() as *const libc::c_char,
+        message.as_ptr() as *const libc::c_char,
     );
     closelog();
 
@@ -1,6 +1,9 @@
-use std::{
-    arch::x86_64::{__m256i, _mm256_setzero_si256, _mm256_storeu_si256},
-    ptr,
+use crate::library::utils::cpu::{simd_level, SimdLevel};
+use std::ptr;
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::{
+    __m128i, __m256i, _mm256_setzero_si256, _mm256_storeu_si256, _mm_setzero_si128,
+    _mm_storeu_si128,
 };
 
 // Flattens a slice of iovecs into a single `&mut [u8]`.
@@ -47,6 +50,7 @@ pub unsafe fn flatten_iovec(iovecs: &[libc::iovec], out: &mut Vec<u8>) {
 // - AVX2-capable CPU
 // - You know what you’re doing (you probably don’t)
 // - Alignment? Who cares? We're using unaligned stores and hoping for the best.
+#[cfg(target_arch = "x86_64")]
 #[target_feature(enable = "avx2")]
 pub unsafe fn avx2_zero(buf: *mut u8, len: usize) {
     let mut ptr = buf;
@@ -63,3 +67,32 @@ pub unsafe fn avx2_zero(buf: *mut u8, len: usize) {
         ptr::write_bytes(ptr, 0, end.offset_from(ptr) as usize);
     }
 }
+
+// Zero `len` bytes at `buf` with whatever the CPU has to offer. Same contract as `avx2_zero`,
+// minus the AVX2 requirement.
+#[inline(always)]
+pub unsafe fn simd_zero(buf: *mut u8, len: usize) {
+    match simd_level() {
+        #[cfg(target_arch = "x86_64")]
+        SimdLevel::Avx2 => avx2_zero(buf, len),
+        #[cfg(target_arch = "x86_64")]
+        SimdLevel::Sse2 => sse2_zero(buf, len),
+        _ => ptr::write_bytes(buf, 0, len),
+    }
+}
+
+// `avx2_zero` on a budget: 16 bytes per store.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse2")]
+pub unsafe fn sse2_zero(buf: *mut u8, len: usize) {
+    let mut ptr = buf;
+    let end = buf.add(len);
+    let zero = _mm_setzero_si128();
+    while ptr.add(16) <= end {
+        _mm_storeu_si128(ptr as *mut __m128i, zero);
+        ptr = ptr.add(16);
+    }
+    if ptr < end {
+        ptr::write_bytes(ptr, 0, end.offset_from(ptr) as usize);
+    }
+}
@@ -1,8 +1,14 @@
+pub mod buffer_pool;
+pub mod chunked;
+pub mod compat;
+pub mod cpu;
 pub mod faf_helpers;
+pub mod histogram;
 pub mod http;
 pub mod kernel;
 pub mod memory;
 pub mod shift;
+pub mod signals;
 pub mod trim;
 
 pub fn print_as_const(bytes: &[u8], name: &str) {
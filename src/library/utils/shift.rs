@@ -1,5 +1,8 @@
-use crate::library::server::BUFFER_SIZE;
-use std::arch::x86_64::{__m256i, _mm256_loadu_si256, _mm256_storeu_si256};
+use crate::library::utils::cpu::{simd_level, SimdLevel};
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::{
+    __m128i, __m256i, _mm256_loadu_si256, _mm256_storeu_si256, _mm_loadu_si128, _mm_storeu_si128,
+};
 
 #[inline(always)]
 pub unsafe fn shift_safe(buf: &mut [u8], len: usize) {
@@ -14,14 +17,58 @@ pub unsafe fn shift_safe(buf: &mut [u8], len: usize) {
     }
 }
 
+/// Copy the first `len` bytes to the end of a fresh zeroed buffer as long as `buf`, SIMD flavour
+/// picked at runtime.
+///
+/// # Safety:
+/// `len` must not exceed `buf.len()`.
+#[inline(always)]
+pub unsafe fn shift_ub(buf: &mut [u8], len: usize) -> Vec<u8> {
+    match simd_level() {
+        #[cfg(target_arch = "x86_64")]
+        SimdLevel::Avx2 => shift_ub_avx2(buf, len),
+        #[cfg(target_arch = "x86_64")]
+        SimdLevel::Sse2 => shift_ub_sse2(buf, len),
+        _ => shift_ub_scalar(buf, len),
+    }
+}
+
+#[inline(always)]
+pub unsafe fn shift_ub_scalar(buf: &mut [u8], len: usize) -> Vec<u8> {
+    // The migration ritual, performed by hand. Still the same ritual.
+    let mut dst_vec: Vec<u8> = vec![0u8; buf.len()];
+    dst_vec[buf.len() - len..].copy_from_slice(&buf[..len]);
+    dst_vec
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse2")]
+pub unsafe fn shift_ub_sse2(buf: &mut [u8], len: usize) -> Vec<u8> {
+    let src: *const u8 = buf.as_ptr();
+    let mut dst_vec: Vec<u8> = vec![0u8; buf.len()];
+    let dst: *mut u8 = dst_vec.as_mut_ptr().add(buf.len() - len);
+    let mut i = 0;
+    while i + 16 <= len {
+        let chunk: __m128i = _mm_loadu_si128(src.add(i) as *const __m128i);
+        _mm_storeu_si128(dst.add(i) as *mut __m128i, chunk);
+        i += 16;
+    }
+    while i < len {
+        *dst.add(i) = *src.add(i);
+        i += 1;
+    }
+    dst_vec
+}
+
+#[cfg(target_arch = "x86_64")]
 #[target_feature(enable = "avx2")]
-pub unsafe fn shift_ub(buf: &mut [u8], len: usize) -> [u8; BUFFER_SIZE] {
+pub unsafe fn shift_ub_avx2(buf: &mut [u8], len: usize) -> Vec<u8> {
     // Vectorized arcane dance: lift the front, drop it at the end.
     // AVX2 priests bless this operation.
     let src: *const u8 = buf.as_ptr();
     // Allocate the holy destination altar (entirely zeroed, as is tradition)
-    let mut dst_vec: [u8; BUFFER_SIZE] = [0u8; BUFFER_SIZE];
-    let dst: *mut u8 = dst_vec.as_mut_ptr().add(BUFFER_SIZE - len);
+    let mut dst_vec: Vec<u8> = vec![0u8; buf.len()];
+    let dst: *mut u8 = dst_vec.as_mut_ptr().add(buf.len() - len);
     // Bring in the AVX2 death squad
     let mut i = 0;
     while i + 32 <= len {
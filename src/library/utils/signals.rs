@@ -0,0 +1,139 @@
+use libc::{
+    c_void, eventfd, poll, pollfd, pthread_sigmask, read, sigaddset, sigemptyset, signalfd,
+    signalfd_siginfo, sigset_t, write, EFD_CLOEXEC, EFD_NONBLOCK, POLLIN, SFD_CLOEXEC, SIGINT,
+    SIGTERM, SIG_BLOCK, SIG_SETMASK,
+};
+use std::{io, mem, os::fd::RawFd, time::Duration};
+
+// How the outside world tells us to wrap it up.
+//
+// SIGTERM/SIGINT are blocked before any worker is spawned (threads inherit the mask), so
+// nobody gets killed mid-send — the signals pile up in a signalfd the main thread reads.
+// Workers never see a signal. They watch an eventfd instead, one `POLL_ADD` per ring.
+// Nobody ever reads the eventfd, so once it's poked it stays readable for everyone —
+// including a worker that restarts after the fact.
+
+/// Why `ShutdownSignal::wait` woke up.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Wakeup {
+    /// SIGTERM or SIGINT, by number.
+    Signal(i32),
+    /// The extra fd turned readable.
+    Fd,
+    Timeout,
+}
+
+/// Signal plumbing for one `run()`. Dropping it closes both fds and restores the old mask.
+pub struct ShutdownSignal {
+    signal_fd: RawFd,
+    event_fd: RawFd,
+    old_mask: sigset_t,
+}
+
+impl ShutdownSignal {
+    /// Block SIGTERM/SIGINT for the calling thread (and every thread it spawns from now on)
+    /// and route them into a signalfd.
+    pub fn install() -> io::Result<Self> {
+        unsafe {
+            let mut mask: sigset_t = mem::zeroed();
+            sigemptyset(&mut mask);
+            sigaddset(&mut mask, SIGTERM);
+            sigaddset(&mut mask, SIGINT);
+            let mut old_mask: sigset_t = mem::zeroed();
+            let err: i32 = pthread_sigmask(SIG_BLOCK, &mask, &mut old_mask);
+            if err != 0 {
+                return Err(io::Error::from_raw_os_error(err));
+            }
+            let signal_fd: RawFd = signalfd(-1, &mask, SFD_CLOEXEC);
+            if signal_fd < 0 {
+                let err: io::Error = io::Error::last_os_error();
+                pthread_sigmask(SIG_SETMASK, &old_mask, std::ptr::null_mut());
+                return Err(err);
+            }
+            let event_fd: RawFd = eventfd(0, EFD_CLOEXEC | EFD_NONBLOCK);
+            if event_fd < 0 {
+                let err: io::Error = io::Error::last_os_error();
+                libc::close(signal_fd);
+                pthread_sigmask(SIG_SETMASK, &old_mask, std::ptr::null_mut());
+                return Err(err);
+            }
+            Ok(Self {
+                signal_fd,
+                event_fd,
+                old_mask,
+            })
+        }
+    }
+
+    /// The fd workers poll. Readable means "stop accepting, finish up, go home".
+    #[inline(always)]
+    pub fn event_fd(&self) -> RawFd {
+        self.event_fd
+    }
+
+    /// Sleep until SIGTERM or SIGINT shows up, `also` turns readable, or `timeout` runs out.
+    pub fn wait(&self, also: Option<RawFd>, timeout: Option<Duration>) -> io::Result<Wakeup> {
+        let mut fds: [pollfd; 2] = [
+            pollfd {
+                fd: self.signal_fd,
+                events: POLLIN,
+                revents: 0,
+            },
+            pollfd {
+                fd: also.unwrap_or(-1), // negative fds are ignored by poll
+                events: POLLIN,
+                revents: 0,
+            },
+        ];
+        let timeout_ms: i32 = timeout.map_or(-1, |timeout| timeout.as_millis().min(i32::MAX as u128) as i32);
+        loop {
+            let ready: i32 = unsafe { poll(fds.as_mut_ptr(), fds.len() as _, timeout_ms) };
+            if ready < 0 {
+                let err: io::Error = io::Error::last_os_error();
+                if err.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(err);
+            }
+            if ready == 0 {
+                return Ok(Wakeup::Timeout);
+            }
+            if fds[0].revents & POLLIN != 0 {
+                return self.read_signal().map(Wakeup::Signal);
+            }
+            if fds[1].revents != 0 {
+                return Ok(Wakeup::Fd);
+            }
+        }
+    }
+
+    fn read_signal(&self) -> io::Result<i32> {
+        let mut info: signalfd_siginfo = unsafe { mem::zeroed() };
+        let size: usize = mem::size_of::<signalfd_siginfo>();
+        let n: isize = unsafe { read(self.signal_fd, &mut info as *mut _ as *mut c_void, size) };
+        if n != size as isize {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(info.ssi_signo as i32)
+    }
+
+    /// Wake every worker.
+    pub fn trigger(&self) -> io::Result<()> {
+        let one: u64 = 1;
+        let n: isize = unsafe { write(self.event_fd, &one as *const u64 as *const c_void, 8) };
+        if n != 8 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+impl Drop for ShutdownSignal {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.signal_fd);
+            libc::close(self.event_fd);
+            pthread_sigmask(SIG_SETMASK, &self.old_mask, std::ptr::null_mut());
+        }
+    }
+}
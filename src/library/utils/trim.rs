@@ -1,8 +1,94 @@
+use crate::library::utils::cpu::{simd_level, SimdLevel};
+#[cfg(target_arch = "x86_64")]
 use std::arch::x86_64::{
-    __m256i, _mm256_cmpeq_epi8, _mm256_loadu_si256, _mm256_movemask_epi8, _mm256_set1_epi8,
-    _mm256_setzero_si256,
+    __m128i, __m256i, _mm256_cmpeq_epi8, _mm256_loadu_si256, _mm256_movemask_epi8,
+    _mm256_set1_epi8, _mm256_setzero_si256, _mm_cmpeq_epi8, _mm_loadu_si128, _mm_movemask_epi8,
+    _mm_setzero_si128,
 };
 
+/// Left trim of zero bytes, in the widest flavour this CPU can stomach.
+#[inline(always)]
+pub fn l_trim(data: &[u8]) -> &[u8] {
+    unsafe {
+        match simd_level() {
+            #[cfg(target_arch = "x86_64")]
+            SimdLevel::Avx2 => l_trim256(data),
+            #[cfg(target_arch = "x86_64")]
+            SimdLevel::Sse2 => l_trim128(data),
+            _ => l_trim_scalar(data),
+        }
+    }
+}
+
+/// Right trim of zero bytes. Same deal.
+#[inline(always)]
+pub fn r_trim(buf: &[u8]) -> &[u8] {
+    unsafe {
+        match simd_level() {
+            #[cfg(target_arch = "x86_64")]
+            SimdLevel::Avx2 => r_trim256(buf),
+            #[cfg(target_arch = "x86_64")]
+            SimdLevel::Sse2 => r_trim128(buf),
+            _ => r_trim_scalar(buf),
+        }
+    }
+}
+
+#[inline(always)]
+pub fn l_trim_scalar(data: &[u8]) -> &[u8] {
+    // No vectors, no tricks. Just walking until something isn't zero.
+    match data.iter().position(|b| *b != 0) {
+        Some(i) => &data[i..],
+        None => &[],
+    }
+}
+
+#[inline(always)]
+pub fn r_trim_scalar(buf: &[u8]) -> &[u8] {
+    match buf.iter().rposition(|b| *b != 0) {
+        Some(i) => &buf[..i + 1],
+        None => &[],
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse2")]
+pub unsafe fn l_trim128(data: &[u8]) -> &[u8] {
+    // Half the width of the AVX2 scalpel. Still sharper than a loop.
+    let len: usize = data.len();
+    let ptr: *const u8 = data.as_ptr();
+    let mut i: usize = 0;
+    while i + 16 <= len {
+        let chunk: __m128i = _mm_loadu_si128(ptr.add(i) as *const __m128i);
+        let mask: u32 = _mm_movemask_epi8(_mm_cmpeq_epi8(chunk, _mm_setzero_si128())) as u32;
+        if mask != 0xFFFF {
+            return &data[i + mask.trailing_ones() as usize..];
+        }
+        i += 16;
+    }
+    l_trim_scalar(&data[i..])
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse2")]
+pub unsafe fn r_trim128(buf: &[u8]) -> &[u8] {
+    let ptr: *const u8 = buf.as_ptr();
+    let mut i: usize = buf.len();
+    while i >= 16 {
+        let offset: usize = i - 16;
+        let chunk: __m128i = _mm_loadu_si128(ptr.add(offset) as *const __m128i);
+        let mask: u32 = _mm_movemask_epi8(_mm_cmpeq_epi8(chunk, _mm_setzero_si128())) as u32;
+        if mask != 0xFFFF {
+            // Highest non-zero byte in this chunk is where the data ends.
+            let last: usize = 31 - (!mask & 0xFFFF).leading_zeros() as usize;
+            return &buf[..offset + last + 1];
+        }
+        i -= 16;
+    }
+    r_trim_scalar(&buf[..i])
+}
+
+#[cfg(target_arch = "x86_64")]
 #[target_feature(enable = "avx2")]
 pub unsafe fn l_trim256(data: &[u8]) -> &[u8] {
     // AVX2-enhanced left trim.
@@ -34,6 +120,7 @@ pub unsafe fn l_trim256(data: &[u8]) -> &[u8] {
     &[]
 }
 
+#[cfg(target_arch = "x86_64")]
 #[target_feature(enable = "avx2")]
 pub unsafe fn r_trim256(buf: &[u8]) -> &[u8] {
     // AVX2-empowered right trim.
@@ -0,0 +1,140 @@
+// The chunked decoder, fed the way the kernel feeds it: in whatever pieces it likes.
+
+use tachyon::library::utils::chunked::{ChunkedDecoder, ChunkedStatus};
+
+const LIMIT: usize = 1024 * 1024;
+
+// Feed `input` `step` bytes at a time. Returns the final status and how many bytes of the
+// whole input were eaten, if it got that far.
+fn feed_in_steps(
+    decoder: &mut ChunkedDecoder,
+    input: &[u8],
+    step: usize,
+) -> (ChunkedStatus, usize) {
+    let mut offset: usize = 0;
+    for piece in input.chunks(step) {
+        match decoder.feed(piece) {
+            ChunkedStatus::NeedMore => offset += piece.len(),
+            ChunkedStatus::Done(used) => {
+                return (ChunkedStatus::Done(offset + used), offset + used);
+            }
+            other => return (other, offset),
+        }
+    }
+    (ChunkedStatus::NeedMore, offset)
+}
+
+#[test]
+fn any_split_decodes_the_same() {
+    let input: &[u8] = b"5\r\nhello\r\n7\r\n, world\r\n0\r\n\r\nGET / HTTP/1.1\r\n";
+    let end: usize = input.len() - b"GET / HTTP/1.1\r\n".len();
+    for step in [1, 2, 3, 7, input.len()] {
+        let mut decoder: ChunkedDecoder = ChunkedDecoder::new(LIMIT);
+        let (status, used): (ChunkedStatus, usize) = feed_in_steps(&mut decoder, input, step);
+        assert_eq!(status, ChunkedStatus::Done(end), "step {step}");
+        assert_eq!(used, end);
+        assert_eq!(decoder.body(), b"hello, world");
+        assert_eq!(decoder.trailers(), b"\r\n");
+    }
+}
+
+#[test]
+fn size_line_split_across_recvs() {
+    let mut decoder: ChunkedDecoder = ChunkedDecoder::new(LIMIT);
+    assert_eq!(decoder.feed(b"1"), ChunkedStatus::NeedMore);
+    assert_eq!(decoder.feed(b"A\r"), ChunkedStatus::NeedMore);
+    assert_eq!(decoder.feed(b"\n0123456789"), ChunkedStatus::NeedMore);
+    assert_eq!(
+        decoder.feed(b"abcdefghijklmnop\r\n0\r"),
+        ChunkedStatus::NeedMore
+    );
+    assert_eq!(decoder.feed(b"\n\r\n"), ChunkedStatus::Done(3));
+    assert_eq!(decoder.body(), b"0123456789abcdefghijklmnop");
+}
+
+#[test]
+fn extensions_are_skipped() {
+    let mut decoder: ChunkedDecoder = ChunkedDecoder::new(LIMIT);
+    let input: &[u8] = b"3;name=value;x\r\nabc\r\n2 ; y=\"z\"\r\nde\r\n0;last\r\n\r\n";
+    assert_eq!(decoder.feed(input), ChunkedStatus::Done(input.len()));
+    assert_eq!(decoder.body(), b"abcde");
+}
+
+#[test]
+fn trailers_are_kept() {
+    let mut decoder: ChunkedDecoder = ChunkedDecoder::new(LIMIT);
+    let input: &[u8] = b"2\r\nok\r\n0\r\nChecksum: abc\r\nX-Done: 1\r\n\r\n";
+    let (status, _): (ChunkedStatus, usize) = feed_in_steps(&mut decoder, input, 1);
+    assert_eq!(status, ChunkedStatus::Done(input.len()));
+    assert_eq!(decoder.body(), b"ok");
+    assert_eq!(decoder.trailers(), b"Checksum: abc\r\nX-Done: 1\r\n\r\n");
+}
+
+#[test]
+fn endless_trailers_are_too_large() {
+    let mut decoder: ChunkedDecoder = ChunkedDecoder::new(LIMIT);
+    assert_eq!(decoder.feed(b"0\r\n"), ChunkedStatus::NeedMore);
+    let line: &[u8] = b"X-Filler: aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa\r\n";
+    let status: ChunkedStatus = (0..1000)
+        .map(|_| decoder.feed(line))
+        .find(|status| *status != ChunkedStatus::NeedMore)
+        .expect("trailers should hit the cap");
+    assert_eq!(status, ChunkedStatus::TooLarge);
+}
+
+#[test]
+fn size_digits_are_capped() {
+    let mut decoder: ChunkedDecoder = ChunkedDecoder::new(usize::MAX);
+    assert_eq!(decoder.feed(b"00000000000000f"), ChunkedStatus::NeedMore);
+    let mut decoder: ChunkedDecoder = ChunkedDecoder::new(usize::MAX);
+    assert_eq!(decoder.feed(b"0000000000000001"), ChunkedStatus::Invalid);
+}
+
+#[test]
+fn body_over_the_limit_is_too_large() {
+    // A chunk that claims too much is turned away before a single payload byte.
+    let mut decoder: ChunkedDecoder = ChunkedDecoder::new(10);
+    assert_eq!(decoder.feed(b"b\r\n"), ChunkedStatus::TooLarge);
+    // So is the chunk that tips a body over.
+    let mut decoder: ChunkedDecoder = ChunkedDecoder::new(10);
+    assert_eq!(decoder.feed(b"6\r\nabcdef\r\n"), ChunkedStatus::NeedMore);
+    assert_eq!(decoder.feed(b"5\r\n"), ChunkedStatus::TooLarge);
+    // Right at the limit is fine.
+    let mut decoder: ChunkedDecoder = ChunkedDecoder::new(10);
+    assert_eq!(
+        decoder.feed(b"a\r\n0123456789\r\n0\r\n\r\n"),
+        ChunkedStatus::Done(20)
+    );
+}
+
+#[test]
+fn garbage_is_invalid() {
+    for input in [
+        &b"x\r\n"[..],
+        b"\r\n",
+        b";ext\r\n",
+        b"5\rX",
+        b"3\r\nabcX",
+        b"3\r\nabc\rX",
+        b"-1\r\n",
+        // Whitespace after the size is for extensions only.
+        b"1 0\r\na\r\n",
+        b"1\t0\r\n",
+        b"1 \r\n",
+        b"1 x;y\r\n",
+        // Bare LF ends nothing.
+        b"5\nhello\r\n",
+        b"1;ext\na\r\n",
+        b"3\r\nabc\n0\r\n\r\n",
+        b"0\r\n\n",
+        b"0\r\nX-Trailer: 1\n\r\n",
+    ] {
+        let mut decoder: ChunkedDecoder = ChunkedDecoder::new(LIMIT);
+        assert_eq!(
+            decoder.feed(input),
+            ChunkedStatus::Invalid,
+            "{}",
+            String::from_utf8_lossy(input)
+        );
+    }
+}
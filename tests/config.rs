@@ -0,0 +1,157 @@
+// Config from all three places at once: a TOML file, the environment and the command line.
+// The environment is process-wide, so the tests take turns.
+
+use std::{
+    env, fs, io,
+    path::PathBuf,
+    sync::{Mutex, MutexGuard},
+};
+use tachyon::Server;
+use tachyon::library::config::Config;
+
+static ENV: Mutex<()> = Mutex::new(());
+
+// Clean slate: nothing `TACHYON_` left over from whoever ran before.
+fn env_lock() -> MutexGuard<'static, ()> {
+    let guard: MutexGuard<()> = ENV.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    for (name, _) in env::vars() {
+        if name.starts_with("TACHYON_") {
+            unsafe { env::remove_var(name) };
+        }
+    }
+    guard
+}
+
+fn config_file(name: &str, text: &str) -> PathBuf {
+    let path: PathBuf =
+        env::temp_dir().join(format!("tachyon-{}-{}.toml", name, std::process::id()));
+    fs::write(&path, text).unwrap();
+    path
+}
+
+fn load(args: &[&str]) -> io::Result<Config> {
+    Config::load(args.iter().map(|arg| arg.to_string()))
+}
+
+// `args` applied to a fresh server.
+fn configured(args: &[&str]) -> io::Result<Server> {
+    let mut server: Server = Server::new("127.0.0.1:0");
+    load(args)?.apply(&mut server)?;
+    Ok(server)
+}
+
+fn error(result: io::Result<impl Sized>) -> String {
+    match result {
+        Ok(_) => panic!("should have failed"),
+        Err(err) => {
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput, "{err}");
+            err.to_string()
+        }
+    }
+}
+
+#[test]
+fn command_line_beats_environment_beats_file() {
+    let _env = env_lock();
+    let path: PathBuf = config_file(
+        "precedence",
+        "sqpoll_idle = 100\nmax_body_size = 1000\nmax_header_size = 2000\n",
+    );
+    unsafe {
+        env::set_var("TACHYON_CONFIG", &path);
+        env::set_var("TACHYON_SQPOLL_IDLE", "200");
+        env::set_var("TACHYON_MAX_BODY_SIZE", "3000");
+    }
+    let server: Server = configured(&["--max-body-size", "4000"]).unwrap();
+    assert_eq!(server.get_max_header_size(), 2000);
+    assert_eq!(server.get_sqpoll_idle(), 200);
+    assert_eq!(server.get_max_body_size(), 4000);
+    // `--config` picks the file over TACHYON_CONFIG.
+    let other: PathBuf = config_file("precedence-other", "max_header_size = 5000\n");
+    let server: Server = configured(&["--config", other.to_str().unwrap()]).unwrap();
+    assert_eq!(server.get_max_header_size(), 5000);
+    unsafe { env::remove_var("TACHYON_CONFIG") };
+    let _ = fs::remove_file(path);
+    let _ = fs::remove_file(other);
+}
+
+#[test]
+fn repeated_lists_add_up() {
+    let _env = env_lock();
+    let path: PathBuf = config_file("lists", "listen = [\"10.0.0.1:80\"]\n");
+    let args: Vec<&str> = vec![
+        "--config",
+        path.to_str().unwrap(),
+        "--listen",
+        "127.0.0.1:8080",
+        "--listen=[::1]:8080,127.0.0.1:8081",
+    ];
+    let server: Server = configured(&args).unwrap();
+    // The command line adds up with itself, and replaces the file.
+    assert_eq!(
+        server.get_addrs(),
+        ["127.0.0.1:8080", "[::1]:8080", "127.0.0.1:8081"]
+    );
+    let _ = fs::remove_file(path);
+}
+
+#[test]
+fn bare_bools_peek_at_the_next_argument() {
+    let _env = env_lock();
+    let server: Server = configured(&["--sqpoll", "--max-body-size", "7"]).unwrap();
+    assert!(server.get_sqpoll_enabled());
+    assert_eq!(server.get_max_body_size(), 7);
+    let server: Server = configured(&["--sqpoll", "false", "--max-body-size", "8"]).unwrap();
+    assert!(!server.get_sqpoll_enabled());
+    assert_eq!(server.get_max_body_size(), 8);
+    let server: Server = configured(&["--sqpoll=false"]).unwrap();
+    assert!(!server.get_sqpoll_enabled());
+    // Anything but true/false after it is the next flag's business, not the bool's.
+    assert!(error(load(&["--sqpoll", "maybe"])).contains("unexpected argument \"maybe\""));
+}
+
+#[test]
+fn integers_take_octal_hex_and_underscores() {
+    let _env = env_lock();
+    let server: Server = configured(&[
+        "--unix-mode",
+        "0o660",
+        "--max-body-size",
+        "0x1000",
+        "--max-header-size",
+        "16_384",
+    ])
+    .unwrap();
+    assert_eq!(server.get_unix_mode(), Some(0o660));
+    assert_eq!(server.get_max_body_size(), 4096);
+    assert_eq!(server.get_max_header_size(), 16384);
+    unsafe { env::set_var("TACHYON_UNIX_MODE", "0o600") };
+    assert_eq!(configured(&[]).unwrap().get_unix_mode(), Some(0o600));
+    unsafe { env::set_var("TACHYON_UNIX_MODE", "rw-rw----") };
+    assert!(error(load(&[])).contains("TACHYON_UNIX_MODE = \"rw-rw----\": expected an integer"));
+    unsafe { env::remove_var("TACHYON_UNIX_MODE") };
+    assert!(error(configured(&["--workers", "300"])).contains("--workers = 300 is out of range"));
+}
+
+#[test]
+fn unknown_and_mistyped_settings_are_errors() {
+    let _env = env_lock();
+    assert!(error(load(&["--no-such-thing", "1"])).contains("unknown option --no-such-thing"));
+    assert!(error(load(&["--max-body-size"])).contains("--max-body-size needs a value"));
+    let path: PathBuf = config_file("unknown", "worker = 4\n");
+    let args: Vec<&str> = vec!["--config", path.to_str().unwrap()];
+    assert!(error(load(&args)).contains("unknown setting \"worker\""));
+    fs::write(&path, "workers = \"4\"\n").unwrap();
+    assert!(error(load(&args)).contains("workers: expected integer, got string"));
+    let _ = fs::remove_file(path);
+}
+
+#[test]
+fn realtime_and_ubdma_do_not_mix() {
+    let _env = env_lock();
+    let message: String = error(configured(&["--realtime", "--ubdma"]));
+    assert!(
+        message.contains("realtime and ub_kernel_dma are mutually exclusive"),
+        "{message}"
+    );
+}
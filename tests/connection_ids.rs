@@ -0,0 +1,83 @@
+// Connection ids are a worker's seat numbers. They have to be given back, or a busy worker runs
+// out of seats long before it runs out of anything else.
+
+use std::{
+    env, fs,
+    io::{Read, Write},
+    os::unix::{net::UnixStream, thread::JoinHandleExt},
+    path::PathBuf,
+    thread,
+    time::{Duration, Instant},
+};
+use tachyon::{Server, run};
+
+// One past what a u16 of ids could ever hold.
+const CONNECTIONS: usize = u16::MAX as usize + 1000;
+const CLIENTS: usize = 4;
+
+// Connect, ask, hear the whole answer, hang up. True if it was a 404 — nothing is routed.
+fn round_trip(path: &PathBuf) -> bool {
+    let mut stream: UnixStream = UnixStream::connect(path).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(10)))
+        .unwrap();
+    stream
+        .write_all(b"GET /seat HTTP/1.1\r\nHost: x\r\n\r\n")
+        .unwrap();
+    let mut head: Vec<u8> = Vec::new();
+    let mut buf: [u8; 1024] = [0u8; 1024];
+    while !head.ends_with(b"Not, found!") {
+        match stream.read(&mut buf) {
+            Ok(0) | Err(_) => return false,
+            Ok(len) => head.extend_from_slice(&buf[..len]),
+        }
+    }
+    head.starts_with(b"HTTP/1.1 404")
+}
+
+#[test]
+fn more_connections_than_ids() {
+    let dir: PathBuf = env::temp_dir().join(format!("tachyon-ids-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let path: PathBuf = dir.join("http.sock");
+    let leaked: &'static str = Box::leak(path.to_str().unwrap().to_string().into_boxed_str());
+
+    let server: Server = Server::new("127.0.0.1:0")
+        .set_addrs(Vec::new())
+        .listen_unix(leaked)
+        .set_workers(1)
+        .set_drain_timeout(Duration::from_secs(1))
+        .build();
+    let worker: thread::JoinHandle<std::io::Result<()>> = thread::spawn(move || run(server));
+    let started: Instant = Instant::now();
+    while UnixStream::connect(&path).is_err() {
+        assert!(
+            started.elapsed() < Duration::from_secs(10),
+            "server never came up"
+        );
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    let clients: Vec<thread::JoinHandle<usize>> = (0..CLIENTS)
+        .map(|_| {
+            let path: PathBuf = path.clone();
+            thread::spawn(move || {
+                (0..CONNECTIONS / CLIENTS)
+                    .filter(|_| round_trip(&path))
+                    .count()
+            })
+        })
+        .collect();
+    let served: usize = clients
+        .into_iter()
+        .map(|client| client.join().unwrap())
+        .sum();
+    assert_eq!(served, CONNECTIONS / CLIENTS * CLIENTS);
+    // Still taking new ones after all that.
+    assert!(round_trip(&path));
+
+    unsafe { libc::pthread_kill(worker.as_pthread_t(), libc::SIGTERM) };
+    worker.join().unwrap().unwrap();
+    let _ = fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,126 @@
+// The latency histogram, from the outside: a lone sample comes back as the top of its bucket,
+// so what `quantile(1.0)` says about one value is what the bucket math says about it.
+
+use tachyon::library::utils::histogram::{AtomicHistogram, Histogram};
+
+// Where `value` comes back from, on its own.
+fn recorded(histogram: &mut Histogram, value: u64) -> u64 {
+    histogram.clear();
+    histogram.record(value, 1);
+    histogram.quantile(1.0)
+}
+
+// Never below the value, never more than 1/64 of it above. Exact below 64.
+fn assert_close(histogram: &mut Histogram, value: u64) {
+    let top: u64 = recorded(histogram, value);
+    assert!(top >= value, "{value} came back as {top}");
+    assert!(top - value <= value / 64, "{value} came back as {top}");
+}
+
+#[test]
+fn every_value_lands_within_a_64th() {
+    let mut histogram: Histogram = Histogram::default();
+    for value in 0..100_000 {
+        assert_close(&mut histogram, value);
+    }
+    for value in 0..64 {
+        assert_eq!(recorded(&mut histogram, value), value);
+    }
+    // Both edges of every power of two, all the way up.
+    for shift in 6..64 {
+        let power: u64 = 1 << shift;
+        for value in [
+            power - 1,
+            power,
+            power + 1,
+            power | (power >> 1),
+            power | (power - 1),
+        ] {
+            assert_close(&mut histogram, value);
+        }
+    }
+    assert_eq!(recorded(&mut histogram, u64::MAX), u64::MAX);
+    // And a spray of everything in between.
+    let mut x: u64 = 0x9E37_79B9_7F4A_7C15;
+    for _ in 0..100_000 {
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        assert_close(&mut histogram, x >> (x % 64));
+    }
+}
+
+#[test]
+fn bucket_tops_only_go_up() {
+    let mut histogram: Histogram = Histogram::default();
+    let mut last: u64 = 0;
+    for value in 0..200_000 {
+        let top: u64 = recorded(&mut histogram, value);
+        assert!(top >= last, "{value} came back as {top}, below {last}");
+        last = top;
+    }
+}
+
+#[test]
+fn known_quantiles() {
+    let mut histogram: Histogram = Histogram::default();
+    assert_eq!(histogram.quantile(0.5), 0);
+    for value in 1..=1000 {
+        histogram.record(value, 1);
+    }
+    assert_eq!(histogram.count(), 1000);
+    assert_eq!(histogram.sum(), 500_500);
+    assert_eq!(histogram.quantile(0.0), 1);
+    // 500 shares a 4-wide bucket that ends at 503. 990 and 999 sit in 8-wide ones ending at
+    // 991 and 999, and 1000 starts the next one, up to 1007.
+    assert_eq!(histogram.quantile(0.5), 503);
+    assert_eq!(histogram.quantile(0.99), 991);
+    assert_eq!(histogram.quantile(0.999), 999);
+    assert_eq!(histogram.quantile(1.0), 1007);
+
+    // A fast crowd with a slow tail, in batches.
+    histogram.clear();
+    histogram.record(10, 990);
+    histogram.record(1000, 9);
+    histogram.record(100_000, 1);
+    assert_eq!(histogram.count(), 1000);
+    assert_eq!(histogram.sum(), 9900 + 9000 + 100_000);
+    assert_eq!(histogram.quantile(0.5), 10);
+    assert_eq!(histogram.quantile(0.99), 10);
+    assert_eq!(histogram.quantile(0.999), 1007);
+    assert_eq!(histogram.quantile(1.0), 100_351);
+}
+
+#[test]
+fn atomic_round_trip() {
+    let mut local: Histogram = Histogram::default();
+    for value in 1..=1000 {
+        local.record(value * value, value % 3 + 1);
+    }
+    let shared: AtomicHistogram = AtomicHistogram::default();
+    shared.add(&local);
+    shared.add(&Histogram::default());
+
+    let mut snapshot: Histogram = Histogram::default();
+    shared.merge_into(&mut snapshot);
+    assert_eq!(snapshot.count(), local.count());
+    assert_eq!(snapshot.sum(), local.sum());
+    for q in [0.0, 0.1, 0.5, 0.9, 0.99, 0.999, 1.0] {
+        assert_eq!(snapshot.quantile(q), local.quantile(q), "quantile {q}");
+    }
+
+    // Twice in, twice the counts. Same shape, so the same quantiles.
+    shared.add(&local);
+    let mut doubled: Histogram = Histogram::default();
+    shared.merge_into(&mut doubled);
+    assert_eq!(doubled.count(), 2 * local.count());
+    assert_eq!(doubled.sum(), 2 * local.sum());
+    for q in [0.0, 0.1, 0.5, 0.9, 0.99, 0.999, 1.0] {
+        assert_eq!(doubled.quantile(q), local.quantile(q), "quantile {q}");
+    }
+
+    // Merging adds to what's already there.
+    shared.merge_into(&mut snapshot);
+    assert_eq!(snapshot.count(), 3 * local.count());
+    assert_eq!(snapshot.sum(), 3 * local.sum());
+}
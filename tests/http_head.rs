@@ -0,0 +1,41 @@
+// Request heads that must never be mistaken for something friendlier than they are.
+
+use tachyon::library::utils::http::{BodyFraming, HeadParse, MAX_HEADERS, parse_request_head};
+
+#[test]
+fn whitespace_in_field_name_is_invalid() {
+    for head in [
+        &b"POST / HTTP/1.1\r\nContent-Length : 5\r\n\r\nGET /"[..],
+        b"POST / HTTP/1.1\r\nContent-Length\t: 5\r\n\r\nGET /",
+        b"GET / HTTP/1.1\r\nHost: a\r\n folded: b\r\n\r\n",
+    ] {
+        assert!(
+            matches!(parse_request_head(head), HeadParse::Invalid),
+            "{}",
+            String::from_utf8_lossy(head)
+        );
+    }
+    // Whitespace after the colon is just optional whitespace.
+    match parse_request_head(b"POST / HTTP/1.1\r\nContent-Length:   5 \r\n\r\n") {
+        HeadParse::Complete(head) => {
+            assert!(matches!(
+                head.headers.body_framing(),
+                BodyFraming::Length(5)
+            ))
+        }
+        other => panic!("{other:?}"),
+    }
+}
+
+#[test]
+fn too_many_headers_is_its_own_thing() {
+    let mut head: Vec<u8> = b"GET / HTTP/1.1\r\n".to_vec();
+    for i in 0..MAX_HEADERS {
+        head.extend_from_slice(format!("X-{i}: {i}\r\n").as_bytes());
+    }
+    let mut full: Vec<u8> = head.clone();
+    full.extend_from_slice(b"\r\n");
+    assert!(matches!(parse_request_head(&full), HeadParse::Complete(_)));
+    head.extend_from_slice(b"X-One-Too-Many: 1\r\n\r\n");
+    assert!(matches!(parse_request_head(&head), HeadParse::TooMany));
+}
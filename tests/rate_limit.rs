@@ -0,0 +1,105 @@
+// Token buckets on a clock we get to turn by hand. `now` is nanoseconds, like `nano_clock`.
+
+use std::net::{IpAddr, Ipv4Addr};
+use tachyon::library::rate_limit::{RateLimit, RateTable, Subject};
+
+const MS: i64 = 1_000_000;
+const SECOND: i64 = 1_000 * MS;
+
+#[test]
+fn burst_runs_out_and_refills_at_the_rate() {
+    let mut table: RateTable = RateTable::new(7);
+    let limit: RateLimit = RateLimit::new(10, 5);
+    let client: Subject = Subject::Connection(1);
+    for _ in 0..5 {
+        assert_eq!(table.take(client, 0, limit, 0), Ok(()));
+    }
+    assert!(table.take(client, 0, limit, 0).is_err());
+    // 10 a second is one every 100 ms. Not a moment before.
+    assert!(table.take(client, 0, limit, 99 * MS).is_err());
+    assert_eq!(table.take(client, 0, limit, 100 * MS), Ok(()));
+    assert!(table.take(client, 0, limit, 100 * MS).is_err());
+    // A long nap fills it up to the burst, and no further.
+    let later: i64 = 100 * MS + 60 * SECOND;
+    for _ in 0..5 {
+        assert_eq!(table.take(client, 0, limit, later), Ok(()));
+    }
+    assert!(table.take(client, 0, limit, later).is_err());
+}
+
+#[test]
+fn no_burst_means_one_seconds_worth() {
+    let mut table: RateTable = RateTable::new(7);
+    let limit: RateLimit = RateLimit::new(3, 0);
+    let client: Subject = Subject::Connection(1);
+    for _ in 0..3 {
+        assert_eq!(table.take(client, 0, limit, 0), Ok(()));
+    }
+    assert!(table.take(client, 0, limit, 0).is_err());
+}
+
+#[test]
+fn retry_after_is_whole_seconds_and_never_zero() {
+    let mut table: RateTable = RateTable::new(7);
+    let limit: RateLimit = RateLimit::new(1, 1);
+    let client: Subject = Subject::Connection(1);
+    assert_eq!(table.take(client, 0, limit, 0), Ok(()));
+    assert_eq!(table.take(client, 0, limit, 0), Err(1));
+    // Almost there still rounds up.
+    assert_eq!(table.take(client, 0, limit, 500 * MS), Err(1));
+    assert_eq!(table.take(client, 0, limit, 999 * MS), Err(1));
+    assert_eq!(table.take(client, 0, limit, SECOND), Ok(()));
+    // A fast rate is back in well under a second. The header can't say less than one.
+    let limit: RateLimit = RateLimit::new(1000, 1);
+    let other: Subject = Subject::Connection(2);
+    assert_eq!(table.take(other, 0, limit, 0), Ok(()));
+    assert_eq!(table.take(other, 0, limit, 0), Err(1));
+}
+
+#[test]
+fn fifth_in_a_set_evicts_the_stalest() {
+    let mut table: RateTable = RateTable::new(7);
+    let limit: RateLimit = RateLimit::new(1, 1);
+    // Five serials that all land in the same four ways.
+    let set: usize = table.set_of(Subject::Connection(0), 0);
+    let crowd: Vec<Subject> = (0..)
+        .map(Subject::Connection)
+        .filter(|subject| table.set_of(*subject, 0) == set)
+        .take(5)
+        .collect();
+    let [first, second, third, fourth, fifth] = crowd[..] else {
+        unreachable!()
+    };
+    // Everyone drains their bucket, `first` longest ago.
+    assert_eq!(table.take(first, 0, limit, 0), Ok(()));
+    assert!(table.take(first, 0, limit, MS).is_err());
+    assert_eq!(table.take(second, 0, limit, 2 * MS), Ok(()));
+    assert_eq!(table.take(third, 0, limit, 3 * MS), Ok(()));
+    assert_eq!(table.take(fourth, 0, limit, 4 * MS), Ok(()));
+    // No room for the fifth: `first` is forgotten, and comes back to a full bucket.
+    assert_eq!(table.take(fifth, 0, limit, 5 * MS), Ok(()));
+    assert_eq!(table.take(first, 0, limit, 6 * MS), Ok(()));
+    // That pushed out `second`, the stalest by then. The rest still remember.
+    assert!(table.take(fourth, 0, limit, 7 * MS).is_err());
+    assert!(table.take(fifth, 0, limit, 8 * MS).is_err());
+    assert_eq!(table.take(second, 0, limit, 9 * MS), Ok(()));
+}
+
+#[test]
+fn classes_and_subjects_keep_separate_buckets() {
+    let mut table: RateTable = RateTable::new(7);
+    let limit: RateLimit = RateLimit::new(1, 1);
+    let client: Subject = Subject::Connection(1);
+    assert_eq!(table.take(client, 0, limit, 0), Ok(()));
+    assert!(table.take(client, 0, limit, 0).is_err());
+    // Same client, other route class: untouched.
+    assert_eq!(table.take(client, 1, limit, 0), Ok(()));
+    assert!(table.take(client, 1, limit, 0).is_err());
+    assert_eq!(table.take(client, 2, limit, 0), Ok(()));
+    // Serial 0 in class 1 is not serial 1 in class 0.
+    assert_eq!(table.take(Subject::Connection(0), 1, limit, 0), Ok(()));
+    // An address that happens to be the number 1 is nobody's connection either.
+    let ip: Subject = Subject::Ip(IpAddr::V4(Ipv4Addr::from(1)));
+    assert_eq!(table.take(ip, 0, limit, 0), Ok(()));
+    assert!(table.take(ip, 0, limit, 0).is_err());
+}
@@ -0,0 +1,167 @@
+// The route trie, poked from the outside: who wins, who backs off, and what never compiles.
+
+use std::io;
+use tachyon::library::rate_limit::{Limits, RateLimit};
+use tachyon::library::router::{Route, RouteMatch, Router};
+use tachyon::{Request, Response};
+
+fn noop(_: &Request, _: &mut Response) {}
+
+fn route(method: &'static str, path: &'static str) -> Route {
+    Route {
+        method,
+        path,
+        handler: noop,
+        limits: None,
+    }
+}
+
+fn compile(routes: &[(&'static str, &'static str)]) -> io::Result<Router> {
+    let routes: Vec<Route> = routes.iter().map(|(m, p)| route(m, p)).collect();
+    Router::compile(&routes)
+}
+
+// Route id (table position + 1) and params of whatever `path` resolves to. Panics otherwise.
+fn found<'a>(router: &'a Router, method: &str, path: &'a str) -> (u16, Vec<(String, String)>) {
+    match router.lookup(method.as_bytes(), path.as_bytes()) {
+        RouteMatch::Found(_, params, id, _) => (
+            id,
+            params
+                .iter()
+                .map(|(name, value)| {
+                    (
+                        String::from_utf8_lossy(name).into_owned(),
+                        String::from_utf8_lossy(value).into_owned(),
+                    )
+                })
+                .collect(),
+        ),
+        RouteMatch::MethodNotAllowed(_) => panic!("{method} {path}: 405"),
+        RouteMatch::NotFound => panic!("{method} {path}: 404"),
+    }
+}
+
+fn param(name: &str, value: &str) -> (String, String) {
+    (name.to_string(), value.to_string())
+}
+
+#[test]
+fn static_beats_param_beats_wildcard() {
+    let router: Router = compile(&[
+        ("GET", "/users/*rest"),
+        ("GET", "/users/:id"),
+        ("GET", "/users/new"),
+    ])
+    .unwrap();
+    assert_eq!(found(&router, "GET", "/users/new"), (3, vec![]));
+    assert_eq!(
+        found(&router, "GET", "/users/42"),
+        (2, vec![param("id", "42")])
+    );
+    assert_eq!(
+        found(&router, "GET", "/users/42/posts"),
+        (1, vec![param("rest", "42/posts")])
+    );
+    // Query strings don't take part.
+    assert_eq!(found(&router, "GET", "/users/new?x=1"), (3, vec![]));
+}
+
+#[test]
+fn dead_end_param_branch_backtracks() {
+    let router: Router = compile(&[
+        ("GET", "/files/:id/meta"),
+        ("GET", "/files/*path"),
+        ("GET", "/a/b/c"),
+        ("GET", "/a/:x/d"),
+    ])
+    .unwrap();
+    // `:id` takes "7", finds no "raw" under it, and hands over to the wildcard — without
+    // leaving its param behind.
+    assert_eq!(
+        found(&router, "GET", "/files/7/raw"),
+        (2, vec![param("path", "7/raw")])
+    );
+    assert_eq!(
+        found(&router, "GET", "/files/7/meta"),
+        (1, vec![param("id", "7")])
+    );
+    // Static "b" is a dead end for "d". The param sibling picks it up.
+    assert_eq!(found(&router, "GET", "/a/b/d"), (4, vec![param("x", "b")]));
+}
+
+#[test]
+fn trailing_wildcard_matches_its_parent() {
+    let router: Router = compile(&[("GET", "/static/*")]).unwrap();
+    assert_eq!(found(&router, "GET", "/static"), (1, vec![param("*", "")]));
+    assert_eq!(
+        found(&router, "GET", "/static/css/site.css"),
+        (1, vec![param("*", "css/site.css")])
+    );
+    assert!(matches!(
+        router.lookup(b"GET", b"/stat"),
+        RouteMatch::NotFound
+    ));
+}
+
+#[test]
+fn not_found_vs_method_not_allowed() {
+    let router: Router = compile(&[("GET", "/items"), ("POST", "/items")]).unwrap();
+    match router.lookup(b"DELETE", b"/items") {
+        RouteMatch::MethodNotAllowed(allow) => assert_eq!(allow, b"Allow: GET, POST\r\n"),
+        _ => panic!("expected 405"),
+    }
+    assert!(matches!(
+        router.lookup(b"GET", b"/nothing"),
+        RouteMatch::NotFound
+    ));
+    // A node on the way to a route isn't a route.
+    let router: Router = compile(&[("GET", "/a/b")]).unwrap();
+    assert!(matches!(router.lookup(b"GET", b"/a"), RouteMatch::NotFound));
+}
+
+#[test]
+fn bad_tables_do_not_compile() {
+    let rejected = |routes: &[(&'static str, &'static str)], reason: &str| {
+        let err: io::Error = compile(routes).err().expect("should not compile");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(err.to_string().contains(reason), "{err}");
+    };
+    rejected(&[("GET", "/a"), ("GET", "/a")], "duplicate route");
+    rejected(
+        &[("GET", "/a/:id"), ("GET", "/a/:name/x")],
+        "conflicting param name",
+    );
+    rejected(
+        &[("GET", "/a/*x"), ("GET", "/a/*y")],
+        "conflicting wildcard name",
+    );
+    rejected(&[("GET", "/a/*x/b")], "wildcard must be the last segment");
+    rejected(&[("GET", "a")], "must start with '/'");
+    rejected(&[("GET", "/:a/:b/:c/:d/:e/:f/:g/:h/:i")], "too many params");
+    // Same path, another method — that's fine.
+    assert!(compile(&[("GET", "/a/:id"), ("PUT", "/a/:id")]).is_ok());
+}
+
+#[test]
+fn limits_come_with_the_match() {
+    let slow: Limits = Limits {
+        connection: RateLimit::new(1, 1),
+        ip: RateLimit::default(),
+    };
+    let routes: Vec<Route> = vec![
+        route("GET", "/fast"),
+        Route {
+            limits: Some(slow),
+            ..route("GET", "/slow/:id")
+        },
+    ];
+    let router: Router = Router::compile(&routes).unwrap();
+    assert!(router.has_limits());
+    let class = |path: &str| match router.lookup(b"GET", path.as_bytes()) {
+        RouteMatch::Found(_, _, _, class) => class,
+        _ => panic!("{path}: no route"),
+    };
+    assert_eq!(class("/fast"), 0);
+    assert_eq!(router.class_limits(0), None);
+    assert_eq!(router.class_limits(class("/slow/7")), Some(slow));
+}
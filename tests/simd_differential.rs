@@ -0,0 +1,172 @@
+// Every SIMD flavour has to give the exact same answer as the dumbest loop we could write.
+// Inputs are random, but drawn from an alphabet that actually hits the interesting paths:
+// spaces, colons, line ends, zeros and the first letters of GET/POST.
+
+use tachyon::library::utils::{http, memory, shift, trim};
+
+const ROUNDS: usize = 2000;
+const ALPHABET: &[u8] = b"GETPOS /:\r\n\0\0\0ax";
+
+struct XorShift(u64);
+
+impl XorShift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn bytes(&mut self, max_len: usize) -> Vec<u8> {
+        let len: usize = self.below(max_len + 1);
+        (0..len).map(|_| ALPHABET[self.below(ALPHABET.len())]).collect()
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+fn avx2() -> bool {
+    std::arch::is_x86_feature_detected!("avx2")
+}
+
+#[test]
+fn find_request_starts_variants_agree() {
+    let mut rng: XorShift = XorShift(0x9E3779B97F4A7C15);
+    for _ in 0..ROUNDS {
+        let buf: Vec<u8> = rng.bytes(300);
+        let expected = http::find_request_starts_scalar::<16>(&buf);
+        let naive: Vec<usize> = (0..buf.len().saturating_sub(2))
+            .filter(|i| &buf[*i..*i + 3] == b"GET" || &buf[*i..*i + 3] == b"POS")
+            .take(16)
+            .collect();
+        assert_eq!(&expected.0[..expected.1], &naive[..], "scalar on {buf:?}");
+        #[cfg(target_arch = "x86_64")]
+        {
+            assert_eq!(unsafe { http::find_request_starts_sse2::<16>(&buf) }, expected);
+            if avx2() {
+                assert_eq!(unsafe { http::find_request_starts_avx2::<16>(&buf) }, expected);
+            }
+        }
+        #[cfg(target_arch = "aarch64")]
+        assert_eq!(unsafe { http::find_request_starts_neon::<16>(&buf) }, expected);
+        assert_eq!(http::find_request_starts::<16>(&buf), expected);
+    }
+}
+
+#[test]
+fn parse_one_manual_variants_agree() {
+    let mut rng: XorShift = XorShift(0xD1B54A32D192ED03);
+    for round in 0..ROUNDS * 4 {
+        let mut window: [u8; 32] = [b'a'; 32];
+        // Every other round starts like a real request, so the GET/POST shortcuts get exercised.
+        let prefix: &[u8] = match round % 4 {
+            0 => b"GET /",
+            1 => b"POST /",
+            _ => b"",
+        };
+        let random: Vec<u8> = rng.bytes(32);
+        window[..random.len()].copy_from_slice(&random);
+        window[..prefix.len()].copy_from_slice(prefix);
+        let ptr: *const u8 = window.as_ptr();
+        let expected = unsafe { http::parse_one_manual_scalar(ptr) };
+        #[cfg(target_arch = "x86_64")]
+        {
+            assert_eq!(unsafe { http::parse_one_manual_sse2(ptr) }, expected, "{window:?}");
+            if avx2() {
+                assert_eq!(unsafe { http::parse_one_manual_avx2(ptr) }, expected, "{window:?}");
+            }
+        }
+        #[cfg(target_arch = "aarch64")]
+        assert_eq!(unsafe { http::parse_one_manual_neon(ptr) }, expected, "{window:?}");
+        assert_eq!(unsafe { http::parse_one_manual(ptr) }, expected);
+    }
+}
+
+#[test]
+fn scan_line_variants_agree() {
+    let mut rng: XorShift = XorShift(0xA0761D6478BD642F);
+    for _ in 0..ROUNDS {
+        let buf: Vec<u8> = rng.bytes(200);
+        let from: usize = rng.below(buf.len() + 1);
+        let expected = http::scan_line_scalar(&buf, from);
+        #[cfg(target_arch = "x86_64")]
+        {
+            assert_eq!(unsafe { http::scan_line_sse2(&buf, from) }, expected, "{buf:?} @ {from}");
+            if avx2() {
+                assert_eq!(unsafe { http::scan_line_avx2(&buf, from) }, expected, "{buf:?} @ {from}");
+            }
+        }
+        #[cfg(target_arch = "aarch64")]
+        assert_eq!(unsafe { http::scan_line_neon(&buf, from) }, expected, "{buf:?} @ {from}");
+        assert_eq!(http::scan_line(&buf, from), expected);
+    }
+}
+
+#[test]
+fn trim_variants_agree() {
+    let mut rng: XorShift = XorShift(0xE7037ED1A0B428DB);
+    for _ in 0..ROUNDS {
+        let mut buf: Vec<u8> = rng.bytes(150);
+        // Long runs of zeros on either side, or the vector loops never get a workout.
+        let pad: usize = rng.below(80);
+        buf.splice(0..0, std::iter::repeat_n(0u8, pad));
+        buf.extend(std::iter::repeat_n(0u8, rng.below(80)));
+        let left: &[u8] = trim::l_trim_scalar(&buf);
+        let right: &[u8] = trim::r_trim_scalar(&buf);
+        #[cfg(target_arch = "x86_64")]
+        {
+            assert_eq!(unsafe { trim::l_trim128(&buf) }, left);
+            assert_eq!(unsafe { trim::r_trim128(&buf) }, right);
+            if avx2() {
+                assert_eq!(unsafe { trim::l_trim256(&buf) }, left);
+                assert_eq!(unsafe { trim::r_trim256(&buf) }, right);
+            }
+        }
+        assert_eq!(trim::l_trim(&buf), left);
+        assert_eq!(trim::r_trim(&buf), right);
+    }
+}
+
+#[test]
+fn shift_and_zero_variants_agree() {
+    let mut rng: XorShift = XorShift(0x8EBC6AF09C88C6E3);
+    for _ in 0..200 {
+        // Shifts land in a buffer as long as the one they came from, whatever that is.
+        let buffer_size: usize = 64 + rng.below(4096);
+        let mut buf: Vec<u8> = (0..buffer_size).map(|_| rng.next() as u8).collect();
+        let len: usize = rng.below(buffer_size + 1);
+        let expected = unsafe { shift::shift_ub_scalar(&mut buf, len) };
+        assert_eq!(&expected[buffer_size - len..], &buf[..len]);
+        assert!(expected[..buffer_size - len].iter().all(|b| *b == 0));
+        #[cfg(target_arch = "x86_64")]
+        {
+            assert_eq!(unsafe { shift::shift_ub_sse2(&mut buf, len) }, expected);
+            if avx2() {
+                assert_eq!(unsafe { shift::shift_ub_avx2(&mut buf, len) }, expected);
+            }
+        }
+        assert_eq!(unsafe { shift::shift_ub(&mut buf, len) }, expected);
+
+        let start: usize = rng.below(64);
+        let zeroed: usize = rng.below(buf.len() - start);
+        let mut expected: Vec<u8> = buf.clone();
+        expected[start..start + zeroed].fill(0);
+        #[cfg(target_arch = "x86_64")]
+        {
+            let mut sse2: Vec<u8> = buf.clone();
+            unsafe { memory::sse2_zero(sse2.as_mut_ptr().add(start), zeroed) };
+            assert_eq!(sse2, expected);
+            if avx2() {
+                let mut avx: Vec<u8> = buf.clone();
+                unsafe { memory::avx2_zero(avx.as_mut_ptr().add(start), zeroed) };
+                assert_eq!(avx, expected);
+            }
+        }
+        let mut auto: Vec<u8> = buf.clone();
+        unsafe { memory::simd_zero(auto.as_mut_ptr().add(start), zeroed) };
+        assert_eq!(auto, expected);
+    }
+}
@@ -0,0 +1,80 @@
+// systemd, faked: a datagram socket in a temp dir plays NOTIFY_SOCKET, and the environment
+// plays socket activation. Both are process-wide, so the tests take turns.
+
+use std::{
+    env, fs,
+    os::unix::{net::UnixDatagram, thread::JoinHandleExt},
+    path::PathBuf,
+    sync::Mutex,
+    thread,
+    time::Duration,
+};
+use tachyon::library::network::systemd::listen_fds;
+use tachyon::{Server, run};
+
+static ENV: Mutex<()> = Mutex::new(());
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir: PathBuf = env::temp_dir().join(format!("tachyon-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+// Next datagram on `socket`, as text.
+fn next_state(socket: &UnixDatagram) -> String {
+    let mut buf: [u8; 256] = [0u8; 256];
+    let len: usize = socket.recv(&mut buf).expect("no notification in time");
+    String::from_utf8_lossy(&buf[..len]).into_owned()
+}
+
+#[test]
+fn notifies_ready_and_stopping() {
+    let _env = ENV.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let dir: PathBuf = temp_dir("notify");
+    let path: PathBuf = dir.join("notify.sock");
+    let socket: UnixDatagram = UnixDatagram::bind(&path).unwrap();
+    socket
+        .set_read_timeout(Some(Duration::from_secs(10)))
+        .unwrap();
+    unsafe { env::set_var("NOTIFY_SOCKET", &path) };
+
+    let server: Server = Server::new("127.0.0.1:0")
+        .set_workers(1)
+        .set_drain_timeout(Duration::from_secs(1))
+        .build();
+    let worker: thread::JoinHandle<std::io::Result<()>> = thread::spawn(move || run(server));
+
+    let ready: String = next_state(&socket);
+    assert!(ready.starts_with("READY=1\n"), "{ready:?}");
+    assert!(
+        ready.contains(&format!("MAINPID={}", std::process::id())),
+        "{ready:?}"
+    );
+    // `run` waits for its signals on a signalfd in its own thread, so that's where this one goes.
+    // Anywhere else in the process it would take the whole test binary down.
+    unsafe { libc::pthread_kill(worker.as_pthread_t(), libc::SIGTERM) };
+    assert_eq!(next_state(&socket), "STOPPING=1");
+    worker.join().unwrap().unwrap();
+
+    unsafe { env::remove_var("NOTIFY_SOCKET") };
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn listen_fds_for_someone_else_are_ignored() {
+    let _env = ENV.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    unsafe {
+        env::set_var("LISTEN_PID", (std::process::id() + 1).to_string());
+        env::set_var("LISTEN_FDS", "1");
+        env::set_var("LISTEN_FDNAMES", "http");
+    }
+    assert!(listen_fds().unwrap().is_empty());
+    // Not ours, but gone anyway — nothing we'd spawn should pick them up either.
+    assert!(env::var_os("LISTEN_PID").is_none());
+    assert!(env::var_os("LISTEN_FDS").is_none());
+    assert!(env::var_os("LISTEN_FDNAMES").is_none());
+    // No LISTEN_PID at all is the same story.
+    unsafe { env::set_var("LISTEN_FDS", "1") };
+    assert!(listen_fds().unwrap().is_empty());
+}